@@ -0,0 +1,164 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Policies deciding whether a new entry is worth evicting an existing one for.
+
+use crate::{policy, recency, LruCache};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Decides whether a new entry is admitted into a full cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AdmissionPolicy {
+    /// New entries are always admitted, evicting an existing one if needed.  This is the default.
+    #[default]
+    Always,
+    /// TinyLFU: the cache keeps a compact, ageing estimate of how often each key has been seen
+    /// recently, and a new entry is only admitted into a full cache if it has been seen more often
+    /// than the entry it would evict.  This keeps one-off keys from displacing popular ones.
+    TinyLfu,
+    /// W-TinyLFU: new entries are always admitted into a window made of the most recently
+    /// inserted or used entries, about 1% of the capacity.  When a new entry pushes one out of
+    /// the window, the latter competes with the entry the eviction policy would evict, and
+    /// whichever has been seen less often by the TinyLFU sketch is evicted.  Unlike `TinyLfu`, new
+    /// keys get a chance to build up their frequency before competing, which suits workloads with
+    /// bursts of recently popular keys.
+    WindowTinyLfu,
+}
+
+/// Share of the capacity the window of `AdmissionPolicy::WindowTinyLfu` takes, in percent.
+const WINDOW_PERCENT: u8 = 1;
+
+/// Number of counters per key in the sketch.
+const DEPTH: usize = 4;
+/// Counters saturate at this value.
+const MAX_COUNT: u8 = 15;
+const SEEDS: [u64; DEPTH] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xbf58_476d_1ce4_e5b9,
+    0x94d0_49bb_1331_11eb,
+    0xc2b2_ae3d_27d4_eb4f,
+];
+
+/// Count-min sketch estimating how often keys have been seen.  All counters are halved
+/// periodically, so that the estimates favour recent history.
+#[derive(Clone)]
+pub(crate) struct FrequencySketch<Key> {
+    counters: Vec<u8>,
+    /// Number of counters per row, minus one.
+    mask: usize,
+    additions: usize,
+    sample_size: usize,
+    hash: fn(&Key) -> u64,
+}
+
+impl<Key> FrequencySketch<Key> {
+    /// Creates a sketch sized for a cache holding up to `capacity` entries.
+    fn new(capacity: usize, hash: fn(&Key) -> u64) -> Self {
+        let width = capacity.clamp(16, 1 << 24).next_power_of_two();
+        Self {
+            counters: vec![0; width * DEPTH],
+            mask: width - 1,
+            additions: 0,
+            sample_size: width * 10,
+            hash,
+        }
+    }
+
+    /// Returns an empty sketch sized for a cache holding up to `capacity` entries.
+    pub fn resized(&self, capacity: usize) -> Self {
+        Self::new(capacity, self.hash)
+    }
+
+    /// Records an occurrence of `key`.
+    pub fn increment(&mut self, key: &Key) {
+        let hash = (self.hash)(key);
+        for row in 0..DEPTH {
+            let index = self.index(hash, row);
+            if self.counters[index] < MAX_COUNT {
+                self.counters[index] += 1;
+            }
+        }
+        self.additions += 1;
+        if self.additions >= self.sample_size {
+            for counter in &mut self.counters {
+                *counter /= 2;
+            }
+            self.additions /= 2;
+        }
+    }
+
+    /// Returns the estimated number of recent occurrences of `key`.
+    pub fn frequency(&self, key: &Key) -> u8 {
+        let hash = (self.hash)(key);
+        (0..DEPTH)
+            .map(|row| self.counters[self.index(hash, row)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn index(&self, hash: u64, row: usize) -> usize {
+        let mixed = hash.wrapping_mul(SEEDS[row]) >> 32;
+        row * (self.mask + 1) + (mixed as usize & self.mask)
+    }
+}
+
+fn hash_key<Key: Hash>(key: &Key) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone + Hash,
+{
+    /// Sets the policy deciding whether a new entry is admitted into a full cache.
+    ///
+    /// Admission policies other than `AdmissionPolicy::Always` require the keys to be hashable.
+    /// Entries which are not admitted are dropped, and reported by `notify_insert_full()`, except
+    /// under `AdmissionPolicy::WindowTinyLfu` where new entries are always admitted and those
+    /// losing the competition on leaving the window are evicted instead.  Changing the policy
+    /// forgets the access history gathered so far.
+    pub fn set_admission_policy(&mut self, policy: AdmissionPolicy) {
+        self.admission = match policy {
+            AdmissionPolicy::Always => None,
+            AdmissionPolicy::TinyLfu | AdmissionPolicy::WindowTinyLfu => {
+                Some(FrequencySketch::new(self.capacity, hash_key::<Key>))
+            }
+        };
+        self.admission_window = policy == AdmissionPolicy::WindowTinyLfu;
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns the index in `list` of the entry leaving the window to make room for a new one
+    /// under `AdmissionPolicy::WindowTinyLfu`, if it has been seen no more often than the entry
+    /// the eviction policy would evict, so is to be evicted instead.
+    pub(crate) fn window_loser_index(&mut self) -> Option<usize> {
+        if !self.admission_window || recency::overflow(self.map.len(), self.capacity) != 1 {
+            return None;
+        }
+        let window = policy::share(self.capacity, WINDOW_PERCENT).max(1);
+        let candidate = self.list.len().checked_sub(window)?;
+        let victim = self.victim_index()?;
+        let sketch = self.admission.as_ref()?;
+        let candidate_key = &self.list[candidate];
+        if candidate == victim
+            || self.map[candidate_key].pinned
+            || sketch.frequency(candidate_key) > sketch.frequency(&self.list[victim])
+        {
+            return None;
+        }
+        Some(candidate)
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Bookkeeping data stored along with each cached value.
+
+use crate::policy::Segment;
+use crate::{ExpiryPolicy, Instant};
+use std::time::Duration;
+
+/// Cache-wide settings deciding when entries expire.
+#[derive(Clone, Copy)]
+pub(crate) struct ExpirySettings {
+    pub time_to_live: Option<Duration>,
+    pub policy: ExpiryPolicy,
+    /// Whether expiration is paused, in which case no entry expires.
+    pub paused: bool,
+}
+
+/// A cached value along with the data needed to decide when to evict it.
+#[derive(Clone)]
+pub(crate) struct CacheEntry<Value> {
+    pub value: Value,
+    /// Time of the last access.
+    pub timestamp: Instant,
+    /// Time the value was written, i.e. inserted or replaced.
+    pub written: Instant,
+    /// Time the key was inserted, which replacing the value doesn't change.
+    pub inserted: Instant,
+    /// Number of times the entry was accessed since it was inserted.
+    pub accesses: u64,
+    /// Number the value was given when written, higher than that of any value written before.
+    pub generation: u64,
+    /// Cost of retrieving the value again, in arbitrary units.
+    pub cost: u64,
+    /// Size of the value in arbitrary units, never zero.
+    pub size: u64,
+    /// Priority of the entry under the GDSF policy, the lowest being evicted first.
+    pub priority: f64,
+    /// Time to live of this entry overriding the cache-wide one.
+    pub time_to_live: Option<Duration>,
+    /// Point in time the entry expires at no matter how it is accessed, if set.
+    pub expires_at: Option<Instant>,
+    /// Pinned entries are neither evicted on expiry nor to make room for new ones.
+    pub pinned: bool,
+    /// Permanent entries never expire, but may be evicted to make room for new ones.
+    pub permanent: bool,
+    /// Expiry time the entry is filed under in the expiry index.
+    pub deadline: Option<Instant>,
+    /// Segment the entry belongs to under the segmented eviction policies.
+    pub segment: Segment,
+    /// Whether the entry has been hit since the CLOCK hand last passed it.
+    pub referenced: bool,
+}
+
+impl<Value> CacheEntry<Value> {
+    pub fn new(value: Value, now: Instant) -> Self {
+        Self {
+            value,
+            timestamp: now,
+            written: now,
+            inserted: now,
+            accesses: 0,
+            generation: 0,
+            cost: 1,
+            size: 1,
+            priority: 0.0,
+            time_to_live: None,
+            expires_at: None,
+            pinned: false,
+            permanent: false,
+            deadline: None,
+            segment: Segment::Recent,
+            referenced: false,
+        }
+    }
+
+    /// Returns the time this entry expires at given the cache-wide settings, or `None` if it never
+    /// expires.
+    pub fn expiry_time(&self, expiry: ExpirySettings) -> Option<Instant> {
+        if self.pinned || self.permanent || expiry.paused {
+            return None;
+        }
+        let ttl = self.time_to_live.or(expiry.time_to_live);
+        let expiry_time = match expiry.policy {
+            ExpiryPolicy::AfterAccess => ttl.and_then(|ttl| self.timestamp.checked_add(ttl)),
+            ExpiryPolicy::AfterWrite => ttl.and_then(|ttl| self.written.checked_add(ttl)),
+            ExpiryPolicy::AfterWriteOrIdle(time_to_idle) => ttl
+                .and_then(|ttl| self.written.checked_add(ttl))
+                .into_iter()
+                .chain(self.timestamp.checked_add(time_to_idle))
+                .min(),
+        };
+        expiry_time.into_iter().chain(self.expires_at).min()
+    }
+
+    /// Returns how worth keeping the entry is under the GDSF policy, not accounting for ageing.
+    pub fn worth(&self) -> f64 {
+        (self.accesses + 1) as f64 * self.cost as f64 / self.size as f64
+    }
+
+    pub fn is_expired(&self, expiry: ExpirySettings, now: Instant) -> bool {
+        self.expiry_time(expiry)
+            .is_some_and(|expiry_time| expiry_time < now)
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Cache shared between threads behind a read-mostly `RwLock`, whose reads don't block each other.
+
+use crate::{instrument, Instant, LruCache};
+use std::borrow::Borrow;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Number of buffers the lookups are spread over, to keep readers from contending for one.
+const STRIPES: usize = 16;
+/// Number of lookups a buffer holds before readers try to apply them to the cache.
+const STRIPE_CAPACITY: usize = 64;
+
+/// Lookups recorded by readers, i.e. the key hit or `None` for a miss, along with the time they
+/// happened at.
+type Lookups<Key> = Vec<Mutex<Vec<(Option<Key>, Instant)>>>;
+
+/// `LruCache` which can be shared between threads behind a read-mostly `RwLock`, and read by
+/// several of them at once.
+///
+/// Moving an entry to the top of the cache on a hit requires exclusive access to the cache, so
+/// instead of taking the write lock on every `get()`, readers only take the read lock and record
+/// their hits and misses in buffers.  The recorded lookups are applied in batches, on every write
+/// and whenever a buffer fills up, exactly as `LruCache::get()` would have applied them, so that
+/// the recency order, timestamps, statistics and observer catch up with the reads shortly after.
+/// A lookup which can't be recorded straight away, because another reader holds the buffer or it
+/// is full, is dropped rather than waited for, which merely makes the recency order and
+/// statistics approximate under heavy contention.
+///
+/// Hits which do more than that, i.e. roll for early expiry or set a new time to live through
+/// `Expiry::expire_after_read()`, can't be deferred, so `get()` takes the write lock for them.
+///
+/// Reads aren't lock-free: they still take the read lock, so they block while a write is in
+/// progress, and contend on the lock's reader count.  Operations not offered here can be performed
+/// through `read()` and `write()`.
+pub struct ConcurrentLruCache<Key, Value> {
+    cache: RwLock<LruCache<Key, Value>>,
+    lookups: Lookups<Key>,
+    /// Buffer the next lookup is recorded in, modulo `STRIPES`.
+    next_stripe: AtomicUsize,
+}
+
+impl<Key, Value> ConcurrentLruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Constructs a `ConcurrentLruCache` sharing `cache`.
+    pub fn new(cache: LruCache<Key, Value>) -> Self {
+        Self {
+            cache: RwLock::new(cache),
+            lookups: (0..STRIPES).map(|_| Mutex::new(Vec::new())).collect(),
+            next_stripe: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a clone of the value stored under `key`, or `None` if the key doesn't exist or has
+    /// expired.  Usually takes the read lock only, so doesn't block other readers; the lookup is
+    /// applied to the cache later.
+    pub fn get<Q>(&self, key: &Q) -> Option<Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        Value: Clone,
+    {
+        let now = Instant::now();
+        let deferred = {
+            let cache = self.read();
+            let expiry = cache.expiry();
+            match cache.map.get_key_value(key) {
+                Some((stored_key, entry)) if !entry.is_expired(expiry, now) => {
+                    if cache.hit_needs_write(stored_key, &entry.value) {
+                        None
+                    } else {
+                        let stripe_full = self.record_lookup(Some(stored_key.clone()), now);
+                        Some((Some(entry.value.clone()), stripe_full))
+                    }
+                }
+                _ => Some((None, self.record_lookup(None, now))),
+            }
+        };
+        let (value, stripe_full) = match deferred {
+            Some(deferred) => deferred,
+            None => return self.write().get(key).cloned(),
+        };
+        if stripe_full {
+            if let Ok(mut cache) = self.cache.try_write() {
+                apply_lookups(&self.lookups, &mut cache);
+            }
+        }
+        value
+    }
+
+    /// Returns whether the cache holds an unexpired entry for `key`, without counting as a hit.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.read().contains_key(key)
+    }
+
+    /// Returns the number of unexpired entries in the cache.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    /// Returns whether the cache holds no unexpired entries.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Inserts a key-value pair into the cache, returning the value it replaced if any.
+    pub fn insert(&self, key: Key, value: Value) -> Option<Value> {
+        self.write().insert(key, value)
+    }
+
+    /// Removes a key-value pair from the cache.
+    pub fn remove<Q>(&self, key: &Q) -> Option<Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.write().remove(key)
+    }
+
+    /// Applies `f` to the value stored under `key` and returns its result, or `None` if the key
+    /// doesn't exist.  The whole update happens under exclusive access to the cache, so it can't
+    /// interleave with other writes.
+    pub fn update<Q, R, F>(&self, key: &Q, f: F) -> Option<R>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        F: FnOnce(&mut Value) -> R,
+    {
+        self.write().update(key, f)
+    }
+
+    /// Replaces the value stored under `key` by `new` if it is still equal to `current`, e.g. for
+    /// optimistic updates computed without holding a lock, and returns the replaced value.
+    ///
+    /// Otherwise, returns a clone of the value found instead, or `None` if the key doesn't exist,
+    /// so that the update can be retried.
+    pub fn compare_exchange<Q>(
+        &self,
+        key: &Q,
+        current: &Value,
+        new: Value,
+    ) -> Result<Value, Option<Value>>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        Value: PartialEq + Clone,
+    {
+        self.compare_exchange_by(key, |value| value == current, new)
+    }
+
+    /// Much like `compare_exchange()`, except `new` replaces the value stored under `key` if
+    /// `predicate` holds for it, e.g. to compare a version number only.
+    pub fn compare_exchange_by<Q, P>(
+        &self,
+        key: &Q,
+        predicate: P,
+        new: Value,
+    ) -> Result<Value, Option<Value>>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        Value: Clone,
+        P: FnOnce(&Value) -> bool,
+    {
+        let mut cache = self.write();
+        match cache.get_mut(key) {
+            Some(value) if predicate(value) => Ok(mem::replace(value, new)),
+            Some(value) => Err(Some(value.clone())),
+            None => Err(None),
+        }
+    }
+
+    /// Shares access to the cache, for reads which don't update it such as `peek()`.  Doesn't
+    /// block other readers.
+    pub fn read(&self) -> RwLockReadGuard<'_, LruCache<Key, Value>> {
+        self.cache.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes exclusive access to the cache, once all the lookups recorded so far are applied to
+    /// it.
+    pub fn write(&self) -> RwLockWriteGuard<'_, LruCache<Key, Value>> {
+        let mut cache = self.cache.write().unwrap_or_else(PoisonError::into_inner);
+        apply_lookups(&self.lookups, &mut cache);
+        cache
+    }
+
+    /// Unwraps the cache, once all the lookups recorded so far are applied to it.
+    pub fn into_inner(self) -> LruCache<Key, Value> {
+        let mut cache = self
+            .cache
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        apply_lookups(&self.lookups, &mut cache);
+        cache
+    }
+
+    /// Records a hit on `key`, or a miss if `None`, returning whether its buffer is full.
+    fn record_lookup(&self, key: Option<Key>, now: Instant) -> bool {
+        let stripe = self.next_stripe.fetch_add(1, Ordering::Relaxed) % STRIPES;
+        match self.lookups[stripe].try_lock() {
+            Ok(mut lookups) => {
+                if lookups.len() < STRIPE_CAPACITY {
+                    lookups.push((key, now));
+                }
+                lookups.len() >= STRIPE_CAPACITY
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Applies the lookups recorded in `lookups` to `cache`, in the order they happened in.
+fn apply_lookups<Key, Value>(lookups: &Lookups<Key>, cache: &mut LruCache<Key, Value>)
+where
+    Key: Ord + Clone,
+{
+    let mut recorded = Vec::new();
+    for stripe in lookups {
+        recorded.append(&mut stripe.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+    recorded.sort_by_key(|(_, at)| *at);
+    for (key, at) in recorded {
+        match key {
+            Some(key) => cache.replay_hit(&key, at),
+            None => cache.record_miss(at),
+        }
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns whether a hit on `key` can't be deferred, because it rolls for early expiry or sets
+    /// a new time to live.
+    fn hit_needs_write(&self, key: &Key, value: &Value) -> bool {
+        self.early_expiry.is_some()
+            || self
+                .expiry_hook
+                .as_ref()
+                .is_some_and(|hook| hook.expire_after_read(key, value).is_some())
+    }
+
+    /// Applies a hit on `key` which happened at `at`, as `get()` would have.
+    fn replay_hit(&mut self, key: &Key, at: Instant) {
+        match self.map.get(key) {
+            Some(entry) => {
+                // Entries written since keep their later timestamp.
+                let refresh = entry.timestamp < at;
+                let _ = self.do_lookup(key, at, self.promotion.on_read(), refresh, false);
+            }
+            // Removed since, but the reader got the value all the same.
+            None => {
+                instrument::lookup(true);
+                self.stats.lookup(true, at);
+            }
+        }
+    }
+}
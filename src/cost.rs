@@ -0,0 +1,117 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Retrieval cost and size of the entries, weighed by `EvictionPolicy::GreedyDualSizeFrequency`.
+
+use crate::{instrument, InsertOutcome, LruCache};
+use std::sync::Arc;
+
+/// Hook returning the size of the entries inserted without an explicit one.
+pub(crate) type Weigher<Key, Value> = Arc<dyn Fn(&Key, &Value) -> u64 + Send + Sync>;
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Inserts a key-value pair into the cache along with the cost of retrieving the value again
+    /// and its size, in any units as long as they are consistent across entries.
+    ///
+    /// Only `EvictionPolicy::GreedyDualSizeFrequency` takes them into account.  Entries inserted
+    /// by `insert()` have a cost and size of 1, unless they replace an entry, whose cost and size
+    /// they keep, or a weigher gives them their size.  A size of 0 counts as 1.  The weigher isn't
+    /// consulted here.
+    ///
+    /// If the key already existed in the cache, the existing value is returned and overwritten in
+    /// the cache.  Otherwise, the key-value pair is inserted and `None` is returned.
+    pub fn insert_with_cost(
+        &mut self,
+        key: Key,
+        value: Value,
+        cost: u64,
+        size: u64,
+    ) -> Option<Value> {
+        let outcome = self.notify_insert_with_cost(key, value, cost, size);
+        self.forward_expired(outcome.expired);
+        outcome.replaced
+    }
+
+    /// Much like `insert_with_cost()`, except it returns everything that left the cache as a
+    /// result, as `notify_insert_full()` does.
+    ///
+    /// The value is also handed back as rejected if its size exceeds the maximum entry weight,
+    /// in which case the cache is left untouched, previous value included.
+    pub fn notify_insert_with_cost(
+        &mut self,
+        key: Key,
+        value: Value,
+        cost: u64,
+        size: u64,
+    ) -> InsertOutcome<Key, Value> {
+        if self
+            .max_entry_weight
+            .is_some_and(|max_weight| size > max_weight)
+        {
+            instrument::insertion(false, true, 0);
+            return InsertOutcome {
+                replaced: None,
+                expired: Vec::new(),
+                evicted: Vec::new(),
+                rejected: Some(value),
+            };
+        }
+        // The size given takes the place of the weigher's.
+        let weigher = self.weigher.take();
+        let outcome = self.notify_insert_full(key.clone(), value);
+        self.weigher = weigher;
+        if outcome.rejected.is_none() {
+            if let Some(entry) = self.map.get_mut(&key) {
+                entry.cost = cost;
+                entry.size = size.max(1);
+                entry.priority = self.inflation + entry.worth();
+            }
+        }
+        outcome
+    }
+
+    /// Returns the largest weight of the entries inserted, or `None` if there is no limit.
+    pub fn max_entry_weight(&self) -> Option<u64> {
+        self.max_entry_weight
+    }
+
+    /// Makes the inserts turn down the entries heavier than `max_weight`, or lifts the limit if
+    /// `None`, so that a single huge entry can't evict a large part of the cache.  Applies to the
+    /// entries inserted from then on.
+    ///
+    /// The weight of an entry is the size given to `insert_with_cost()`, or else the one the
+    /// weigher returns; entries aren't limited without a weigher.  Turned down entries are handed
+    /// back as rejected by `notify_insert_full()` and the like, leaving the previous value if any
+    /// in the cache.  As with the admission policy, the entry API, `get_or_insert_with_ttl()` and
+    /// `try_insert()` insert their value regardless, since they return a reference to it.
+    pub fn set_max_entry_weight(&mut self, max_weight: Option<u64>) {
+        self.max_entry_weight = max_weight;
+    }
+
+    /// Makes `weigh` give the size of the entries inserted or replaced from then on, other than by
+    /// `insert_with_cost()`, replacing the previous weigher if any.  Clones of the cache share the
+    /// weigher.
+    ///
+    /// The size is checked against the maximum entry weight, and weighed by
+    /// `EvictionPolicy::GreedyDualSizeFrequency`.
+    pub fn set_weigher<F>(&mut self, weigh: F)
+    where
+        F: Fn(&Key, &Value) -> u64 + Send + Sync + 'static,
+    {
+        self.weigher = Some(Arc::new(weigh));
+    }
+
+    /// Removes the weigher, if any.  The entries keep the size it gave them.
+    pub fn remove_weigher(&mut self) {
+        self.weigher = None;
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Manual traversal of a cache in recency order.
+
+use crate::{CacheEvent, Instant, LruCache};
+
+/// Cursor over the entries of a cache in recency order, which can read, update, remove and
+/// reposition the entry it points at as it goes.
+///
+/// Besides the entries, the cursor can point at a "ghost" position past both ends, from which
+/// `move_next()` wraps around to the most recently used entry and `move_prev()` to the least
+/// recently used one.  Expired entries are skipped.  Nothing the cursor does updates the
+/// timestamps.
+pub struct CursorMut<'a, Key, Value> {
+    cache: &'a mut LruCache<Key, Value>,
+    /// Index in the recency list of the current entry, or `None` at the ghost position.
+    index: Option<usize>,
+    /// Time the cursor was created at, which decides whether entries are expired.
+    now: Instant,
+}
+
+impl<'a, Key, Value> CursorMut<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns the key of the current entry, or `None` at the ghost position.
+    pub fn key(&self) -> Option<&Key> {
+        self.index.map(|index| &self.cache.list[index])
+    }
+
+    /// Returns the key and a mutable reference to the value of the current entry, or `None` at
+    /// the ghost position.
+    pub fn current(&mut self) -> Option<(&Key, &mut Value)> {
+        let key = &self.cache.list[self.index?];
+        LruCache::entry_mut(&mut self.cache.map, key).map(|(key, entry)| (key, &mut entry.value))
+    }
+
+    /// Moves to the next less recently used entry, or to the ghost position past the least
+    /// recently used one.
+    pub fn move_next(&mut self) {
+        let mut next = match self.index {
+            Some(index) => index.checked_sub(1),
+            None => self.cache.list.len().checked_sub(1),
+        };
+        while let Some(index) = next {
+            if !self.is_expired(index) {
+                break;
+            }
+            next = index.checked_sub(1);
+        }
+        self.index = next;
+    }
+
+    /// Moves to the next more recently used entry, or to the ghost position past the most
+    /// recently used one.
+    pub fn move_prev(&mut self) {
+        let len = self.cache.list.len();
+        let mut prev = match self.index {
+            Some(index) => Some(index + 1),
+            None => Some(0),
+        }
+        .filter(|index| *index < len);
+        while let Some(index) = prev {
+            if !self.is_expired(index) {
+                break;
+            }
+            prev = Some(index + 1).filter(|index| *index < len);
+        }
+        self.index = prev;
+    }
+
+    /// Removes the current entry and returns it, then moves to the next less recently used one.
+    /// Returns `None` at the ghost position.
+    pub fn remove_current(&mut self) -> Option<(Key, Value)> {
+        let index = self.index?;
+        let key = self.cache.list.remove(index)?;
+        let (key, entry) = self.cache.map.remove_entry(&key)?;
+        self.cache.expiries.remove(&key, &entry);
+        self.cache.segments.remove(&entry);
+        self.cache
+            .events
+            .emit(CacheEvent::Removed(&key, &entry.value));
+        self.move_next();
+        Some((key, entry.value))
+    }
+
+    /// Makes the current entry the most recently used one, then moves to the next less recently
+    /// used entry.  Returns whether there was a current entry.
+    pub fn move_current_to_front(&mut self) -> bool {
+        let key = match self.index.and_then(|index| self.cache.list.remove(index)) {
+            Some(key) => key,
+            None => return false,
+        };
+        self.cache.list.push_back(key);
+        self.move_next();
+        true
+    }
+
+    /// Makes the current entry the least recently used one, then moves to the next less recently
+    /// used entry, so that moving on eventually comes across it again.  Returns whether there was
+    /// a current entry.
+    pub fn move_current_to_back(&mut self) -> bool {
+        let key = match self.index.and_then(|index| self.cache.list.remove(index)) {
+            Some(key) => key,
+            None => return false,
+        };
+        self.cache.list.push_front(key);
+        match self.index {
+            // Already the least recently used one, so there's no next one.
+            Some(0) => self.index = None,
+            index => {
+                self.index = index.map(|index| index + 1);
+                self.move_next();
+            }
+        }
+        true
+    }
+
+    fn is_expired(&self, index: usize) -> bool {
+        self.cache.map[&self.cache.list[index]].is_expired(self.cache.expiry(), self.now)
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns a cursor pointing at the most recently used entry, or at the ghost position if the
+    /// cache is empty.  Also removes expired elements.
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, Key, Value> {
+        let now = Instant::now();
+        self.discard_expired(now);
+        self.flush_recency();
+        let mut cursor = CursorMut {
+            cache: self,
+            index: None,
+            now,
+        };
+        cursor.move_next();
+        cursor
+    }
+}
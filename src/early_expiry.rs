@@ -0,0 +1,46 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Probabilistic early expiration, protecting the source of the cached values from stampedes.
+
+use crate::rng::Rng;
+use crate::Instant;
+use std::time::Duration;
+
+/// Decides whether reads treat entries close to their expiry as misses, following the XFetch
+/// algorithm: an entry with `remaining` time to live is missed with probability
+/// `exp(-remaining / reload_time)`, so that the closer it is to expiry, the likelier a single
+/// reader reloads it ahead of everyone else.
+#[derive(Clone)]
+pub(crate) struct EarlyExpiry {
+    /// Time it typically takes to reload an entry.
+    pub reload_time: Duration,
+    rng: Rng,
+}
+
+impl EarlyExpiry {
+    pub fn new(reload_time: Duration) -> Self {
+        Self {
+            reload_time,
+            rng: Rng::new(),
+        }
+    }
+
+    /// Returns whether an entry expiring at `deadline` should be treated as expired at `now`.
+    pub fn expires_early(&mut self, deadline: Instant, now: Instant) -> bool {
+        let reload_time = self.reload_time.as_secs_f64();
+        if reload_time <= 0.0 {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(now).as_secs_f64();
+        // `-ln(u)` for `u` uniform in (0, 1] is exponentially distributed.
+        let gap = -self.rng.next_unit().ln() * reload_time;
+        gap >= remaining
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Notifications of the changes made to a cache.
+
+use crate::oplog::Recorder;
+use crate::LruCache;
+use std::collections::BTreeSet;
+
+/// Change made to the entries of a cache, as reported to its subscriber.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CacheEvent<'a, Key, Value> {
+    /// A new entry was inserted.
+    Inserted(&'a Key, &'a Value),
+    /// The value of an entry was replaced by an insert, the old value coming first.
+    Replaced(&'a Key, &'a Value, &'a Value),
+    /// An entry was removed on request, e.g. by `remove()`, `clear()` or `extract_if()`.
+    Removed(&'a Key, &'a Value),
+    /// An entry expired and was removed.
+    Expired(&'a Key, &'a Value),
+    /// An entry was evicted to make room for others, or to fit a reduced capacity.
+    Evicted(&'a Key, &'a Value),
+}
+
+impl<'a, Key, Value> Clone for CacheEvent<'a, Key, Value> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, Key, Value> Copy for CacheEvent<'a, Key, Value> {}
+
+/// Extension point notified of the outcome of every cache operation, e.g. for custom accounting.
+///
+/// All the callbacks do nothing by default, so implementors only override those they need.  They
+/// are called while the operation is being carried out, so they mustn't take long.
+pub trait CacheObserver<Key, Value> {
+    /// Called when a lookup finds an unexpired entry under `key`.
+    fn on_hit(&mut self, _key: &Key) {}
+
+    /// Called when a lookup finds no unexpired entry.
+    fn on_miss(&mut self) {}
+
+    /// Called when a value is inserted under `key`, whether new or replacing the previous one.
+    fn on_insert(&mut self, _key: &Key, _value: &Value) {}
+
+    /// Called when an entry is evicted to make room for others, or to fit a reduced capacity.
+    fn on_evict(&mut self, _key: &Key, _value: &Value) {}
+
+    /// Called when an entry expires and is removed.
+    fn on_expire(&mut self, _key: &Key, _value: &Value) {}
+}
+
+type Subscriber<Key, Value> = Box<dyn FnMut(CacheEvent<'_, Key, Value>) + Send + Sync>;
+
+type Observer<Key, Value> = Box<dyn CacheObserver<Key, Value> + Send + Sync>;
+
+/// The subscriber and observer of a cache and the log it records, if any, along with the keys
+/// changed since a `WriteHandle` last published them.
+pub(crate) struct EventHook<Key, Value> {
+    subscriber: Option<Subscriber<Key, Value>>,
+    observer: Option<Observer<Key, Value>>,
+    pub recorder: Option<Recorder<Key, Value>>,
+    pub changed: Option<BTreeSet<Key>>,
+}
+
+impl<Key, Value> EventHook<Key, Value> {
+    pub fn new() -> Self {
+        Self {
+            subscriber: None,
+            observer: None,
+            recorder: None,
+            changed: None,
+        }
+    }
+}
+
+impl<Key, Value> EventHook<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns whether the events are either subscribed to, recorded or tracked.
+    pub fn is_subscribed(&self) -> bool {
+        self.subscriber.is_some() || self.recorder.is_some() || self.changed.is_some()
+    }
+
+    pub fn emit(&mut self, event: CacheEvent<'_, Key, Value>) {
+        if let Some(changed) = &mut self.changed {
+            let key = match event {
+                CacheEvent::Inserted(key, _)
+                | CacheEvent::Replaced(key, _, _)
+                | CacheEvent::Removed(key, _)
+                | CacheEvent::Expired(key, _)
+                | CacheEvent::Evicted(key, _) => key,
+            };
+            let _ = changed.insert(key.clone());
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_event(event);
+        }
+        if let Some(observer) = &mut self.observer {
+            match event {
+                CacheEvent::Inserted(key, value) | CacheEvent::Replaced(key, _, value) => {
+                    observer.on_insert(key, value)
+                }
+                CacheEvent::Evicted(key, value) => observer.on_evict(key, value),
+                CacheEvent::Expired(key, value) => observer.on_expire(key, value),
+                CacheEvent::Removed(..) => (),
+            }
+        }
+        if let Some(subscriber) = &mut self.subscriber {
+            subscriber(event);
+        }
+    }
+
+    /// Reports a lookup hitting the entry stored under `key`, which isn't reported to the
+    /// subscriber.
+    pub fn hit(&mut self, key: &Key) {
+        if let Some(changed) = &mut self.changed {
+            let _ = changed.insert(key.clone());
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_access(key);
+        }
+        if let Some(observer) = &mut self.observer {
+            observer.on_hit(key);
+        }
+    }
+
+    /// Reports a lookup missing.
+    pub fn miss(&mut self) {
+        if let Some(observer) = &mut self.observer {
+            observer.on_miss();
+        }
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Registers `subscriber` to be called on every change made to the entries of the cache,
+    /// replacing the previous subscriber if any.
+    ///
+    /// The subscriber is called while the change is being made, so it mustn't take long; to
+    /// process the events elsewhere, e.g. on another thread, it can clone what it needs and send
+    /// it over a channel.  Clones of the cache have no subscriber.
+    pub fn subscribe<F>(&mut self, subscriber: F)
+    where
+        F: FnMut(CacheEvent<'_, Key, Value>) + Send + Sync + 'static,
+    {
+        self.events.subscriber = Some(Box::new(subscriber));
+    }
+
+    /// Unregisters the subscriber, if any.
+    pub fn unsubscribe(&mut self) {
+        self.events.subscriber = None;
+    }
+
+    /// Attaches `observer` to be notified of the outcome of every operation, replacing the
+    /// previous observer if any.  Clones of the cache have no observer.
+    pub fn set_observer<O>(&mut self, observer: O)
+    where
+        O: CacheObserver<Key, Value> + Send + Sync + 'static,
+    {
+        self.events.observer = Some(Box::new(observer));
+    }
+
+    /// Detaches the observer, if any.
+    pub fn remove_observer(&mut self) {
+        self.events.observer = None;
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Per-entry time to live derived from the cached values.
+
+use crate::LruCache;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Decides the time to live of the entries of a cache from their contents, e.g. from the
+/// `expires_in` field of a cached token, rather than giving them all the cache-wide one.
+///
+/// Each callback returns the time to live the entry is given from then on, as if by
+/// `LruCache::set_entry_ttl()`, or `None` to leave it as it is.  It is still measured according
+/// to the expiry policy.  All the callbacks return `None` by default, except
+/// `expire_after_update()` which defers to `expire_after_create()`.
+pub trait Expiry<Key, Value> {
+    /// Returns the time to live of a newly inserted entry, which otherwise gets the cache-wide
+    /// one.
+    fn expire_after_create(&self, _key: &Key, _value: &Value) -> Option<Duration> {
+        None
+    }
+
+    /// Returns the time to live of an entry whose value was replaced, which otherwise gets the
+    /// cache-wide one.
+    fn expire_after_update(&self, key: &Key, value: &Value) -> Option<Duration> {
+        self.expire_after_create(key, value)
+    }
+
+    /// Returns the time to live of an entry read by `get()` and the like, which otherwise keeps
+    /// its current one.
+    fn expire_after_read(&self, _key: &Key, _value: &Value) -> Option<Duration> {
+        None
+    }
+}
+
+pub(crate) type ExpiryHook<Key, Value> = Arc<dyn Expiry<Key, Value> + Send + Sync>;
+
+/// Hook giving the entries the time to live of the class their key falls in.
+struct KeyClassifier<F>(F);
+
+impl<Key, Value, F> Expiry<Key, Value> for KeyClassifier<F>
+where
+    F: Fn(&Key) -> Duration,
+{
+    fn expire_after_create(&self, key: &Key, _value: &Value) -> Option<Duration> {
+        Some((self.0)(key))
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Makes `hook` decide the time to live of the entries inserted, replaced and read from then
+    /// on, replacing the previous hook if any.  Clones of the cache share the hook.
+    ///
+    /// Reads through iterators and cursors don't consult the hook.
+    pub fn set_expiry_hook<E>(&mut self, hook: E)
+    where
+        E: Expiry<Key, Value> + Send + Sync + 'static,
+    {
+        self.expiry_hook = Some(Arc::new(hook));
+    }
+
+    /// Makes the entries inserted or replaced from then on get the time to live `classify` returns
+    /// for their key, e.g. so that peer info and data chunks kept in the same cache get different
+    /// lifetimes.  Short for `set_expiry_hook()` with a hook deferring to `classify`, which
+    /// replaces the previous hook if any.
+    pub fn set_ttl_classifier<F>(&mut self, classify: F)
+    where
+        F: Fn(&Key) -> Duration + Send + Sync + 'static,
+    {
+        self.set_expiry_hook(KeyClassifier(classify));
+    }
+
+    /// Removes the expiry hook, if any.  The entries keep the time to live it gave them.
+    pub fn remove_expiry_hook(&mut self) {
+        self.expiry_hook = None;
+    }
+}
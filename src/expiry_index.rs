@@ -0,0 +1,94 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Index of the cache entries ordered by the time they expire at.
+
+use crate::cache_entry::{CacheEntry, ExpirySettings};
+use crate::Instant;
+use std::collections::{btree_set, BTreeMap, BTreeSet};
+
+/// Keys of the cache entries which expire, ordered by expiry time.
+///
+/// Entries with their own time to live, or expiring on write rather than on access, don't expire
+/// in recency order, so the recency list alone can't tell which ones have expired.  Every change
+/// to an entry's timestamps or expiry settings must be followed by `update()` for the index to
+/// stay accurate.
+#[derive(Clone)]
+pub(crate) struct ExpiryIndex<Key> {
+    deadlines: BTreeSet<(Instant, Key)>,
+}
+
+impl<Key> ExpiryIndex<Key>
+where
+    Key: Ord + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            deadlines: BTreeSet::new(),
+        }
+    }
+
+    /// Files `entry` under its current expiry time, replacing the previous filing if any.
+    pub fn update<Value>(
+        &mut self,
+        key: &Key,
+        entry: &mut CacheEntry<Value>,
+        expiry: ExpirySettings,
+    ) {
+        self.remove(key, entry);
+        entry.deadline = entry.expiry_time(expiry);
+        if let Some(deadline) = entry.deadline {
+            let _ = self.deadlines.insert((deadline, key.clone()));
+        }
+    }
+
+    /// Removes the filing of `entry`, which is about to leave the cache.
+    pub fn remove<Value>(&mut self, key: &Key, entry: &CacheEntry<Value>) {
+        if let Some(deadline) = entry.deadline {
+            let _ = self.deadlines.remove(&(deadline, key.clone()));
+        }
+    }
+
+    /// Re-files all the entries of `map`, e.g. after the cache-wide expiry settings changed.
+    pub fn rebuild<Value>(
+        &mut self,
+        map: &mut BTreeMap<Key, CacheEntry<Value>>,
+        expiry: ExpirySettings,
+    ) {
+        self.deadlines.clear();
+        for (key, entry) in map.iter_mut() {
+            entry.deadline = entry.expiry_time(expiry);
+            if let Some(deadline) = entry.deadline {
+                let _ = self.deadlines.insert((deadline, key.clone()));
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.deadlines.clear();
+    }
+
+    /// Returns the earliest expiry time of all the entries.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.deadlines.iter().next().map(|(deadline, _)| *deadline)
+    }
+
+    /// Returns the expiry times and keys of all the entries, the earliest expiring first.
+    pub fn iter(&self) -> btree_set::Iter<'_, (Instant, Key)> {
+        self.deadlines.iter()
+    }
+
+    /// Returns the keys of the entries expired at `now`, the earliest expired first.
+    pub fn expired(&self, now: Instant) -> impl Iterator<Item = &Key> {
+        self.deadlines
+            .iter()
+            .take_while(move |(deadline, _)| *deadline < now)
+            .map(|(_, key)| key)
+    }
+}
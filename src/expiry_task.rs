@@ -0,0 +1,84 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Background removal of expired entries driven by a `tokio` runtime.
+
+use crate::{Instant, LruCache};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Wakes up the expiry task when the schedule changes, and when the cache is dropped so that the
+/// task can finish.
+pub(crate) struct ExpiryNotify(Arc<Notify>);
+
+impl ExpiryNotify {
+    pub(crate) fn wake(&self) {
+        self.0.notify_one();
+    }
+}
+
+impl Drop for ExpiryNotify {
+    fn drop(&mut self) {
+        self.wake();
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone + Send + 'static,
+    Value: Send + 'static,
+{
+    /// Spawns a task on the current `tokio` runtime which removes entries from `cache` as soon as
+    /// they expire, rather than leaving them in memory until the next cache access.
+    ///
+    /// The task sleeps until `next_expiry()` and is woken up early whenever a mutation schedules
+    /// an earlier expiry.  Removed entries are fed into the `expired_stream()` when the `async`
+    /// feature is enabled.  Only a single task should be spawned per cache; it finishes when the
+    /// cache is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from outside of a `tokio` runtime.
+    pub fn spawn_expiry_task(cache: &Arc<Mutex<Self>>) -> JoinHandle<()> {
+        let notify = {
+            let mut cache = lock(cache);
+            let notify = cache
+                .expiry_notify
+                .get_or_insert_with(|| ExpiryNotify(Arc::new(Notify::new())));
+            Arc::clone(&notify.0)
+        };
+        let cache = Arc::downgrade(cache);
+
+        tokio::spawn(async move {
+            loop {
+                let next_expiry = match cache.upgrade() {
+                    Some(cache) => {
+                        let mut cache = lock(&cache);
+                        cache.discard_expired(Instant::now());
+                        cache.next_expiry()
+                    }
+                    None => return,
+                };
+
+                match next_expiry {
+                    Some(at) => {
+                        let timeout = at.saturating_duration_since(Instant::now());
+                        let _ = tokio::time::timeout(timeout, notify.notified()).await;
+                    }
+                    None => notify.notified().await,
+                }
+            }
+        })
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
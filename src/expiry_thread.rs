@@ -0,0 +1,44 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Periodic removal of expired entries on a dedicated thread.
+
+use crate::{Instant, LruCache};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone + Send + 'static,
+    Value: Send + 'static,
+{
+    /// Wraps the cache for sharing between threads and spawns a thread which removes its expired
+    /// entries every `interval`, so that a cache which isn't accessed for a long time doesn't keep
+    /// holding on to expired values.
+    ///
+    /// Each purge is subject to the expiry budget.  Removed entries are fed into the
+    /// `expired_stream()` when the `async` feature is enabled.  The thread finishes on its next
+    /// wake-up after the last reference to the returned cache is dropped.
+    pub fn with_background_expiry(self, interval: Duration) -> Arc<Mutex<Self>> {
+        let cache = Arc::new(Mutex::new(self));
+        let weak = Arc::downgrade(&cache);
+        let _ = thread::spawn(move || loop {
+            thread::sleep(interval);
+            match weak.upgrade() {
+                Some(cache) => cache
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .discard_expired(Instant::now()),
+                None => return,
+            }
+        });
+        cache
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Read-only views of the contents of a cache at some point in time.
+
+use crate::{Instant, LruCache};
+use std::borrow::Borrow;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+/// Read-only view of the unexpired entries of an `LruCache` as of `LruCache::freeze()`.
+///
+/// The view never changes: entries which expire after the freeze remain visible, and changes
+/// made to the cache since aren't.  Clones share the same contents, so the view can be handed to
+/// any number of threads, e.g. request handlers, while the cache itself keeps changing.
+pub struct FrozenLruCache<Key, Value> {
+    inner: Arc<Frozen<Key, Value>>,
+}
+
+struct Frozen<Key, Value> {
+    /// The entries, in ascending key order.
+    entries: Vec<(Key, Value)>,
+    /// Indices in `entries` of the entries, least recently used first.
+    order: Vec<usize>,
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+    Value: Clone,
+{
+    /// Returns a read-only view of the unexpired entries, which the changes made to the cache
+    /// from then on don't affect.  Does not update the timestamps.
+    ///
+    /// Freezing clones every unexpired key and value once, and takes O(n log n) time for n
+    /// entries, so is best suited to views taken much less often than they are read.
+    pub fn freeze(&self) -> FrozenLruCache<Key, Value> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        let entries: Vec<_> = self
+            .map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(expiry, now))
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect();
+        let order = self
+            .list
+            .iter()
+            .filter_map(|key| entries.binary_search_by(|(other, _)| other.cmp(key)).ok())
+            .collect();
+        FrozenLruCache {
+            inner: Arc::new(Frozen { entries, order }),
+        }
+    }
+}
+
+impl<Key, Value> FrozenLruCache<Key, Value>
+where
+    Key: Ord,
+{
+    /// Returns a reference to the value stored under `key`, if any.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.position(key)
+            .map(|position| &self.inner.entries[position].1)
+    }
+
+    /// Returns whether `key` exists in the view or not.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.position(key).is_some()
+    }
+
+    /// Returns the number of entries in the view.
+    pub fn len(&self) -> usize {
+        self.inner.entries.len()
+    }
+
+    /// Returns `true` if there are no entries in the view.
+    pub fn is_empty(&self) -> bool {
+        self.inner.entries.is_empty()
+    }
+
+    /// Returns an iterator over all entries, in the most recently used order as of the freeze,
+    /// or the least recently used order when iterating from the back.
+    pub fn peek_iter(&self) -> impl DoubleEndedIterator<Item = (&Key, &Value)> + ExactSizeIterator {
+        let entries = &self.inner.entries;
+        self.inner.order.iter().rev().map(move |&position| {
+            let (key, value) = &entries[position];
+            (key, value)
+        })
+    }
+
+    /// Returns an iterator over the entries whose keys fall within `range`, in ascending key
+    /// order.
+    pub fn range<Q, R>(&self, range: R) -> impl DoubleEndedIterator<Item = (&Key, &Value)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let entries = &self.inner.entries;
+        let start = match range.start_bound() {
+            Bound::Included(start) => entries.partition_point(|(key, _)| key.borrow() < start),
+            Bound::Excluded(start) => entries.partition_point(|(key, _)| key.borrow() <= start),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => entries.partition_point(|(key, _)| key.borrow() <= end),
+            Bound::Excluded(end) => entries.partition_point(|(key, _)| key.borrow() < end),
+            Bound::Unbounded => entries.len(),
+        };
+        entries[start..end.max(start)]
+            .iter()
+            .map(|(key, value)| (key, value))
+    }
+
+    /// Returns the index in `entries` of the entry stored under `key`, if any.
+    fn position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.inner
+            .entries
+            .binary_search_by(|(other, _)| other.borrow().cmp(key))
+            .ok()
+    }
+}
+
+impl<Key, Value> Clone for FrozenLruCache<Key, Value> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Reads whose effect on the recency order is deferred until the value is known to be used.
+
+use crate::{Instant, LruCache};
+use std::borrow::Borrow;
+use std::ops::Deref;
+
+/// Guard returned by `LruCache::get_guard()`, dereferencing to the value read.
+///
+/// The read only counts as an access, promoting the entry and updating its timestamp, once the
+/// guard is committed or dropped.  Discarding the guard instead leaves the entry as if it had
+/// been peeked at, so speculative reads which end up unused don't affect the recency order.
+pub struct ValueGuard<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    cache: &'a mut LruCache<Key, Value>,
+    key: Key,
+    /// Time of the read, which the access is recorded at.
+    now: Instant,
+    done: bool,
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Retrieves a guard dereferencing to the value stored under `key`, or `None` if the key
+    /// doesn't exist.  Also removes expired elements.
+    ///
+    /// Unlike `get()`, the read only promotes the entry and updates its timestamp once the guard
+    /// is committed or dropped, and not at all if it is discarded.
+    pub fn get_guard<Q>(&mut self, key: &Q) -> Option<ValueGuard<'_, Key, Value>>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = Instant::now();
+        self.discard_expired(now);
+        if !self.is_hit(key, now) {
+            self.record_miss(now);
+            return None;
+        }
+        let (key, _) = self.map.get_key_value(key)?;
+        Some(ValueGuard {
+            key: key.clone(),
+            cache: self,
+            now,
+            done: false,
+        })
+    }
+}
+
+impl<'a, Key, Value> ValueGuard<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Records the read as an access now rather than when the guard is dropped.
+    pub fn commit(mut self) {
+        self.apply();
+    }
+
+    /// Drops the guard without recording the read, leaving the recency order and the timestamp
+    /// of the entry untouched.
+    pub fn discard(mut self) {
+        self.done = true;
+    }
+
+    /// Returns the key of the entry read.
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    fn apply(&mut self) {
+        if !self.done {
+            self.done = true;
+            let promote = self.cache.promotion.on_read();
+            let _ = self
+                .cache
+                .do_lookup(&self.key, self.now, promote, true, false);
+        }
+    }
+}
+
+impl<'a, Key, Value> Deref for ValueGuard<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.cache.map[&self.key].value
+    }
+}
+
+impl<'a, Key, Value> Drop for ValueGuard<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    fn drop(&mut self) {
+        self.apply();
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Cache split into a single writer and many readers of the contents it last published.
+
+use crate::{Instant, LruCache};
+use arc_swap::ArcSwap;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+use std::sync::Arc;
+
+/// Unexpired entries of a cache as of a publication, along with the time they expire at.
+type Contents<Key, Value> = BTreeMap<Key, (Value, Option<Instant>)>;
+
+/// Slot the writer publishes the contents of the cache into, and the readers read them from.
+type Shared<Key, Value> = Arc<ArcSwap<Contents<Key, Value>>>;
+
+/// Reading end of a cache split by `LruCache::into_handles()`.
+///
+/// Readers see the entries as of the last publication by the `WriteHandle`, minus those which
+/// have expired since.  The published contents are swapped atomically, so reads are lock-free and
+/// never wait for the writer.  Reads don't count as accesses, so they neither move the entries to
+/// the top of the cache nor extend their life.  Handles are cheap to clone, e.g. one for each
+/// thread.
+pub struct ReadHandle<Key, Value> {
+    shared: Shared<Key, Value>,
+}
+
+/// Writing end of a cache split by `LruCache::into_handles()`.
+///
+/// Changes made through the write handle are only visible to the readers once published.  Like
+/// evmap, the writer keeps two copies of the contents: the one the readers see, and a standby one
+/// it brings up to date and swaps in on publication.  Publishing thus only applies the entries
+/// changed since, rather than copying the whole cache.
+pub struct WriteHandle<Key, Value> {
+    cache: LruCache<Key, Value>,
+    shared: Shared<Key, Value>,
+    /// Copy of the contents which the readers don't see, as of the publication before last.
+    standby: Arc<Contents<Key, Value>>,
+    /// Keys changed between the publication before last and the last one, which are yet to be
+    /// applied to `standby`.
+    lagging: BTreeSet<Key>,
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+    Value: Clone,
+{
+    /// Splits the cache into a write handle, owning the cache, and a read handle to the contents
+    /// it publishes, which are published once to begin with.  Suits read-mostly workloads, e.g.
+    /// routing tables, where readers shouldn't be held up by a lock around the whole cache.
+    pub fn into_handles(mut self) -> (ReadHandle<Key, Value>, WriteHandle<Key, Value>) {
+        self.events.changed = Some(BTreeSet::new());
+        let contents = contents(&self);
+        let shared = Arc::new(ArcSwap::from_pointee(contents.clone()));
+        let writer = WriteHandle {
+            cache: self,
+            shared: Arc::clone(&shared),
+            standby: Arc::new(contents),
+            lagging: BTreeSet::new(),
+        };
+        (ReadHandle { shared }, writer)
+    }
+}
+
+impl<Key, Value> ReadHandle<Key, Value>
+where
+    Key: Ord,
+{
+    /// Returns a clone of the value stored under `key`, or `None` if the key doesn't exist or has
+    /// expired.
+    pub fn get<Q>(&self, key: &Q) -> Option<Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        Value: Clone,
+    {
+        let now = Instant::now();
+        self.shared
+            .load()
+            .get(key)
+            .filter(|(_, expiry_time)| !is_expired(*expiry_time, now))
+            .map(|(value, _)| value.clone())
+    }
+
+    /// Returns whether the published contents hold an unexpired entry for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = Instant::now();
+        self.shared
+            .load()
+            .get(key)
+            .is_some_and(|(_, expiry_time)| !is_expired(*expiry_time, now))
+    }
+
+    /// Returns the number of unexpired entries in the published contents.
+    pub fn len(&self) -> usize {
+        let now = Instant::now();
+        self.shared
+            .load()
+            .values()
+            .filter(|(_, expiry_time)| !is_expired(*expiry_time, now))
+            .count()
+    }
+
+    /// Returns whether the published contents hold no unexpired entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<Key, Value> Clone for ReadHandle<Key, Value> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<Key, Value> WriteHandle<Key, Value>
+where
+    Key: Ord + Clone,
+    Value: Clone,
+{
+    /// Inserts a key-value pair into the cache, returning the value it replaced if any.  Not
+    /// visible to the readers until published.
+    pub fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
+        self.cache.insert(key, value)
+    }
+
+    /// Removes a key-value pair from the cache.  Not visible to the readers until published.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache.remove(key)
+    }
+
+    /// Publishes the changes made to the cache since the last publication to the readers.
+    /// Readers still holding the previous contents carry on with them until they are done.
+    ///
+    /// Takes time proportional to the number of entries inserted, removed, expired, evicted or
+    /// read since the publication before last, rather than to the size of the cache, unless a
+    /// reader still holds the contents it saw back then.  Other changes, e.g. values modified
+    /// through `peek_mut()` or lifetimes changed by `pin()` or `set_entry_ttl()`, are only
+    /// published by `publish_all()`.
+    pub fn publish(&mut self) {
+        let changed = self
+            .cache
+            .events
+            .changed
+            .as_mut()
+            .map_or_else(BTreeSet::new, mem::take);
+        let (now, expiry) = (Instant::now(), self.cache.expiry());
+        let standby = Arc::make_mut(&mut self.standby);
+        for key in self.lagging.iter().chain(&changed) {
+            match self.cache.map.get(key) {
+                Some(entry) if !entry.is_expired(expiry, now) => {
+                    let published = (entry.value.clone(), entry.expiry_time(expiry));
+                    let _ = standby.insert(key.clone(), published);
+                }
+                _ => {
+                    let _ = standby.remove(key);
+                }
+            }
+        }
+        self.standby = self.shared.swap(Arc::clone(&self.standby));
+        self.lagging = changed;
+    }
+
+    /// Publishes all the unexpired entries of the cache to the readers, replacing the contents
+    /// they see, whatever changes were made to the cache.  Takes time proportional to the size
+    /// of the cache.
+    pub fn publish_all(&mut self) {
+        if let Some(changed) = &mut self.cache.events.changed {
+            changed.clear();
+        }
+        let contents = contents(&self.cache);
+        self.standby = Arc::new(contents.clone());
+        self.lagging.clear();
+        self.shared.store(Arc::new(contents));
+    }
+
+    /// Returns the underlying cache, e.g. to inspect it.
+    pub fn cache(&self) -> &LruCache<Key, Value> {
+        &self.cache
+    }
+
+    /// Returns the underlying cache, e.g. for operations not offered here.  Not visible to the
+    /// readers until published.
+    pub fn cache_mut(&mut self) -> &mut LruCache<Key, Value> {
+        &mut self.cache
+    }
+
+    /// Unwraps the underlying cache.  The readers keep the contents last published.
+    pub fn into_inner(mut self) -> LruCache<Key, Value> {
+        self.cache.events.changed = None;
+        self.cache
+    }
+}
+
+/// Returns the unexpired entries of `cache`, as published.
+fn contents<Key, Value>(cache: &LruCache<Key, Value>) -> Contents<Key, Value>
+where
+    Key: Ord + Clone,
+    Value: Clone,
+{
+    let (now, expiry) = (Instant::now(), cache.expiry());
+    cache
+        .map
+        .iter()
+        .filter(|(_, entry)| !entry.is_expired(expiry, now))
+        .map(|(key, entry)| {
+            (
+                key.clone(),
+                (entry.value.clone(), entry.expiry_time(expiry)),
+            )
+        })
+        .collect()
+}
+
+fn is_expired(expiry_time: Option<Instant>, now: Instant) -> bool {
+    expiry_time.is_some_and(|expiry_time| expiry_time < now)
+}
@@ -0,0 +1,400 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! LRU cache backed by a hash map.
+
+use crate::cache_entry::{CacheEntry, ExpirySettings};
+use crate::{recency, ExpiryPolicy, Instant};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hash};
+use std::time::Duration;
+
+/// Implementation of [LRU cache](crate#least-recently-used-lru-cache) for keys which can be
+/// hashed but not necessarily ordered.
+///
+/// Behaves like `LruCache`, except that the keys are stored in a `HashMap` and so only need to
+/// implement `Hash + Eq` rather than `Ord`.  As with `HashMap`, the keys are hashed with
+/// `RandomState` by default, which can be replaced by another `BuildHasher` using the
+/// `*_and_hasher()` constructors.
+pub struct LruHashCache<Key, Value, S = RandomState> {
+    map: HashMap<Key, CacheEntry<Value>, S>,
+    list: VecDeque<Key>,
+    capacity: usize,
+    time_to_live: Option<Duration>,
+}
+
+impl<Key, Value> LruHashCache<Key, Value>
+where
+    Key: Hash + Eq + Clone,
+{
+    /// Constructor for capacity based `LruHashCache`.
+    pub fn with_capacity(capacity: usize) -> LruHashCache<Key, Value> {
+        LruHashCache::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+
+    /// Constructor for time based `LruHashCache`.
+    pub fn with_expiry_duration(time_to_live: Duration) -> LruHashCache<Key, Value> {
+        LruHashCache::with_expiry_duration_and_hasher(time_to_live, RandomState::new())
+    }
+
+    /// Constructor for dual-feature capacity and time based `LruHashCache`.
+    pub fn with_expiry_duration_and_capacity(
+        time_to_live: Duration,
+        capacity: usize,
+    ) -> LruHashCache<Key, Value> {
+        LruHashCache::new(capacity, Some(time_to_live), RandomState::new())
+    }
+}
+
+impl<Key, Value, S> LruHashCache<Key, Value, S>
+where
+    Key: Hash + Eq + Clone,
+    S: BuildHasher,
+{
+    /// Constructor for capacity based `LruHashCache` hashing the keys with `hash_builder`.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        LruHashCache::new(capacity, None, hash_builder)
+    }
+
+    /// Constructor for time based `LruHashCache` hashing the keys with `hash_builder`.
+    pub fn with_expiry_duration_and_hasher(time_to_live: Duration, hash_builder: S) -> Self {
+        LruHashCache::new(usize::MAX, Some(time_to_live), hash_builder)
+    }
+
+    fn new(capacity: usize, time_to_live: Option<Duration>, hash_builder: S) -> Self {
+        // Don't preallocate for caches without a capacity limit.
+        let reserved = if capacity == usize::MAX { 0 } else { capacity };
+        LruHashCache {
+            map: HashMap::with_capacity_and_hasher(reserved, hash_builder),
+            list: VecDeque::with_capacity(reserved),
+            capacity,
+            time_to_live,
+        }
+    }
+
+    /// Returns a reference to the cache's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Returns the maximum number of entries the cache may hold.
+    ///
+    /// Caches constructed without a capacity limit report `usize::MAX`.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the time to live of the cache entries, or `None` if they don't expire.
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.time_to_live
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the key already existed in the cache, the existing value is returned and overwritten in
+    /// the cache.  Otherwise, the key-value pair is inserted and `None` is returned.
+    /// Evicts and returns expired entries.
+    pub fn notify_insert(&mut self, key: Key, value: Value) -> (Option<Value>, Vec<(Key, Value)>) {
+        let now = Instant::now();
+        let expired = self.remove_expired(now);
+        if self.map.contains_key(&key) {
+            recency::touch(&mut self.list, &key);
+        } else {
+            self.remove_lru();
+            self.list.push_back(key.clone());
+        }
+
+        (
+            self.map
+                .insert(key, CacheEntry::new(value, now))
+                .map(|entry| entry.value),
+            expired,
+        )
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the key already existed in the cache, the existing value is returned and overwritten in
+    /// the cache.  Otherwise, the key-value pair is inserted and `None` is returned.
+    pub fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
+        self.notify_insert(key, value).0
+    }
+
+    /// Removes a key-value pair from the cache.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Value>
+    where
+        Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.remove(key).map(|entry| {
+            let _ = self
+                .list
+                .iter()
+                .position(|l| l.borrow() == key)
+                .map(|p| self.list.remove(p));
+            entry.value
+        })
+    }
+
+    /// Clears the `LruHashCache`, removing all values.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.list.clear();
+    }
+
+    /// Much like `get()`, except in addition returns expired entries.
+    pub fn notify_get<Q>(&mut self, key: &Q) -> (Option<&Value>, Vec<(Key, Value)>)
+    where
+        Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (value, expired) = self.notify_get_mut(key);
+        (value.map(|v| &*v), expired)
+    }
+
+    /// Retrieves a reference to the value stored under `key`, or `None` if the key doesn't exist.
+    /// Also removes expired elements and updates the time.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    /// Returns a reference to the value with the given `key`, if present and not expired, without
+    /// updating the timestamp.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.map
+            .get(key)
+            .filter(|entry| !entry.is_expired(expiry, now))
+            .map(|entry| &entry.value)
+    }
+
+    /// Retrieves a mutable reference to the value stored under `key`, or `None` if the key doesn't
+    /// exist. Also removes expired elements and updates the time.
+    pub fn notify_get_mut<Q>(&mut self, key: &Q) -> (Option<&mut Value>, Vec<(Key, Value)>)
+    where
+        Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        let expired = self.remove_expired(now);
+
+        let list = &mut self.list;
+        (
+            self.map.get_mut(key).map(|entry| {
+                recency::touch(list, key);
+                entry.timestamp = now;
+                &mut entry.value
+            }),
+            expired,
+        )
+    }
+
+    /// Retrieves a mutable reference to the value stored under `key`, or `None` if the key doesn't
+    /// exist.  Also removes expired elements and updates the time.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut Value>
+    where
+        Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.notify_get_mut(key).0
+    }
+
+    /// Returns whether `key` exists in the cache or not.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Returns the size of the cache, i.e. the number of cached non-expired key-value pairs.
+    pub fn len(&self) -> usize {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.list
+            .iter()
+            .filter_map(|key| self.map.get(key))
+            .position(|entry| !entry.is_expired(expiry, now))
+            .map_or(0, |p| self.map.len() - p)
+    }
+
+    /// Returns `true` if there are no non-expired entries in the cache.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over all unexpired entries that does not modify the timestamps.
+    /// Values are produced in the most recently used order.
+    pub fn peek_iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.list.iter().rev().filter_map(move |key| {
+            self.map
+                .get_key_value(key)
+                .filter(|(_, entry)| !entry.is_expired(expiry, now))
+                .map(|(key, entry)| (key, &entry.value))
+        })
+    }
+
+    /// Returns the settings deciding when entries expire, which are the same as those of an
+    /// `LruCache` expiring entries after access.
+    fn expiry(&self) -> ExpirySettings {
+        ExpirySettings {
+            time_to_live: self.time_to_live,
+            policy: ExpiryPolicy::AfterAccess,
+            paused: false,
+        }
+    }
+
+    /// Removes expired items from the cache and returns them.
+    ///
+    /// The list is ordered by access time, so the expired items are the ones at its front.
+    fn remove_expired(&mut self, now: Instant) -> Vec<(Key, Value)> {
+        let expiry = self.expiry();
+        let (map, list) = (&mut self.map, &mut self.list);
+        let expired = list
+            .iter()
+            .take_while(|key| map[*key].is_expired(expiry, now))
+            .count();
+        list.drain(..expired)
+            .filter_map(|key| map.remove(&key).map(|entry| (key, entry.value)))
+            .collect()
+    }
+
+    /// Removes least recently used items to make space for new ones.
+    fn remove_lru(&mut self) {
+        let count = recency::overflow(self.map.len(), self.capacity).min(self.list.len());
+        for key in self.list.drain(..count) {
+            assert!(self.map.remove(&key).is_some());
+        }
+    }
+}
+
+impl<Key, Value, S> Clone for LruHashCache<Key, Value, S>
+where
+    Key: Clone,
+    Value: Clone,
+    S: Clone,
+{
+    fn clone(&self) -> LruHashCache<Key, Value, S> {
+        LruHashCache {
+            map: self.map.clone(),
+            list: self.list.clone(),
+            capacity: self.capacity,
+            time_to_live: self.time_to_live,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "sn_fake_clock")]
+    fn sleep(time: u64) {
+        use sn_fake_clock::FakeClock;
+        FakeClock::advance_time(time);
+    }
+
+    #[cfg(not(feature = "sn_fake_clock"))]
+    fn sleep(time: u64) {
+        use std::thread;
+        thread::sleep(Duration::from_millis(time));
+    }
+
+    /// A key type without an ordering.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct PeerId([u8; 4]);
+
+    #[test]
+    fn size_only() {
+        let mut lru_cache = LruHashCache::<PeerId, usize>::with_capacity(3);
+
+        for i in 0..5 {
+            let _ = lru_cache.insert(PeerId([i; 4]), usize::from(i));
+        }
+        let _ = lru_cache.get(&PeerId([2; 4]));
+
+        assert_eq!(lru_cache.len(), 3);
+        assert!(!lru_cache.contains_key(&PeerId([1; 4])));
+        assert_eq!(
+            lru_cache.peek_iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            vec![2, 4, 3]
+        );
+    }
+
+    #[test]
+    fn time_only() {
+        let time_to_live = Duration::from_millis(100);
+        let mut lru_cache = LruHashCache::<PeerId, usize>::with_expiry_duration(time_to_live);
+        let _ = lru_cache.insert(PeerId([0; 4]), 0);
+        let _ = lru_cache.insert(PeerId([1; 4]), 1);
+        sleep(150);
+        assert!(lru_cache.is_empty());
+
+        let (_, expired) = lru_cache.notify_insert(PeerId([2; 4]), 2);
+
+        assert_eq!(expired, vec![(PeerId([0; 4]), 0), (PeerId([1; 4]), 1)]);
+        assert_eq!(lru_cache.len(), 1);
+    }
+
+    #[test]
+    fn custom_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let mut lru_cache = LruHashCache::<PeerId, usize, _>::with_capacity_and_hasher(
+            2,
+            BuildHasherDefault::<DefaultHasher>::default(),
+        );
+        for i in 0..3 {
+            let _ = lru_cache.insert(PeerId([i; 4]), usize::from(i));
+        }
+
+        assert_eq!(lru_cache.len(), 2);
+        assert_eq!(lru_cache.peek(&PeerId([2; 4])), Some(&2));
+        assert!(!lru_cache.contains_key(&PeerId([0; 4])));
+        let _: &BuildHasherDefault<DefaultHasher> = lru_cache.hasher();
+    }
+
+    #[test]
+    fn remove() {
+        let mut lru_cache = LruHashCache::<String, usize>::with_capacity(2);
+        let _ = lru_cache.insert("foo".to_string(), 0);
+
+        assert_eq!(lru_cache.remove("foo"), Some(0));
+        assert!(lru_cache.is_empty());
+        assert!(lru_cache.list.is_empty());
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut lru_cache = LruHashCache::<PeerId, usize>::with_capacity(0);
+        let mut reference = crate::LruCache::<usize, usize>::with_capacity(0);
+        for i in 0..3 {
+            let _ = lru_cache.insert(PeerId([i; 4]), usize::from(i));
+            let _ = reference.insert(usize::from(i), usize::from(i));
+        }
+
+        assert_eq!(lru_cache.len(), reference.len());
+        assert_eq!(
+            lru_cache.peek_iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            reference.peek_iter().map(|(_, v)| *v).collect::<Vec<_>>()
+        );
+    }
+}
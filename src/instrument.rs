@@ -0,0 +1,89 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Reporting of the cache activity to `tracing`, when the `tracing` feature is enabled.
+
+#[cfg(feature = "tracing")]
+use crate::Instant;
+
+/// Reports a lookup, and whether it found an unexpired entry.
+#[cfg(feature = "tracing")]
+pub(crate) fn lookup(hit: bool) {
+    tracing::trace!(target: "lru_time_cache", hit, "lookup");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn lookup(_hit: bool) {}
+
+/// Reports an insertion, and whether it replaced a value or was turned down.
+#[cfg(feature = "tracing")]
+pub(crate) fn insertion(replaced: bool, rejected: bool, evicted: usize) {
+    tracing::trace!(
+        target: "lru_time_cache",
+        replaced,
+        rejected,
+        evicted,
+        "insert"
+    );
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn insertion(_replaced: bool, _rejected: bool, _evicted: usize) {}
+
+/// Reports the eviction of `count` entries to make room for others.
+#[cfg(feature = "tracing")]
+pub(crate) fn eviction(count: usize) {
+    if count > 0 {
+        tracing::debug!(target: "lru_time_cache", count, "evicted entries");
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn eviction(_count: usize) {}
+
+/// Times a sweep of the expired entries.
+pub(crate) struct SweepTimer {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "tracing")]
+    started: Instant,
+}
+
+impl SweepTimer {
+    #[cfg(feature = "tracing")]
+    pub fn start() -> Self {
+        Self {
+            span: tracing::trace_span!(target: "lru_time_cache", "expiry_sweep").entered(),
+            started: Instant::now(),
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn start() -> Self {
+        Self {}
+    }
+
+    /// Reports the end of the sweep, which removed `count` entries.
+    #[cfg(feature = "tracing")]
+    pub fn finish(self, count: usize) {
+        if count > 0 {
+            let duration = Instant::now().saturating_duration_since(self.started);
+            tracing::debug!(
+                target: "lru_time_cache",
+                count,
+                duration_us = duration.as_micros() as u64,
+                "removed expired entries"
+            );
+        }
+        drop(self.span);
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn finish(self, _count: usize) {}
+}
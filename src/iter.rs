@@ -9,61 +9,81 @@
 
 //! Misc LRU cache iterators.
 
-#[cfg(feature = "sn_fake_clock")]
-use sn_fake_clock::FakeClock as Instant;
-use std::collections::{BTreeMap, VecDeque};
+use crate::cache_entry::{CacheEntry, ExpirySettings};
+use crate::events::{CacheEvent, EventHook};
+use crate::expiry_index::ExpiryIndex;
+use crate::policy::SegmentCounts;
+use crate::Instant;
+use std::collections::{btree_map, btree_set, BTreeMap, VecDeque};
+use std::iter::FusedIterator;
 use std::time::Duration;
-#[cfg(not(feature = "sn_fake_clock"))]
-use std::time::Instant;
 
 /// An iterator over an `LruCache`'s entries that updates the timestamps as values are traversed.
-/// Values are produced in the most recently used order.
+/// Values are produced in the most recently used order, or the least recently used order when
+/// iterating from the back.
 pub struct Iter<'a, Key, Value> {
     /// Reference to the iterated cache.
-    map: &'a mut BTreeMap<Key, (Value, Instant)>,
+    map: &'a mut BTreeMap<Key, CacheEntry<Value>>,
     /// Ordered cache entry keys where the least recently used items are first.
     list: &'a mut VecDeque<Key>,
-    lru_cache_ttl: Option<Duration>,
-    /// Index in `list` of the previously used item.
-    item_index: usize,
+    expiries: &'a mut ExpiryIndex<Key>,
+    expiry: ExpirySettings,
+    /// Index in `list` of the first item not visited yet.
+    front: usize,
+    /// Index in `list` past the last item not visited yet.
+    back: usize,
+    /// Number of unexpired items not visited yet.
+    remaining: usize,
+    /// Time the iterator was created at, traversed entries are given this timestamp.
+    now: Instant,
 }
 
 impl<'a, Key, Value> Iter<'a, Key, Value>
 where
-    Key: Ord,
+    Key: Ord + Clone,
 {
-    #[doc(hidden)]
-    pub fn new(
-        map: &'a mut BTreeMap<Key, (Value, Instant)>,
+    pub(crate) fn new(
+        map: &'a mut BTreeMap<Key, CacheEntry<Value>>,
         list: &'a mut VecDeque<Key>,
-        lru_cache_ttl: Option<Duration>,
+        expiries: &'a mut ExpiryIndex<Key>,
+        expiry: ExpirySettings,
     ) -> Self {
-        let item_index = list.len();
+        let now = Instant::now();
+        let remaining = list.len() - expiries.expired(now).count();
+        let back = list.len();
         Self {
             map,
             list,
-            lru_cache_ttl,
-            item_index,
+            expiries,
+            expiry,
+            front: 0,
+            back,
+            remaining,
+            now,
         }
     }
 
-    /// Returns next unexpired item in the cache or `None` if no such items.
-    /// Expired items are removed from the cache.
-    fn next_unexpired(&mut self, now: Instant) -> Option<Key> {
-        loop {
-            self.item_index = self.item_index.checked_sub(1)?;
-            let key = self.list.remove(self.item_index)?;
-            let value = self.map.get(&key)?;
-
-            if let Some(ttl) = self.lru_cache_ttl {
-                if value.1 + ttl > now {
-                    return Some(key);
-                } else {
-                    let _ = self.map.remove(&key);
-                }
-            } else {
-                return Some(key);
-            }
+    fn is_expired(&self, index: usize) -> bool {
+        self.map[&self.list[index]].is_expired(self.expiry, self.now)
+    }
+
+    /// Moves the item at `index` in `list` to the top of the cache and returns it.  Expired items
+    /// are never visited and are left for the next expiry sweep, since the references already
+    /// handed out by this iterator must stay valid.
+    #[allow(unsafe_code)]
+    fn visit(&mut self, index: usize) -> Option<(&'a Key, &'a Value)> {
+        let key = self.list.remove(index)?;
+        let (key, entry) = self.map.range_mut(&key..=&key).next()?;
+        self.list.push_back(key.clone());
+        entry.timestamp = self.now;
+        entry.accesses += 1;
+        self.expiries.update(key, entry, self.expiry);
+        self.remaining -= 1;
+
+        unsafe {
+            let key = std::mem::transmute::<&Key, &'a Key>(key);
+            let value = std::mem::transmute::<&Value, &'a Value>(&entry.value);
+            Some((key, value))
         }
     }
 }
@@ -76,23 +96,45 @@ where
 
     /// Returns the next element in the cache and moves it to the top of the cache.
     /// The most recently used items are yield first.
-    #[allow(unsafe_code)]
     fn next(&mut self) -> Option<(&'a Key, &'a Value)> {
-        let now = Instant::now();
-        let key = self.next_unexpired(now)?;
-        self.list.push_back(key);
-        let key = self.list.back()?;
-        let mut value = self.map.get_mut(&key)?;
-        value.1 = now;
+        while self.back > self.front {
+            self.back -= 1;
+            if !self.is_expired(self.back) {
+                return self.visit(self.back);
+            }
+        }
+        None
+    }
 
-        unsafe {
-            let key = std::mem::transmute::<&Key, &'a Key>(key);
-            let value = std::mem::transmute::<&Value, &'a Value>(&value.0);
-            Some((key, value))
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, Key, Value> DoubleEndedIterator for Iter<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns the least recently used element not visited yet and moves it to the top of the
+    /// cache.
+    fn next_back(&mut self) -> Option<(&'a Key, &'a Value)> {
+        while self.back > self.front {
+            if self.is_expired(self.front) {
+                self.front += 1;
+            } else {
+                // Every item after it shifts one place down.
+                self.back -= 1;
+                return self.visit(self.front);
+            }
         }
+        None
     }
 }
 
+impl<'a, Key: Ord + Clone, Value> ExactSizeIterator for Iter<'a, Key, Value> {}
+
+impl<'a, Key: Ord + Clone, Value> FusedIterator for Iter<'a, Key, Value> {}
+
 /// Entry produced by `NotifyIter` that might be still valid or expired.
 pub enum TimedEntry<'a, Key: 'a, Value: 'a> {
     /// Entry has not yet expired.
@@ -102,32 +144,81 @@ pub enum TimedEntry<'a, Key: 'a, Value: 'a> {
 }
 
 /// Much like `Iter` except will produce expired entries too where `Iter` silently drops them.
+/// Valid entries are produced first, followed by the expired ones; iterating from the back
+/// reverses that order.
 pub struct NotifyIter<'a, Key, Value> {
     /// Reference to the iterated cache.
-    map: &'a mut BTreeMap<Key, (Value, Instant)>,
+    map: &'a mut BTreeMap<Key, CacheEntry<Value>>,
     /// Ordered cache entry keys where the least recently used items are first.
     list: &'a mut VecDeque<Key>,
-    lru_cache_ttl: Option<Duration>,
-    /// Index in `list` of the previously used item.
-    item_index: usize,
+    expiries: &'a mut ExpiryIndex<Key>,
+    expiry: ExpirySettings,
+    /// Entries which had expired when the iterator was created, least recently used first.
+    expired: VecDeque<(Key, Value)>,
+    /// Index in `list` of the first item not visited yet.
+    front: usize,
+    /// Index in `list` past the last item not visited yet.
+    back: usize,
+    /// Time the iterator was created at, traversed entries are given this timestamp.
+    now: Instant,
 }
 
 impl<'a, Key, Value> NotifyIter<'a, Key, Value>
 where
     Key: Ord + Clone,
 {
-    #[doc(hidden)]
-    pub fn new(
-        map: &'a mut BTreeMap<Key, (Value, Instant)>,
+    pub(crate) fn new(
+        map: &'a mut BTreeMap<Key, CacheEntry<Value>>,
         list: &'a mut VecDeque<Key>,
-        lru_cache_ttl: Option<Duration>,
+        expiries: &'a mut ExpiryIndex<Key>,
+        segments: &mut SegmentCounts,
+        events: &mut EventHook<Key, Value>,
+        expiry: ExpirySettings,
     ) -> Self {
-        let item_index = list.len();
+        let now = Instant::now();
+        let mut expired = VecDeque::new();
+        let mut index = 0;
+        while index < list.len() {
+            if map[&list[index]].is_expired(expiry, now) {
+                if let Some(key) = list.remove(index) {
+                    if let Some(entry) = map.remove(&key) {
+                        expiries.remove(&key, &entry);
+                        segments.remove(&entry);
+                        events.emit(CacheEvent::Expired(&key, &entry.value));
+                        expired.push_back((key, entry.value));
+                    }
+                }
+            } else {
+                index += 1;
+            }
+        }
+        let back = list.len();
         Self {
             map,
             list,
-            lru_cache_ttl,
-            item_index,
+            expiries,
+            expiry,
+            expired,
+            front: 0,
+            back,
+            now,
+        }
+    }
+
+    /// Moves the item at `index` in `list` to the top of the cache and returns it.
+    #[allow(unsafe_code)]
+    fn visit(&mut self, index: usize) -> Option<TimedEntry<'a, Key, Value>> {
+        let key = self.list.remove(index)?;
+        let (key, entry) = self.map.range_mut(&key..=&key).next()?;
+        self.list.push_back(key.clone());
+        entry.timestamp = self.now;
+        entry.accesses += 1;
+        self.expiries.update(key, entry, self.expiry);
+
+        unsafe {
+            let key = std::mem::transmute::<&Key, &'a Key>(key);
+            let value = std::mem::transmute::<&Value, &'a Value>(&entry.value);
+            Some(TimedEntry::Valid(key, value))
         }
     }
 }
@@ -140,75 +231,128 @@ where
 
     /// Returns the next element in the cache and moves it to the top of the cache.
     /// The most recently used items are yield first.
-    #[allow(unsafe_code)]
     fn next(&mut self) -> Option<Self::Item> {
-        self.item_index = self.item_index.checked_sub(1)?;
-        let key = self.list.remove(self.item_index)?;
-        let mut value = self.map.get_mut(&key)?;
-        let now = Instant::now();
-
-        if let Some(ttl) = self.lru_cache_ttl {
-            if value.1 + ttl <= now {
-                let value = self.map.remove(&key)?;
-                return Some(TimedEntry::Expired(key, value.0));
-            }
+        if self.back > self.front {
+            self.back -= 1;
+            return self.visit(self.back);
         }
+        self.expired
+            .pop_back()
+            .map(|(key, value)| TimedEntry::Expired(key, value))
+    }
 
-        self.list.push_back(key);
-        let key = self.list.back()?;
-        value.1 = now;
-        unsafe {
-            let key = std::mem::transmute::<&Key, &'a Key>(key);
-            let value = std::mem::transmute::<&Value, &'a Value>(&value.0);
-            Some(TimedEntry::Valid(key, value))
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front + self.expired.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, Key, Value> DoubleEndedIterator for NotifyIter<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns the least recently used expired entry, or once there are none left, the least
+    /// recently used valid element not visited yet, moving it to the top of the cache.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if let Some((key, value)) = self.expired.pop_front() {
+            return Some(TimedEntry::Expired(key, value));
+        }
+        if self.back > self.front {
+            // Every item after it shifts one place down.
+            self.back -= 1;
+            return self.visit(self.front);
         }
+        None
     }
 }
 
+impl<'a, Key: Ord + Clone, Value> ExactSizeIterator for NotifyIter<'a, Key, Value> {}
+
+impl<'a, Key: Ord + Clone, Value> FusedIterator for NotifyIter<'a, Key, Value> {}
+
 /// An iterator over an `LruCache`'s entries that does not modify the timestamp.
+/// Values are produced in the most recently used order, or the least recently used order when
+/// iterating from the back.
 pub struct PeekIter<'a, Key, Value> {
     /// Reference to the iterated cache.
-    map: &'a BTreeMap<Key, (Value, Instant)>,
+    map: &'a BTreeMap<Key, CacheEntry<Value>>,
     /// Ordered cache entry keys where the least recently used items are first.
     list: &'a VecDeque<Key>,
-    lru_cache_ttl: Option<Duration>,
-    /// Index in `list` of the previously used item.
-    item_index: usize,
+    expiry: ExpirySettings,
+    /// Index in `list` of the first item not visited yet.
+    front: usize,
+    /// Index in `list` past the last item not visited yet.
+    back: usize,
+    /// Number of unexpired items not visited yet.
+    remaining: usize,
+    /// Time the iterator was created at, entries expired by then are skipped.
+    now: Instant,
 }
 
 impl<'a, Key, Value> PeekIter<'a, Key, Value>
 where
-    Key: Ord,
+    Key: Ord + Clone,
 {
-    #[doc(hidden)]
-    pub fn new(
-        map: &'a BTreeMap<Key, (Value, Instant)>,
+    pub(crate) fn new(
+        map: &'a BTreeMap<Key, CacheEntry<Value>>,
         list: &'a VecDeque<Key>,
-        lru_cache_ttl: Option<Duration>,
+        expiries: &ExpiryIndex<Key>,
+        expiry: ExpirySettings,
     ) -> Self {
-        let item_index = list.len();
+        let now = Instant::now();
         Self {
             map,
             list,
-            lru_cache_ttl,
-            item_index,
+            expiry,
+            front: 0,
+            back: list.len(),
+            remaining: list.len() - expiries.expired(now).count(),
+            now,
         }
     }
 
-    /// Returns next unexpired item in the cache or `None` if no such items.
-    fn next_unexpired(&mut self, now: Instant) -> Option<()> {
-        loop {
-            self.item_index = self.item_index.checked_sub(1)?;
-            let value = self.map.get(&self.list[self.item_index])?;
+    /// Restricts the iteration to the items before `end` in `list`.
+    pub(crate) fn truncated(mut self, end: usize) -> Self {
+        self.back = end.min(self.back);
+        let (map, expiry, now) = (self.map, self.expiry, self.now);
+        self.remaining = self
+            .list
+            .range(..self.back)
+            .filter(|key| !map[*key].is_expired(expiry, now))
+            .count();
+        self
+    }
 
-            if let Some(ttl) = self.lru_cache_ttl {
-                if value.1 + ttl > now {
-                    return Some(());
-                }
-            } else {
-                return Some(());
+    /// Returns the item at `index` in `list` unless it has expired.
+    fn unexpired(&mut self, index: usize) -> Option<(&'a Key, &'a CacheEntry<Value>)> {
+        let (list, map) = (self.list, self.map);
+        let key = &list[index];
+        let entry = &map[key];
+        if entry.is_expired(self.expiry, self.now) {
+            return None;
+        }
+        self.remaining -= 1;
+        Some((key, entry))
+    }
+
+    fn next_entry(&mut self) -> Option<(&'a Key, &'a CacheEntry<Value>)> {
+        while self.back > self.front {
+            self.back -= 1;
+            if let Some(item) = self.unexpired(self.back) {
+                return Some(item);
+            }
+        }
+        None
+    }
+
+    fn next_back_entry(&mut self) -> Option<(&'a Key, &'a CacheEntry<Value>)> {
+        while self.back > self.front {
+            self.front += 1;
+            if let Some(item) = self.unexpired(self.front - 1) {
+                return Some(item);
             }
         }
+        None
     }
 }
 
@@ -220,17 +364,349 @@ where
 
     /// Returns the next element in the cache that has not expired yet.
     /// The most recently used items are yield first.
-    #[allow(unsafe_code)]
     fn next(&mut self) -> Option<(&'a Key, &'a Value)> {
+        self.next_entry().map(|(key, entry)| (key, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, Key, Value> DoubleEndedIterator for PeekIter<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns the least recently used element not visited yet that has not expired yet.
+    fn next_back(&mut self) -> Option<(&'a Key, &'a Value)> {
+        self.next_back_entry()
+            .map(|(key, entry)| (key, &entry.value))
+    }
+}
+
+impl<'a, Key: Ord + Clone, Value> ExactSizeIterator for PeekIter<'a, Key, Value> {}
+
+impl<'a, Key: Ord + Clone, Value> FusedIterator for PeekIter<'a, Key, Value> {}
+
+/// Much like `PeekIter`, except it also yields the time each entry was last accessed at, and the
+/// time it has left to live if it expires, both as of when the iterator was created.
+pub struct MetaIter<'a, Key, Value> {
+    inner: PeekIter<'a, Key, Value>,
+}
+
+impl<'a, Key, Value> MetaIter<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    pub(crate) fn new(inner: PeekIter<'a, Key, Value>) -> Self {
+        Self { inner }
+    }
+
+    fn with_meta(
+        &self,
+        (key, entry): (&'a Key, &'a CacheEntry<Value>),
+    ) -> (&'a Key, &'a Value, Instant, Option<Duration>) {
+        let now = self.inner.now;
+        let remaining_ttl = entry
+            .expiry_time(self.inner.expiry)
+            .map(|expiry_time| expiry_time.saturating_duration_since(now));
+        (key, &entry.value, entry.timestamp, remaining_ttl)
+    }
+}
+
+impl<'a, Key, Value> Iterator for MetaIter<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    type Item = (&'a Key, &'a Value, Instant, Option<Duration>);
+
+    /// Returns the next element in the cache that has not expired yet along with its timing.
+    /// The most recently used items are yield first.
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next_entry()?;
+        Some(self.with_meta(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, Key, Value> DoubleEndedIterator for MetaIter<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns the least recently used element not visited yet that has not expired yet, along
+    /// with its timing.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next_back_entry()?;
+        Some(self.with_meta(item))
+    }
+}
+
+impl<'a, Key: Ord + Clone, Value> ExactSizeIterator for MetaIter<'a, Key, Value> {}
+
+impl<'a, Key: Ord + Clone, Value> FusedIterator for MetaIter<'a, Key, Value> {}
+
+/// An iterator over the unexpired entries of an `LruCache` which haven't been accessed for some
+/// time, least recently used first, that does not modify the timestamps.
+pub struct StaleIter<'a, Key, Value> {
+    inner: MetaIter<'a, Key, Value>,
+    min_age: Duration,
+}
+
+impl<'a, Key, Value> StaleIter<'a, Key, Value> {
+    pub(crate) fn new(inner: MetaIter<'a, Key, Value>, min_age: Duration) -> Self {
+        Self { inner, min_age }
+    }
+}
+
+impl<'a, Key, Value> Iterator for StaleIter<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    type Item = (&'a Key, &'a Value);
+
+    fn next(&mut self) -> Option<(&'a Key, &'a Value)> {
+        let now = self.inner.inner.now;
+        let min_age = self.min_age;
+        self.inner
+            .by_ref()
+            .rev()
+            .find(|(_, _, last_access, _)| now.saturating_duration_since(*last_access) > min_age)
+            .map(|(key, value, ..)| (key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+impl<'a, Key: Ord + Clone, Value> FusedIterator for StaleIter<'a, Key, Value> {}
+
+/// An iterator over the unexpired entries of an `LruCache` which are due to expire soon, along
+/// with the time they have left to live.  Values are produced in the order they expire in and
+/// their timestamps are not modified.
+pub struct ExpiringIter<'a, Key, Value> {
+    deadlines: btree_set::Iter<'a, (Instant, Key)>,
+    map: &'a BTreeMap<Key, CacheEntry<Value>>,
+    /// Time the iterator was created at, entries expired by then are skipped.
+    now: Instant,
+    /// Entries expiring at or after this time are not yielded, `None` if all are.
+    until: Option<Instant>,
+}
+
+impl<'a, Key, Value> ExpiringIter<'a, Key, Value>
+where
+    Key: Ord + Clone,
+{
+    pub(crate) fn new(
+        map: &'a BTreeMap<Key, CacheEntry<Value>>,
+        expiries: &'a ExpiryIndex<Key>,
+        within: Duration,
+    ) -> Self {
         let now = Instant::now();
-        self.next_unexpired(now)?;
-        let key = &self.list[self.item_index];
-        let value = self.map.get(&key)?;
+        Self {
+            deadlines: expiries.iter(),
+            map,
+            now,
+            until: now.checked_add(within),
+        }
+    }
+}
 
-        unsafe {
-            let key = std::mem::transmute::<&Key, &'a Key>(key);
-            let value = std::mem::transmute::<&Value, &'a Value>(&value.0);
-            Some((key, value))
+impl<'a, Key, Value> Iterator for ExpiringIter<'a, Key, Value>
+where
+    Key: Ord,
+{
+    type Item = (&'a Key, &'a Value, Duration);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (deadline, key) = self.deadlines.next()?;
+            if *deadline < self.now {
+                continue;
+            }
+            if self.until.is_some_and(|until| *deadline >= until) {
+                return None;
+            }
+            let entry = self.map.get(key)?;
+            return Some((key, &entry.value, *deadline - self.now));
+        }
+    }
+}
+
+impl<'a, Key: Ord, Value> FusedIterator for ExpiringIter<'a, Key, Value> {}
+
+/// An owning iterator over an `LruCache`'s unexpired entries.
+/// Values are produced in the most recently used order.
+pub struct IntoIter<Key, Value> {
+    map: BTreeMap<Key, CacheEntry<Value>>,
+    /// Ordered cache entry keys where the least recently used items are first.
+    list: VecDeque<Key>,
+    expiry: ExpirySettings,
+}
+
+impl<Key, Value> IntoIter<Key, Value>
+where
+    Key: Ord,
+{
+    pub(crate) fn new(
+        map: BTreeMap<Key, CacheEntry<Value>>,
+        list: VecDeque<Key>,
+        expiry: ExpirySettings,
+    ) -> Self {
+        Self { map, list, expiry }
+    }
+}
+
+impl<Key, Value> Iterator for IntoIter<Key, Value>
+where
+    Key: Ord,
+{
+    type Item = (Key, Value);
+
+    /// Returns the next unexpired element in the cache.
+    /// The most recently used items are yield first.
+    fn next(&mut self) -> Option<(Key, Value)> {
+        let now = Instant::now();
+        loop {
+            let key = self.list.pop_back()?;
+            let entry = self.map.remove(&key)?;
+            if !entry.is_expired(self.expiry, now) {
+                return Some((key, entry.value));
+            }
+        }
+    }
+}
+
+/// An iterator over the unexpired entries of an `LruCache` whose keys fall within a range.
+/// Values are produced in ascending key order and their timestamps are not modified.
+pub struct Range<'a, Key, Value> {
+    inner: btree_map::Range<'a, Key, CacheEntry<Value>>,
+    expiry: ExpirySettings,
+    /// Time the iterator was created at, entries expired by then are skipped.
+    now: Instant,
+}
+
+impl<'a, Key, Value> Range<'a, Key, Value> {
+    pub(crate) fn new(
+        inner: btree_map::Range<'a, Key, CacheEntry<Value>>,
+        expiry: ExpirySettings,
+    ) -> Self {
+        Self {
+            inner,
+            expiry,
+            now: Instant::now(),
+        }
+    }
+}
+
+impl<'a, Key, Value> Iterator for Range<'a, Key, Value> {
+    type Item = (&'a Key, &'a Value);
+
+    fn next(&mut self) -> Option<(&'a Key, &'a Value)> {
+        let (expiry, now) = (self.expiry, self.now);
+        self.inner
+            .find(|(_, entry)| !entry.is_expired(expiry, now))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+}
+
+/// A mutable iterator over the unexpired entries of an `LruCache` whose keys fall within a range.
+/// Values are produced in ascending key order and their timestamps are not modified.
+pub struct RangeMut<'a, Key, Value> {
+    inner: btree_map::RangeMut<'a, Key, CacheEntry<Value>>,
+    expiry: ExpirySettings,
+    /// Time the iterator was created at, entries expired by then are skipped.
+    now: Instant,
+}
+
+impl<'a, Key, Value> RangeMut<'a, Key, Value> {
+    pub(crate) fn new(
+        inner: btree_map::RangeMut<'a, Key, CacheEntry<Value>>,
+        expiry: ExpirySettings,
+    ) -> Self {
+        Self {
+            inner,
+            expiry,
+            now: Instant::now(),
+        }
+    }
+}
+
+impl<'a, Key, Value> Iterator for RangeMut<'a, Key, Value> {
+    type Item = (&'a Key, &'a mut Value);
+
+    fn next(&mut self) -> Option<(&'a Key, &'a mut Value)> {
+        let (expiry, now) = (self.expiry, self.now);
+        self.inner
+            .find(|(_, entry)| !entry.is_expired(expiry, now))
+            .map(|(key, entry)| (key, &mut entry.value))
+    }
+}
+
+/// An iterator which removes and yields the entries of an `LruCache` matching a predicate.
+/// Entries are visited in the least recently used order and removed only as the iterator is
+/// consumed; entries which haven't been visited yet are kept in the cache.
+pub struct ExtractIf<'a, Key, Value, F> {
+    /// Reference to the iterated cache.
+    map: &'a mut BTreeMap<Key, CacheEntry<Value>>,
+    /// Ordered cache entry keys where the least recently used items are first.
+    list: &'a mut VecDeque<Key>,
+    expiries: &'a mut ExpiryIndex<Key>,
+    segments: &'a mut SegmentCounts,
+    events: &'a mut EventHook<Key, Value>,
+    /// Index in `list` of the next item to visit.
+    item_index: usize,
+    pred: F,
+}
+
+impl<'a, Key, Value, F> ExtractIf<'a, Key, Value, F> {
+    pub(crate) fn new(
+        map: &'a mut BTreeMap<Key, CacheEntry<Value>>,
+        list: &'a mut VecDeque<Key>,
+        expiries: &'a mut ExpiryIndex<Key>,
+        segments: &'a mut SegmentCounts,
+        events: &'a mut EventHook<Key, Value>,
+        pred: F,
+    ) -> Self {
+        Self {
+            map,
+            list,
+            expiries,
+            segments,
+            events,
+            item_index: 0,
+            pred,
+        }
+    }
+}
+
+impl<'a, Key, Value, F> Iterator for ExtractIf<'a, Key, Value, F>
+where
+    Key: Ord + Clone,
+    F: FnMut(&Key, &mut Value) -> bool,
+{
+    type Item = (Key, Value);
+
+    fn next(&mut self) -> Option<(Key, Value)> {
+        while self.item_index < self.list.len() {
+            let key = &self.list[self.item_index];
+            let matches = match self.map.get_mut(key) {
+                Some(entry) => (self.pred)(key, &mut entry.value),
+                None => false,
+            };
+            if matches {
+                let key = self.list.remove(self.item_index)?;
+                let entry = self.map.remove(&key)?;
+                self.expiries.remove(&key, &entry);
+                self.segments.remove(&entry);
+                self.events.emit(CacheEvent::Removed(&key, &entry.value));
+                return Some((key, entry.value));
+            }
+            self.item_index += 1;
         }
+        None
     }
 }
@@ -76,23 +76,181 @@
     unused_results
 )]
 #![allow(
-    box_pointers,
     missing_copy_implementations,
     missing_debug_implementations,
     variant_size_differences
 )]
 
+use crate::admission::FrequencySketch;
+use crate::cache_entry::{CacheEntry, ExpirySettings};
+use crate::cost::Weigher;
+use crate::early_expiry::EarlyExpiry;
+use crate::events::EventHook;
+use crate::expiry_hook::ExpiryHook;
+use crate::expiry_index::ExpiryIndex;
+#[cfg(feature = "tokio")]
+use crate::expiry_task::ExpiryNotify;
+use crate::instrument::SweepTimer;
+use crate::negative::MissTtl;
+use crate::policy::{Ghosts, Segment, SegmentCounts};
+use crate::rng::Rng;
+use crate::stats::StatsRecorder;
+#[cfg(feature = "async")]
+use futures::channel::mpsc::UnboundedSender;
 #[cfg(feature = "sn_fake_clock")]
 use sn_fake_clock::FakeClock as Instant;
 use std::borrow::Borrow;
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, TryReserveError, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::Hash;
+use std::iter::{FromIterator, Rev};
+#[cfg(feature = "index_mut")]
+use std::ops::IndexMut;
+use std::ops::{Bound, Index, RangeBounds};
 use std::time::Duration;
-#[cfg(not(feature = "sn_fake_clock"))]
+#[cfg(not(any(feature = "sn_fake_clock", feature = "wasm")))]
 use std::time::Instant;
-use std::usize;
-
+#[cfg(all(feature = "wasm", not(feature = "sn_fake_clock")))]
+use web_time::Instant;
+
+mod admission;
+mod cache_entry;
+mod concurrent;
+mod cost;
+mod cursor;
+mod early_expiry;
+mod events;
+mod expiry_hook;
+mod expiry_index;
+#[cfg(feature = "tokio")]
+mod expiry_task;
+#[cfg(feature = "background_expiry")]
+mod expiry_thread;
+mod frozen;
+mod guard;
+mod handles;
+mod hash;
+mod instrument;
 mod iter;
-pub use crate::iter::{Iter, NotifyIter, PeekIter, TimedEntry};
+mod loader;
+mod negative;
+mod oplog;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "persistence")]
+mod persist;
+#[cfg(feature = "persistent")]
+mod persistent;
+mod policy;
+mod prefix;
+mod recency;
+mod rng;
+mod snapshot;
+mod sort_key;
+mod stats;
+mod store;
+#[cfg(feature = "async")]
+mod stream;
+mod tags;
+#[cfg(any(debug_assertions, feature = "validate"))]
+mod validate;
+mod weak;
+pub use crate::admission::AdmissionPolicy;
+pub use crate::concurrent::ConcurrentLruCache;
+pub use crate::cursor::CursorMut;
+pub use crate::events::{CacheEvent, CacheObserver};
+pub use crate::expiry_hook::Expiry;
+pub use crate::frozen::FrozenLruCache;
+pub use crate::guard::ValueGuard;
+pub use crate::handles::{ReadHandle, WriteHandle};
+pub use crate::hash::LruHashCache;
+pub use crate::iter::{
+    ExpiringIter, ExtractIf, IntoIter, Iter, MetaIter, NotifyIter, PeekIter, Range, RangeMut,
+    StaleIter, TimedEntry,
+};
+pub use crate::loader::{CacheLoader, LoadingCache};
+pub use crate::oplog::{Op, OpLog, OpRecord};
+#[cfg(feature = "persistent")]
+pub use crate::persistent::PersistentLruCache;
+pub use crate::policy::{EvictionPolicy, Promotion};
+pub use crate::snapshot::{CacheSnapshot, SnapshotEntry};
+pub use crate::sort_key::SortKeyCache;
+pub use crate::stats::{CacheStats, StatsWindow};
+pub use crate::store::{BackedCache, BackingStore, WriteMode};
+#[cfg(feature = "async")]
+pub use crate::stream::ExpiredStream;
+pub use crate::tags::TaggedLruCache;
+#[cfg(any(debug_assertions, feature = "validate"))]
+pub use crate::validate::ValidationError;
+pub use crate::weak::WeakLruCache;
+
+/// Decides which point in time the time to live of the cache entries is measured from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpiryPolicy {
+    /// Entries expire once they haven't been accessed for the time to live.  This is the default.
+    AfterAccess,
+    /// Entries expire once the time to live has passed since they were inserted or last replaced,
+    /// no matter how often they are read in the meantime.
+    AfterWrite,
+    /// Entries expire once the time to live has passed since they were inserted or last replaced,
+    /// or once they haven't been accessed for the given time to idle, whichever comes first.
+    AfterWriteOrIdle(Duration),
+}
+
+/// Everything that left the cache as a result of an insertion, returned by
+/// `LruCache::notify_insert_full()`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InsertOutcome<Key, Value> {
+    /// The value previously stored under the inserted key, if any.
+    pub replaced: Option<Value>,
+    /// Entries evicted because their time to live had passed, least recently used first.
+    pub expired: Vec<(Key, Value)>,
+    /// Entries evicted to make room for the inserted one, least recently used first.
+    pub evicted: Vec<(Key, Value)>,
+    /// The inserted value, if the admission policy or the maximum entry weight turned it down.
+    pub rejected: Option<Value>,
+}
+
+/// Bookkeeping data of a cache entry, returned by `LruCache::metadata()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryMetadata {
+    /// Time the entry was last accessed, which the time to live is measured from under
+    /// `ExpiryPolicy::AfterAccess`.
+    pub last_accessed: Instant,
+    /// Time the key was inserted.  Replacing the value doesn't change it.
+    pub inserted: Instant,
+    /// Time the value was inserted or last replaced.
+    pub written: Instant,
+    /// Number of times the entry was accessed since the key was inserted.
+    pub access_count: u64,
+    /// Generation of the value, as returned by `LruCache::get_with_generation()`.
+    pub generation: u64,
+    /// Position of the entry in the recency order among the unexpired entries, the most recently
+    /// used one being ranked 0.
+    pub recency_rank: usize,
+}
+
+/// Read-only view of an unexpired cache entry, returned by `LruCache::peek_entry()`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EntryView<'a, Key, Value> {
+    /// The stored key.
+    pub key: &'a Key,
+    /// The value.
+    pub value: &'a Value,
+    /// Time the entry was last accessed.
+    pub last_accessed: Instant,
+    /// Time left until the entry expires unless accessed again, or `None` if it doesn't expire.
+    pub remaining_ttl: Option<Duration>,
+}
+
+impl<'a, Key, Value> Clone for EntryView<'a, Key, Value> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, Key, Value> Copy for EntryView<'a, Key, Value> {}
 
 /// A view into a single entry in an LRU cache, which may either be vacant or occupied.
 pub enum Entry<'a, Key: 'a, Value: 'a> {
@@ -113,12 +271,67 @@ pub struct OccupiedEntry<'a, Value> {
     value: &'a mut Value,
 }
 
+/// The error returned by `LruCache::try_insert()` when the key already exists.
+pub struct OccupiedError<'a, Key, Value> {
+    /// The key which was not inserted.
+    pub key: Key,
+    /// The entry already stored under the key.
+    pub entry: OccupiedEntry<'a, Value>,
+    /// The value which was not inserted.
+    pub value: Value,
+}
+
+/// The error returned by `LruCache::try_insert_within_memory()` when the room for the new entry
+/// couldn't be allocated.
+pub struct ReserveError<Key, Value> {
+    /// The key which was not inserted.
+    pub key: Key,
+    /// The value which was not inserted.
+    pub value: Value,
+    /// The allocation failure.
+    pub error: TryReserveError,
+}
+
 /// Implementation of [LRU cache](self#least-recently-used-lru-cache).
 pub struct LruCache<Key, Value> {
-    map: BTreeMap<Key, (Value, Instant)>,
+    map: BTreeMap<Key, CacheEntry<Value>>,
     list: VecDeque<Key>,
+    expiries: ExpiryIndex<Key>,
+    segments: SegmentCounts,
+    ghosts: Ghosts<Key>,
     capacity: usize,
     time_to_live: Option<Duration>,
+    expiry_policy: ExpiryPolicy,
+    expiry_budget: Option<usize>,
+    /// Time expiration was paused at, if it is.
+    expiry_paused_at: Option<Instant>,
+    eviction_policy: EvictionPolicy,
+    promotion: Promotion,
+    rng: Rng,
+    /// Priority of the last entry evicted under the GDSF policy, which ages the others.
+    inflation: f64,
+    admission: Option<FrequencySketch<Key>>,
+    /// Whether new entries go through a window before competing for admission.
+    admission_window: bool,
+    early_expiry: Option<EarlyExpiry>,
+    /// Fraction of the time to live entries are randomly given or taken on insertion.
+    ttl_jitter: Option<f64>,
+    miss_time_to_live: Option<MissTtl<Value>>,
+    expiry_hook: Option<ExpiryHook<Key, Value>>,
+    max_entry_weight: Option<u64>,
+    weigher: Option<Weigher<Key, Value>>,
+    /// Maximum number of promotions buffered before being applied, if reads defer them.
+    promotion_buffer: Option<usize>,
+    /// Keys read since the buffered promotions were last applied, in the order they were read.
+    pending_promotions: Vec<Key>,
+    /// Generation of the last value written.
+    generation: u64,
+    events: EventHook<Key, Value>,
+    stats: StatsRecorder,
+    #[cfg(feature = "async")]
+    expired_sender: Option<UnboundedSender<(Key, Value)>>,
+    #[cfg(feature = "tokio")]
+    expiry_notify: Option<ExpiryNotify>,
 }
 
 impl<Key, Value> LruCache<Key, Value>
@@ -127,22 +340,18 @@ where
 {
     /// Constructor for capacity based `LruCache`.
     pub fn with_capacity(capacity: usize) -> LruCache<Key, Value> {
-        LruCache {
-            map: BTreeMap::new(),
-            list: VecDeque::with_capacity(capacity),
-            capacity,
-            time_to_live: None,
-        }
+        LruCache::new(VecDeque::with_capacity(capacity), capacity, None)
+    }
+
+    /// Constructor for an `LruCache` with neither capacity nor time limit, which may be added later
+    /// with `set_capacity()` and `set_time_to_live()`.
+    pub fn unbounded() -> LruCache<Key, Value> {
+        LruCache::new(VecDeque::new(), usize::MAX, None)
     }
 
     /// Constructor for time based `LruCache`.
     pub fn with_expiry_duration(time_to_live: Duration) -> LruCache<Key, Value> {
-        LruCache {
-            map: BTreeMap::new(),
-            list: VecDeque::new(),
-            capacity: usize::MAX,
-            time_to_live: Some(time_to_live),
-        }
+        LruCache::new(VecDeque::new(), usize::MAX, Some(time_to_live))
     }
 
     /// Constructor for dual-feature capacity and time based `LruCache`.
@@ -150,12 +359,264 @@ where
         time_to_live: Duration,
         capacity: usize,
     ) -> LruCache<Key, Value> {
-        LruCache {
-            map: BTreeMap::new(),
-            list: VecDeque::with_capacity(capacity),
+        LruCache::new(
+            VecDeque::with_capacity(capacity),
             capacity,
-            time_to_live: Some(time_to_live),
+            Some(time_to_live),
+        )
+    }
+
+    /// Returns the maximum number of entries the cache may hold.
+    ///
+    /// Caches constructed without a capacity limit report `usize::MAX`.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns whether the number of entries in the cache is limited.
+    pub fn is_capacity_limited(&self) -> bool {
+        self.capacity != usize::MAX
+    }
+
+    /// Returns the time to live of the cache entries, or `None` if they don't expire.
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.time_to_live
+    }
+
+    /// Returns the policy deciding which point in time the time to live is measured from.
+    pub fn expiry_policy(&self) -> ExpiryPolicy {
+        self.expiry_policy
+    }
+
+    /// Sets the policy deciding which point in time the time to live is measured from.
+    ///
+    /// The new policy applies to all entries, including the ones already in the cache.  Entries
+    /// which are expired under the new policy are evicted immediately and returned.
+    pub fn set_expiry_policy(&mut self, policy: ExpiryPolicy) -> Vec<(Key, Value)> {
+        let next_expiry = self.next_expiry();
+        self.expiry_policy = policy;
+        let expiry = self.expiry();
+        self.expiries.rebuild(&mut self.map, expiry);
+        let expired = self.remove_all_expired(Instant::now());
+        self.reschedule_expiry(next_expiry);
+        expired
+    }
+
+    /// Returns the maximum number of expired entries removed by a single cache operation, or `None`
+    /// if there is no limit.
+    pub fn expiry_budget(&self) -> Option<usize> {
+        self.expiry_budget
+    }
+
+    /// Limits the number of expired entries removed by a single cache operation such as `get()`
+    /// or `insert()`, or lifts the limit if `None`.
+    ///
+    /// Bounding the work done per call avoids latency spikes when many entries expire at once,
+    /// e.g. after an idle period.  Expired entries beyond the budget are left in the cache, where
+    /// they are invisible to lookups, and are removed by subsequent operations.  Changing the
+    /// configuration of the cache, e.g. by `set_capacity()`, still removes all expired entries.
+    pub fn set_expiry_budget(&mut self, budget: Option<usize>) {
+        self.expiry_budget = budget;
+    }
+
+    /// Returns whether expiration is paused by `pause_expiry()`.
+    pub fn is_expiry_paused(&self) -> bool {
+        self.expiry_paused_at.is_some()
+    }
+
+    /// Suspends expiration until `resume_expiry()` is called, e.g. for a maintenance window or
+    /// while debugging, so that the passage of time doesn't invalidate the entries.
+    ///
+    /// Entries which have already expired are removed first.  While paused, no entry expires and
+    /// `remaining_ttl()` returns `None`, but the cache is still limited by its capacity.  Has no
+    /// effect if expiration is already paused.
+    pub fn pause_expiry(&mut self) {
+        if self.expiry_paused_at.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        self.discard_all_expired(now);
+        self.expiry_paused_at = Some(now);
+        let expiry = self.expiry();
+        self.expiries.rebuild(&mut self.map, expiry);
+    }
+
+    /// Resumes expiration paused by `pause_expiry()`, extending the lifetime of every entry by the
+    /// time spent paused.
+    ///
+    /// The time to live of entries accessed or written while paused counts from the resumption.
+    /// Their access and write times are shifted accordingly, as reported by `metadata()`.  Has no
+    /// effect if expiration isn't paused.
+    pub fn resume_expiry(&mut self) {
+        let paused_at = match self.expiry_paused_at.take() {
+            Some(paused_at) => paused_at,
+            None => return,
+        };
+        let now = Instant::now();
+        let paused = now.saturating_duration_since(paused_at);
+        let shift = |time: Instant| time.checked_add(paused).map_or(now, |time| time.min(now));
+        for entry in self.map.values_mut() {
+            entry.timestamp = shift(entry.timestamp);
+            entry.written = shift(entry.written);
+            entry.expires_at = entry
+                .expires_at
+                .map(|deadline| deadline.checked_add(paused).unwrap_or(deadline));
+        }
+        let expiry = self.expiry();
+        self.expiries.rebuild(&mut self.map, expiry);
+        self.reschedule_expiry(None);
+    }
+
+    /// Returns the time it typically takes to reload an entry if early expiration is enabled, or
+    /// `None` otherwise.
+    pub fn early_expiry(&self) -> Option<Duration> {
+        self.early_expiry.as_ref().map(|early| early.reload_time)
+    }
+
+    /// Enables probabilistic early expiration given the time it typically takes to reload an
+    /// entry, or disables it if `None`.
+    ///
+    /// When enabled, `get()` and the other reads which update the cache randomly treat entries
+    /// close to expiry as misses, the more likely the closer they are to their expiry time and the
+    /// longer reloading takes.  This way, a single reader usually reloads a popular entry ahead of
+    /// time, instead of all readers missing it at once when it expires.  Entries missed early stay
+    /// in the cache until they are replaced or expire.  `peek()` is unaffected.
+    pub fn set_early_expiry(&mut self, reload_time: Option<Duration>) {
+        self.early_expiry = reload_time.map(EarlyExpiry::new);
+    }
+
+    /// Returns the fraction of the time to live entries are randomly given or taken on insertion,
+    /// if any.  See `set_ttl_jitter()`.
+    pub fn ttl_jitter(&self) -> Option<f64> {
+        self.ttl_jitter
+    }
+
+    /// Makes the entries inserted or replaced from then on live up to `fraction` of their time to
+    /// live longer or shorter, chosen at random, or disables the jitter if `None`.  `fraction` is
+    /// capped at 1.
+    ///
+    /// Entries loaded together, e.g. when warming up the cache, then expire spread over a window
+    /// rather than all at once, so that reloading them doesn't hit their source in a burst.  The
+    /// jittered time to live becomes the entry's own, as if set by `set_entry_ttl()`, so later
+    /// changes to the cache-wide time to live don't apply to it.
+    pub fn set_ttl_jitter(&mut self, fraction: Option<f64>) {
+        self.ttl_jitter = fraction.map(|fraction| {
+            if fraction > 0.0 {
+                fraction.min(1.0)
+            } else {
+                0.0
+            }
+        });
+    }
+
+    /// Returns the time to live of the values recording a miss, if they have their own.  See
+    /// `set_miss_time_to_live()`.
+    pub fn miss_time_to_live(&self) -> Option<Duration> {
+        self.miss_time_to_live
+            .as_ref()
+            .map(|miss| miss.time_to_live)
+    }
+
+    /// Returns the policy deciding which entry to evict when the cache is full.
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction_policy
+    }
+
+    /// Sets the policy deciding which entry to evict when the cache is full.
+    ///
+    /// The entries already in the cache are kept, but any usage history the previous policy
+    /// gathered about them is forgotten.
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.eviction_policy = policy;
+        self.inflation = 0.0;
+        for entry in self.map.values_mut() {
+            entry.segment = Segment::Recent;
+            entry.referenced = false;
+            entry.priority = entry.worth();
+        }
+        self.segments = SegmentCounts::default();
+        self.ghosts.clear();
+    }
+
+    /// Returns which accesses promote an entry.
+    pub fn promotion(&self) -> Promotion {
+        self.promotion
+    }
+
+    /// Sets which accesses promote an entry, e.g. `Promotion::Never` for first-in first-out
+    /// eviction.
+    ///
+    /// Applies to `get()`, `get_mut()`, `get_many()`, the entry API and `insert()`.
+    /// `get_and_promote()` still promotes, and iterating over the cache with `iter()` still moves
+    /// the visited entries to the top of the cache.
+    pub fn set_promotion(&mut self, promotion: Promotion) {
+        self.promotion = promotion;
+    }
+
+    /// Returns the maximum number of promotions reads buffer before applying them, or `None` if
+    /// reads promote entries straight away.
+    pub fn promotion_buffer(&self) -> Option<usize> {
+        self.promotion_buffer
+    }
+
+    /// Makes reads buffer up to `size` promotions, rather than moving each entry read to the top
+    /// of the cache straight away, or stops buffering them if `None`.
+    ///
+    /// The buffered promotions are applied in one pass over the recency order once the buffer is
+    /// full, on the next insert or eviction, or on `flush_recency()`, which spares read-heavy
+    /// workloads most of the churn of the recency order.  Until then, iterators, snapshots and
+    /// the like see the entries in the order they were in before the buffered reads.  Only
+    /// applies under `EvictionPolicy::Lru`: other policies still promote entries straight away.
+    pub fn set_promotion_buffer(&mut self, size: Option<usize>) {
+        self.promotion_buffer = size;
+        if size.map_or(true, |size| self.pending_promotions.len() >= size) {
+            self.flush_recency();
+        }
+    }
+
+    /// Applies the promotions buffered by reads, if any, moving the entries read to the top of
+    /// the cache in the order they were read.
+    pub fn flush_recency(&mut self) {
+        let pending = std::mem::take(&mut self.pending_promotions);
+        self.promote_all(pending.iter());
+    }
+
+    /// Returns the policy deciding whether a new entry is admitted into a full cache.
+    pub fn admission_policy(&self) -> AdmissionPolicy {
+        match (&self.admission, self.admission_window) {
+            (Some(_), false) => AdmissionPolicy::TinyLfu,
+            (Some(_), true) => AdmissionPolicy::WindowTinyLfu,
+            (None, _) => AdmissionPolicy::Always,
+        }
+    }
+
+    /// Sets the maximum number of entries the cache may hold.
+    ///
+    /// If the cache holds more unexpired entries than the new capacity, the least recently used
+    /// ones are evicted immediately and returned.
+    pub fn set_capacity(&mut self, capacity: usize) -> Vec<(Key, Value)> {
+        self.capacity = capacity;
+        if let Some(sketch) = &mut self.admission {
+            *sketch = sketch.resized(capacity);
         }
+        let now = Instant::now();
+        self.discard_all_expired(now);
+        self.evict_lru(self.map.len().saturating_sub(capacity), now)
+    }
+
+    /// Sets the time to live of the cache entries, or lifts the time limit if `None`.
+    ///
+    /// The new time to live applies to all entries, including the ones already in the cache, and
+    /// is measured according to the expiry policy.  Entries which are expired under the new time
+    /// to live are evicted immediately and returned.
+    pub fn set_time_to_live(&mut self, time_to_live: Option<Duration>) -> Vec<(Key, Value)> {
+        let next_expiry = self.next_expiry();
+        self.time_to_live = time_to_live;
+        let expiry = self.expiry();
+        self.expiries.rebuild(&mut self.map, expiry);
+        let expired = self.remove_all_expired(Instant::now());
+        self.reschedule_expiry(next_expiry);
+        expired
     }
 
     /// Inserts a key-value pair into the cache.
@@ -164,8 +625,16 @@ where
     /// the cache.  Otherwise, the key-value pair is inserted and `None` is returned.
     /// Evicts and returns expired entries.
     pub fn notify_insert(&mut self, key: Key, value: Value) -> (Option<Value>, Vec<(Key, Value)>) {
+        let outcome = self.notify_insert_full(key, value);
+        (outcome.replaced, outcome.expired)
+    }
+
+    /// Much like `notify_insert()`, except it also returns the least recently used entries evicted
+    /// to make room for the new one, and the new value if the admission policy turned it down, so
+    /// that no entry leaves the cache unnoticed.
+    pub fn notify_insert_full(&mut self, key: Key, value: Value) -> InsertOutcome<Key, Value> {
         let now = Instant::now();
-        self.do_notify_insert(key, value, now)
+        self.do_notify_insert(key, value, now, true)
     }
 
     /// Inserts a key-value pair into the cache.
@@ -173,36 +642,253 @@ where
     /// If the key already existed in the cache, the existing value is returned and overwritten in
     /// the cache.  Otherwise, the key-value pair is inserted and `None` is returned.
     pub fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
-        self.notify_insert(key, value).0
+        let (old_value, expired) = self.notify_insert(key, value);
+        self.forward_expired(expired);
+        old_value
+    }
+
+    /// Much like `insert()`, but as of `now` rather than the current time, e.g. so that
+    /// simulations and deterministic tests can drive time explicitly.
+    pub fn insert_at(&mut self, key: Key, value: Value, now: Instant) -> Option<Value> {
+        let outcome = self.do_notify_insert(key, value, now, true);
+        self.forward_expired(outcome.expired);
+        outcome.replaced
+    }
+
+    /// Inserts a key-value pair into the cache like `insert()`, but also returns the time the
+    /// replaced value was written at, i.e. inserted or last replaced, so its freshness can be
+    /// judged.
+    pub fn replace(&mut self, key: Key, value: Value) -> Option<(Value, Instant)> {
+        let written = self.map.get(&key).map(|entry| entry.written);
+        let replaced = self.insert(key, value);
+        replaced.zip(written)
+    }
+
+    /// Inserts a key-value pair into the cache which expires at `deadline` at the latest, e.g. when
+    /// the cached token does, even if it is accessed in the meantime.  The time to live still
+    /// applies if it runs out earlier.
+    ///
+    /// The deadline only holds until the value is replaced.  If the key already existed in the
+    /// cache, the existing value is returned and overwritten in the cache.  Otherwise, the
+    /// key-value pair is inserted and `None` is returned.
+    pub fn insert_until(&mut self, key: Key, value: Value, deadline: Instant) -> Option<Value> {
+        let outcome = self.notify_insert_full(key.clone(), value);
+        self.forward_expired(outcome.expired);
+        if outcome.rejected.is_some() {
+            return None;
+        }
+        let next_expiry = self.next_expiry();
+        let expiry = self.expiry();
+        if let Some((key, entry)) = Self::entry_mut(&mut self.map, &key) {
+            entry.expires_at = Some(deadline);
+            self.expiries.update(key, entry, expiry);
+        }
+        self.reschedule_expiry(next_expiry);
+        outcome.replaced
+    }
+
+    /// Inserts a key-value pair into the cache which never expires, but is evicted like any other
+    /// entry when the cache is full.
+    ///
+    /// The entry stays permanent until its value is replaced.  If the key already existed in the
+    /// cache, the existing value is returned and overwritten in the cache.  Otherwise, the
+    /// key-value pair is inserted and `None` is returned.
+    pub fn insert_permanent(&mut self, key: Key, value: Value) -> Option<Value> {
+        let outcome = self.notify_insert_full(key.clone(), value);
+        self.forward_expired(outcome.expired);
+        if outcome.rejected.is_some() {
+            return None;
+        }
+        let expiry = self.expiry();
+        if let Some((key, entry)) = Self::entry_mut(&mut self.map, &key) {
+            entry.permanent = true;
+            self.expiries.update(key, entry, expiry);
+        }
+        outcome.replaced
+    }
+
+    /// Inserts all the key-value pairs into the cache, in iteration order, removing expired
+    /// elements only once for the whole batch.
+    ///
+    /// Returns every entry displaced along the way: the expired ones, the least recently used ones
+    /// evicted to make room, the ones replaced by a pair for the same key, and the pairs the
+    /// admission policy turned down.
+    pub fn insert_many<I>(&mut self, pairs: I) -> Vec<(Key, Value)>
+    where
+        I: IntoIterator<Item = (Key, Value)>,
+    {
+        let now = Instant::now();
+        let pairs = pairs.into_iter();
+        self.reserve(pairs.size_hint().0);
+        let mut displaced = self.remove_expired(now);
+        for (key, value) in pairs {
+            let outcome = self.do_insert(key.clone(), value, now, true, Vec::new());
+            displaced.extend(outcome.expired);
+            displaced.extend(outcome.evicted);
+            if let Some(value) = outcome.replaced.or(outcome.rejected) {
+                displaced.push((key, value));
+            }
+        }
+        displaced
     }
 
     /// Removes a key-value pair from the cache.
-    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<Value>
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Value>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
-        self.map.remove(key).map(|(value, _)| {
-            let _ = self
-                .list
-                .iter()
-                .position(|l| l.borrow() == key)
-                .map(|p| self.list.remove(p));
-            value
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes a key-value pair from the cache, returning the stored key along with the value.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(Key, Value)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.detach(key).map(|(key, entry)| {
+            self.events.emit(CacheEvent::Removed(&key, &entry.value));
+            (key, entry.value)
         })
     }
 
+    /// Removes the key-value pairs stored under `keys`, returning them in the order of `keys`.
+    ///
+    /// Unlike calling `remove()` for each key, this makes a single pass over the recency order.
+    pub fn remove_many<'a, Q, I>(&mut self, keys: I) -> Vec<(Key, Value)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        let mut removed = Vec::new();
+        for key in keys {
+            if let Some((key, entry)) = self.map.remove_entry(key) {
+                self.expiries.remove(&key, &entry);
+                self.segments.remove(&entry);
+                self.events.emit(CacheEvent::Removed(&key, &entry.value));
+                removed.push((key, entry.value));
+            }
+        }
+        if !removed.is_empty() {
+            let keys: BTreeSet<&Key> = removed.iter().map(|(key, _)| key).collect();
+            self.list.retain(|key| !keys.contains(key));
+        }
+        removed
+    }
+
+    /// Removes the entries whose keys fall within `range`, and returns them in ascending key
+    /// order.  Also removes expired elements.
+    pub fn remove_range<Q, R>(&mut self, range: R) -> Vec<(Key, Value)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.discard_all_expired(Instant::now());
+        let keys: Vec<Key> = self.map.range(range).map(|(key, _)| key.clone()).collect();
+        self.remove_many::<Key, _>(&keys)
+    }
+
+    /// Evicts up to `count` unexpired entries and returns them, e.g. to shed load under memory
+    /// pressure.  The entries are chosen by the eviction policy, i.e. the least recently used ones
+    /// under LRU, and pinned entries are skipped.  Also removes expired elements.
+    pub fn evict(&mut self, count: usize) -> Vec<(Key, Value)> {
+        let now = Instant::now();
+        self.discard_all_expired(now);
+        self.evict_lru(count, now)
+    }
+
+    /// Evicts all the entries which haven't been accessed for longer than `age`, whatever the time
+    /// to live, and returns them least recently used first.  Pinned entries are skipped.  Also
+    /// removes expired elements.
+    pub fn evict_older_than(&mut self, age: Duration) -> Vec<(Key, Value)> {
+        let now = Instant::now();
+        self.discard_all_expired(now);
+        let (old, kept): (VecDeque<Key>, VecDeque<Key>) = {
+            let map = &self.map;
+            self.list.drain(..).partition(|key| {
+                let entry = &map[key];
+                !entry.pinned && now.saturating_duration_since(entry.timestamp) > age
+            })
+        };
+        self.list = kept;
+
+        let mut evicted = Vec::with_capacity(old.len());
+        for key in old {
+            if let Some(entry) = self.map.remove(&key) {
+                self.expiries.remove(&key, &entry);
+                self.segments.remove(&entry);
+                self.events.emit(CacheEvent::Evicted(&key, &entry.value));
+                evicted.push((key, entry.value));
+            }
+        }
+        instrument::eviction(evicted.len());
+        self.stats.eviction(evicted.len(), now);
+        evicted
+    }
+
     /// Clears the `LruCache`, removing all values.
     pub fn clear(&mut self) {
+        if self.events.is_subscribed() {
+            for key in &self.list {
+                self.events
+                    .emit(CacheEvent::Removed(key, &self.map[key].value));
+            }
+        }
         self.map.clear();
         self.list.clear();
+        self.expiries.clear();
+        self.segments = SegmentCounts::default();
+        self.ghosts.clear();
+        self.pending_promotions.clear();
+    }
+
+    /// Consumes the cache, returning its unexpired entries in a `BTreeMap`.
+    pub fn into_btree_map(self) -> BTreeMap<Key, Value> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.map
+            .into_iter()
+            .filter(|(_, entry)| !entry.is_expired(expiry, now))
+            .map(|(key, entry)| (key, entry.value))
+            .collect()
+    }
+
+    /// Consumes the cache, returning its unexpired entries in a `HashMap`.
+    pub fn into_hash_map(self) -> HashMap<Key, Value>
+    where
+        Key: Hash,
+    {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.map
+            .into_iter()
+            .filter(|(_, entry)| !entry.is_expired(expiry, now))
+            .map(|(key, entry)| (key, entry.value))
+            .collect()
+    }
+
+    /// Reserves room for at least `additional` more entries, so that inserting them doesn't
+    /// reallocate the cache's storage along the way.
+    ///
+    /// No more room is reserved than the capacity of the cache allows for.
+    pub fn reserve(&mut self, additional: usize) {
+        let room = self.capacity.saturating_sub(self.list.len());
+        self.list.reserve(additional.min(room));
+    }
+
+    /// Much like `reserve()`, but returns an error instead of aborting if the allocation fails,
+    /// e.g. so that a memory-constrained process can degrade gracefully.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let room = self.capacity.saturating_sub(self.list.len());
+        self.list.try_reserve(additional.min(room))
     }
 
     /// Much like `get()`, except in addition returns expired entries.
-    pub fn notify_get<Q: ?Sized>(&mut self, key: &Q) -> (Option<&Value>, Vec<(Key, Value)>)
+    pub fn notify_get<Q>(&mut self, key: &Q) -> (Option<&Value>, Vec<(Key, Value)>)
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
         let (value, expired) = self.notify_get_mut(key);
         (value.map(|v| &*v), expired)
@@ -210,773 +896,7017 @@ where
 
     /// Retrieves a reference to the value stored under `key`, or `None` if the key doesn't exist.
     /// Also removes expired elements and updates the time.
-    pub fn get<Q: ?Sized>(&mut self, key: &Q) -> Option<&Value>
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&Value>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
         self.get_mut(key).map(|v| &*v)
     }
 
-    /// Returns a reference to the value with the given `key`, if present and not expired, without
-    /// updating the timestamp.
-    pub fn peek<Q: ?Sized>(&self, key: &Q) -> Option<&Value>
+    /// Much like `get()`, but also returns a reference to the stored key.
+    pub fn get_key_value<Q>(&mut self, key: &Q) -> Option<(&Key, &Value)>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
-        self.do_peek(key, Instant::now())
+        let _ = self.get_mut(key)?;
+        self.map
+            .get_key_value(key)
+            .map(|(key, entry)| (key, &entry.value))
     }
 
-    /// Retrieves a mutable reference to the value stored under `key`, or `None` if the key doesn't
-    /// exist. Also removes expired elements and updates the time.
-    pub fn notify_get_mut<Q: ?Sized>(&mut self, key: &Q) -> (Option<&mut Value>, Vec<(Key, Value)>)
+    /// Much like `get()`, but also returns the generation of the value, which is higher than that
+    /// of every value written before, under any key.  Replacing the value gives it a new
+    /// generation while mutating it in place doesn't, so comparing generations tells whether a
+    /// value seen earlier is still the one stored under `key`.
+    pub fn get_with_generation<Q>(&mut self, key: &Q) -> Option<(&Value, u64)>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
-        let now = Instant::now();
-        self.do_notify_get_mut(key, now)
+        let _ = self.get_mut(key)?;
+        self.map
+            .get(key)
+            .map(|entry| (&entry.value, entry.generation))
     }
 
-    /// Retrieves a mutable reference to the value stored under `key`, or `None` if the key doesn't
-    /// exist.  Also removes expired elements and updates the time.
-    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut Value>
+    /// Retrieves a reference to the value stored under `key` and moves the entry to the top of the
+    /// cache, without updating its timestamp and so without extending its life.  Also removes
+    /// expired elements.
+    pub fn get_and_promote<Q>(&mut self, key: &Q) -> Option<&Value>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
-        self.notify_get_mut(key).0
+        let now = Instant::now();
+        self.discard_expired(now);
+        self.do_read(key, now, true, false).map(|v| &*v)
     }
 
-    /// Returns whether `key` exists in the cache or not.
-    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool
+    /// Retrieves a reference to the value stored under `key` and updates its timestamp, without
+    /// moving the entry to the top of the cache.  Also removes expired elements.
+    pub fn get_and_refresh<Q>(&mut self, key: &Q) -> Option<&Value>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
-        self.peek(key).is_some()
+        let now = Instant::now();
+        self.discard_expired(now);
+        self.do_read(key, now, false, true).map(|v| &*v)
     }
 
-    /// Returns the size of the cache, i.e. the number of cached non-expired key-value pairs.
-    pub fn len(&self) -> usize {
-        // FIXME: we assume most items are not expired => it is faster to count the expired ones.
-        //
-        // If this assumption is not valid, then directly iterating through all the
-        // map items and counting the not expired ones would be faster (no map lookups)
+    /// Much like calling `get()` for each of `keys` in turn, but removes expired elements only
+    /// once and moves all the hits to the top of the cache in a single pass.  The values are
+    /// returned in the order of `keys`, and the entry of the last key ends up as the most recently
+    /// used.
+    pub fn get_many<Q>(&mut self, keys: &[&Q]) -> Vec<Option<&Value>>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
         let now = Instant::now();
-        self.time_to_live.map_or(self.list.len(), |ttl| {
-            self.list
-                .iter()
-                .filter_map(|key| self.map.get(key))
-                .position(|&(_, t)| t + ttl >= now)
-                .map_or(0, |p| self.map.len() - p)
-        })
+        self.discard_expired(now);
+        // Under LRU, hits are promoted all at once afterwards.
+        let promote = self.eviction_policy != EvictionPolicy::Lru && self.promotion.on_read();
+        let hits: Vec<bool> = keys
+            .iter()
+            .map(|key| self.do_read(*key, now, promote, true).is_some())
+            .collect();
+        if self.eviction_policy == EvictionPolicy::Lru && self.promotion.on_read() {
+            self.flush_recency();
+            self.promote_all(
+                keys.iter()
+                    .zip(&hits)
+                    .filter(|(_, hit)| **hit)
+                    .map(|(key, _)| *key),
+            );
+        }
+        let map = &self.map;
+        keys.iter()
+            .zip(hits)
+            .map(move |(key, hit)| {
+                if hit {
+                    map.get(*key).map(|entry| &entry.value)
+                } else {
+                    None
+                }
+            })
+            .collect()
     }
 
-    /// Returns `true` if there are no non-expired entries in the cache.
-    pub fn is_empty(&self) -> bool {
-        let now = Instant::now();
-        self.time_to_live.map_or(self.list.is_empty(), |ttl| {
-            self.list
-                .back()
-                .and_then(|key| self.map.get(key))
-                .map_or(true, |&(_, t)| t + ttl < now)
-        })
+    /// Returns a reference to the value with the given `key`, if present and not expired, without
+    /// updating the timestamp.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.do_peek(key, Instant::now())
     }
 
-    /// Gets the given key's corresponding entry in the map for in-place manipulation.
-    pub fn entry(&mut self, key: Key) -> Entry<'_, Key, Value> {
-        // We need to do it the ugly way below due to this issue:
-        // https://github.com/rust-lang/rfcs/issues/811
-        // match self.get_mut(&key) {
-        //     Some(value) => Entry::Occupied(OccupiedEntry{value: value}),
-        //     None => Entry::Vacant(VacantEntry{key: key, cache: self}),
-        // }
-        let now = Instant::now();
-        if self.do_peek(&key, now).is_some() {
-            Entry::Occupied(OccupiedEntry {
-                value: self.do_notify_get_mut(&key, now).0.expect("key not found"),
-            })
-        } else {
-            Entry::Vacant(VacantEntry { key, cache: self })
-        }
+    /// Much like `peek()`, but as of `now` rather than the current time.
+    pub fn peek_at<Q>(&self, key: &Q, now: Instant) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.do_peek(key, now)
     }
 
-    /// Returns an iterator over all entries that updates the timestamps as values are
-    /// traversed. Also removes expired elements before creating the iterator.
-    /// Values are produced in the most recently used order.
-    ///
-    /// Also, evicts and returns expired entries.
-    pub fn notify_iter(&mut self) -> NotifyIter<'_, Key, Value> {
-        NotifyIter::new(&mut self.map, &mut self.list, self.time_to_live)
+    /// Much like `peek()`, but also returns a reference to the stored key.
+    pub fn peek_key_value<Q>(&self, key: &Q) -> Option<(&Key, &Value)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = Instant::now();
+        self.map
+            .get_key_value(key)
+            .filter(|(_, entry)| !entry.is_expired(self.expiry(), now))
+            .map(|(key, entry)| (key, &entry.value))
     }
 
-    /// Returns an iterator over all entries that updates the timestamps as values are
-    /// traversed. Also removes expired elements before creating the iterator.
-    /// Values are produced in the most recently used order.
-    pub fn iter(&mut self) -> Iter<'_, Key, Value> {
-        let _ = self.remove_expired(Instant::now());
-        Iter::new(&mut self.map, &mut self.list, self.time_to_live)
+    /// Returns the unexpired entry with the smallest key, without updating its timestamp.
+    pub fn first_key_value(&self) -> Option<(&Key, &Value)> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.map
+            .iter()
+            .find(|(_, entry)| !entry.is_expired(expiry, now))
+            .map(|(key, entry)| (key, &entry.value))
     }
 
-    /// Returns an iterator over all entries that does not modify the timestamps.
-    pub fn peek_iter(&self) -> PeekIter<'_, Key, Value> {
-        PeekIter::new(&self.map, &self.list, self.time_to_live)
+    /// Returns the unexpired entry with the largest key, without updating its timestamp.
+    pub fn last_key_value(&self) -> Option<(&Key, &Value)> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.map
+            .iter()
+            .rev()
+            .find(|(_, entry)| !entry.is_expired(expiry, now))
+            .map(|(key, entry)| (key, &entry.value))
     }
 
-    // Move `key` in the ordered list to the last
-    fn update_key<Q: ?Sized>(list: &mut VecDeque<Key>, key: &Q)
+    /// Returns the stored key, value, time of last access and remaining time to live of the entry
+    /// stored under `key` at once, if present and not expired, without updating the timestamp.
+    pub fn peek_entry<Q>(&self, key: &Q) -> Option<EntryView<'_, Key, Value>>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
-        if let Some(pos) = list.iter().position(|k| k.borrow() == key) {
-            let _ = list.remove(pos).map(|it| list.push_back(it));
+        let now = Instant::now();
+        let (key, entry) = self.map.get_key_value(key)?;
+        let expiry_time = entry.expiry_time(self.expiry());
+        if expiry_time.is_some_and(|expiry_time| expiry_time < now) {
+            return None;
         }
+        Some(EntryView {
+            key,
+            value: &entry.value,
+            last_accessed: entry.timestamp,
+            remaining_ttl: expiry_time
+                .map(|expiry_time| expiry_time.saturating_duration_since(now)),
+        })
     }
 
-    fn do_notify_get_mut<Q: ?Sized>(
-        &mut self,
-        key: &Q,
-        now: Instant,
-    ) -> (Option<&mut Value>, Vec<(Key, Value)>)
+    /// Returns a mutable reference to the value with the given `key`, if present and not expired,
+    /// without updating the timestamp or moving the entry to the top of the cache.  Doesn't remove
+    /// expired elements either.
+    pub fn peek_mut<Q>(&mut self, key: &Q) -> Option<&mut Value>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
     {
-        let expired = self.remove_expired(now);
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.map
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired(expiry, now))
+            .map(|entry| &mut entry.value)
+    }
 
-        let list = &mut self.list;
-        (
-            self.map.get_mut(key).map(|result| {
-                Self::update_key(list, key);
-                result.1 = now;
-                &mut result.0
-            }),
-            expired,
-        )
+    /// Retrieves a mutable reference to the value stored under `key`, or `None` if the key doesn't
+    /// exist. Also removes expired elements and updates the time.
+    pub fn notify_get_mut<Q>(&mut self, key: &Q) -> (Option<&mut Value>, Vec<(Key, Value)>)
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = Instant::now();
+        self.do_notify_get_mut(key, now)
     }
 
-    fn do_notify_insert(
-        &mut self,
-        key: Key,
-        value: Value,
-        now: Instant,
-    ) -> (Option<Value>, Vec<(Key, Value)>) {
-        let expired = self.remove_expired(now);
-        if self.map.contains_key(&key) {
-            Self::update_key(&mut self.list, &key);
-        } else {
-            self.remove_lru();
-            self.list.push_back(key.clone());
-        };
-
-        (
-            self.map.insert(key, (value, now)).map(|pair| pair.0),
-            expired,
-        )
+    /// Retrieves a mutable reference to the value stored under `key`, or `None` if the key doesn't
+    /// exist.  Also removes expired elements and updates the time.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = Instant::now();
+        self.discard_expired(now);
+        self.do_get_mut(key, now)
     }
 
-    fn do_peek<Q: ?Sized>(&self, key: &Q, now: Instant) -> Option<&Value>
+    /// Applies `f` to the value stored under `key` and returns its result, or `None` if the key
+    /// doesn't exist.  Like `get_mut()`, also removes expired elements and updates the time.
+    pub fn update<Q, R, F>(&mut self, key: &Q, f: F) -> Option<R>
     where
         Key: Borrow<Q>,
-        Q: Ord,
+        Q: Ord + ?Sized,
+        F: FnOnce(&mut Value) -> R,
     {
-        self.map
-            .get(key)
-            .into_iter()
-            .find(|&(_, t)| self.time_to_live.map_or(true, |ttl| *t + ttl >= now))
-            .map(|&(ref value, _)| value)
+        self.get_mut(key).map(f)
     }
 
-    /// If expiry timeout is set, removes expired items from the cache and returns them.
-    fn remove_expired(&mut self, now: Instant) -> Vec<(Key, Value)> {
-        let (map, list) = (&mut self.map, &mut self.list);
-
-        if let Some(ttl) = self.time_to_live {
-            let mut expired_values = Vec::new();
-            for key in list.iter() {
-                if map[key].1 + ttl >= now {
-                    break;
-                }
-                if let Some(entry) = map.remove(key) {
-                    expired_values.push(entry.0);
-                }
+    /// Applies `update` to the value stored under `key` if it exists, or inserts the value returned
+    /// by `insert` otherwise, e.g. to accumulate into the cache.  Returns whether a value was
+    /// inserted.
+    ///
+    /// Shorthand for matching on `entry(key)`, so the value is always inserted regardless of the
+    /// admission policy and maximum entry weight.
+    pub fn upsert<I, U>(&mut self, key: Key, insert: I, update: U) -> bool
+    where
+        I: FnOnce() -> Value,
+        U: FnOnce(&mut Value),
+    {
+        match self.entry(key) {
+            Entry::Occupied(entry) => {
+                update(entry.into_mut());
+                false
+            }
+            Entry::Vacant(entry) => {
+                let _ = entry.insert(insert());
+                true
             }
-            // remove keys as well
-            return list
-                .drain(..expired_values.len())
-                .zip(expired_values)
-                .collect();
-        } else if map.is_empty() {
-            list.clear();
         }
+    }
 
-        Vec::new()
+    /// Much like `get()`, but as of `now` rather than the current time, which the entry's
+    /// timestamp is set to.
+    pub fn get_at<Q>(&mut self, key: &Q, now: Instant) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.discard_expired(now);
+        self.do_get_mut(key, now).map(|value| &*value)
     }
 
-    /// Removes least recently used items to make space for new ones.
-    fn remove_lru(&mut self) {
-        if self.map.len() >= self.capacity {
-            for key in self.list.drain(..=self.map.len() - self.capacity) {
-                assert!(self.map.remove(&key).is_some());
-            }
-        }
+    /// Returns the time at which the entry stored under `key` expires unless accessed again.
+    ///
+    /// Returns `None` if the key doesn't exist, has expired or doesn't expire at all.
+    /// Does not update the timestamp.
+    pub fn expiry_time<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.do_expiry_time(key, Instant::now())
     }
-}
 
-impl<Key, Value> Clone for LruCache<Key, Value>
-where
-    Key: Clone,
-    Value: Clone,
-{
-    fn clone(&self) -> LruCache<Key, Value> {
-        LruCache {
-            map: self.map.clone(),
-            list: self.list.clone(),
-            capacity: self.capacity,
-            time_to_live: self.time_to_live,
+    /// Returns the bookkeeping data of the entry stored under `key`, e.g. to find out why it was
+    /// kept or is about to be evicted, or `None` if the key doesn't exist or has expired.
+    ///
+    /// Does not update the timestamp.  Finding the recency rank takes time proportional to the
+    /// number of entries more recently used than this one.
+    pub fn metadata<Q>(&self, key: &Q) -> Option<EntryMetadata>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        let entry = self.map.get(key)?;
+        if entry.is_expired(expiry, now) {
+            return None;
         }
+        let recency_rank = self
+            .list
+            .iter()
+            .rev()
+            .take_while(|listed| <Key as Borrow<Q>>::borrow(*listed) != key)
+            .filter(|listed| {
+                self.map
+                    .get::<Key>(*listed)
+                    .is_some_and(|listed| !listed.is_expired(expiry, now))
+            })
+            .count();
+        Some(EntryMetadata {
+            last_accessed: entry.timestamp,
+            inserted: entry.inserted,
+            written: entry.written,
+            access_count: entry.accesses,
+            generation: entry.generation,
+            recency_rank,
+        })
     }
-}
 
-impl<'a, Key: Ord + Clone, Value> VacantEntry<'a, Key, Value> {
-    /// Inserts a value
-    pub fn insert(self, value: Value) -> &'a mut Value {
+    /// Returns how long the entry stored under `key` remains in the cache unless accessed again.
+    ///
+    /// Returns `None` if the key doesn't exist, has expired or doesn't expire at all.
+    /// Does not update the timestamp.
+    pub fn remaining_ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
         let now = Instant::now();
-        let _ = self.cache.do_notify_insert(self.key.clone(), value, now);
-        self.cache
-            .do_notify_get_mut(&self.key, now)
-            .0
-            .expect("key not found")
+        self.do_expiry_time(key, now)
+            .map(|expiry_time| expiry_time.saturating_duration_since(now))
     }
-}
 
-impl<'a, Value> OccupiedEntry<'a, Value> {
-    /// Converts the entry into a mutable reference to its value.
-    pub fn into_mut(self) -> &'a mut Value {
-        self.value
+    /// Sets the time to live of the entry stored under `key`, overriding the cache-wide one until
+    /// the entry is replaced by an insert.
+    ///
+    /// As with the cache-wide time to live, it is measured according to the expiry policy.  Does
+    /// not update the timestamp.  Returns whether `key` exists in the cache or not.
+    pub fn set_entry_ttl<Q>(&mut self, key: &Q, time_to_live: Duration) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.update_entry_ttl(key, Instant::now(), |_| Some(time_to_live))
     }
-}
 
-impl<'a, Key: Ord + Clone, Value> Entry<'a, Key, Value> {
-    /// Ensures a value is in the entry by inserting the default if empty, and returns
-    /// a mutable reference to the value in the entry.
-    pub fn or_insert(self, default: Value) -> &'a mut Value {
-        match self {
-            Entry::Occupied(entry) => entry.into_mut(),
-            Entry::Vacant(entry) => entry.insert(default),
+    /// Extends the time to live of the entry stored under `key` by `extra`, on top of either the
+    /// entry's own time to live or the cache-wide one.  Has no effect on entries which don't
+    /// expire.
+    ///
+    /// Does not update the timestamp.  Returns whether `key` exists in the cache or not.
+    pub fn extend_entry_ttl<Q>(&mut self, key: &Q, extra: Duration) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.update_entry_ttl(key, Instant::now(), |ttl| {
+            ttl.map(|ttl| ttl.checked_add(extra).unwrap_or(Duration::MAX))
+        })
+    }
+
+    /// Pins the entry stored under `key`, so that it is neither evicted on expiry nor to make room
+    /// for new entries, until it is unpinned or removed.  Pinned entries still count towards the
+    /// capacity, but if the cache is full of them, inserts exceed the capacity rather than evicting
+    /// any of them.
+    ///
+    /// Replacing the value keeps the entry pinned.  Does not update the timestamp.  Returns whether
+    /// `key` exists in the cache or not.
+    pub fn pin<Q>(&mut self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.set_pinned(key, true)
+    }
+
+    /// Unpins the entry stored under `key`, making it subject to the usual eviction rules again.
+    /// Its time to live is measured from its last access as usual, so it may expire immediately.
+    ///
+    /// Does not update the timestamp.  Returns whether `key` exists in the cache or not.
+    pub fn unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.set_pinned(key, false)
+    }
+
+    /// Returns whether the entry stored under `key` exists and is pinned.
+    pub fn is_pinned<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get(key).is_some_and(|entry| entry.pinned)
+    }
+
+    /// Moves the entry stored under `key` to the top of the cache and updates its timestamp, without
+    /// returning the value.  Also removes expired elements.
+    ///
+    /// Returns whether `key` exists in the cache or not.
+    pub fn touch<Q>(&mut self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_mut(key).is_some()
+    }
+
+    /// Returns whether `key` exists in the cache or not.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Returns the size of the cache, i.e. the number of cached non-expired key-value pairs.
+    pub fn len(&self) -> usize {
+        self.map.len() - self.expiries.expired(Instant::now()).count()
+    }
+
+    /// Returns `true` if there are no non-expired entries in the cache.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the cache holds as many unexpired entries as its capacity allows, in which
+    /// case inserting a new key evicts an entry, or is turned down by the admission policy.
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    /// Returns the number of new keys which can be inserted before entries start being evicted.
+    ///
+    /// Caches constructed without a capacity limit report almost `usize::MAX`.
+    pub fn remaining_capacity(&self) -> usize {
+        self.capacity.saturating_sub(self.len())
+    }
+
+    /// Returns whether `key` is stored in the cache, even if it has expired and is only waiting to
+    /// be removed.
+    pub fn contains_key_ignore_expiry<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Returns the number of entries stored in the cache, including the expired ones which haven't
+    /// been removed yet and still take up memory.
+    pub fn len_with_expired(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns the number of expired entries which haven't been removed yet.
+    pub fn expired_len(&self) -> usize {
+        self.expiries.expired(Instant::now()).count()
+    }
+
+    /// Gets the given key's corresponding entry in the map for in-place manipulation.
+    pub fn entry(&mut self, key: Key) -> Entry<'_, Key, Value> {
+        // We need to do it the ugly way below due to this issue:
+        // https://github.com/rust-lang/rfcs/issues/811
+        // match self.get_mut(&key) {
+        //     Some(value) => Entry::Occupied(OccupiedEntry{value: value}),
+        //     None => Entry::Vacant(VacantEntry{key: key, cache: self}),
+        // }
+        let now = Instant::now();
+        if self.is_hit(&key, now) {
+            self.discard_expired(now);
+            Entry::Occupied(OccupiedEntry {
+                value: self.do_get_mut_decided(&key, now).expect("key not found"),
+            })
+        } else {
+            Entry::Vacant(VacantEntry { key, cache: self })
         }
     }
 
-    /// Ensures a value is in the entry by inserting the result of the default function if empty,
-    /// and returns a mutable reference to the value in the entry.
-    pub fn or_insert_with<F: FnOnce() -> Value>(self, default: F) -> &'a mut Value {
-        match self {
-            Entry::Occupied(entry) => entry.into_mut(),
-            Entry::Vacant(entry) => entry.insert(default()),
+    /// Returns a mutable reference to the value stored under `key`, inserting `value` first if the
+    /// key doesn't exist.  Shorthand for `entry(key).or_insert(value)`; use
+    /// `entry(key).or_insert_with()` if the value is costly to construct.
+    pub fn get_or_insert(&mut self, key: Key, value: Value) -> &mut Value {
+        self.entry(key).or_insert(value)
+    }
+
+    /// Much like `entry(key).or_insert_with(default)`, except the value computed on a miss is given
+    /// its own time to live, as with `set_entry_ttl()`.  The time to live of an existing entry is
+    /// left unchanged.
+    pub fn get_or_insert_with_ttl<F>(
+        &mut self,
+        key: Key,
+        time_to_live: Duration,
+        default: F,
+    ) -> &mut Value
+    where
+        F: FnOnce() -> Value,
+    {
+        let now = Instant::now();
+        if self.is_hit(&key, now) {
+            self.discard_expired(now);
+        } else {
+            let outcome = self.do_notify_insert(key.clone(), default(), now, false);
+            self.forward_expired(outcome.expired);
+            let _ = self.update_entry_ttl(&key, now, |_| Some(time_to_live));
         }
+        self.do_get_mut_decided(&key, now).expect("key not found")
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use rand::distributions::{Distribution, Standard};
-    use rand::thread_rng;
-    use std::time::Duration;
+    /// Inserts a key-value pair into the cache unless the key already exists, and returns a mutable
+    /// reference to the inserted value.
+    ///
+    /// If the key exists, nothing is inserted and the error holds the key and value along with the
+    /// existing entry, which is accessed as with `entry()`.  Like `VacantEntry::insert()`, the
+    /// value is always inserted regardless of the admission policy and maximum entry weight.
+    pub fn try_insert(
+        &mut self,
+        key: Key,
+        value: Value,
+    ) -> Result<&mut Value, OccupiedError<'_, Key, Value>> {
+        let now = Instant::now();
+        if self.is_hit(&key, now) {
+            self.discard_expired(now);
+            let entry = OccupiedEntry {
+                value: self.do_get_mut_decided(&key, now).expect("key not found"),
+            };
+            Err(OccupiedError { key, entry, value })
+        } else {
+            Ok(VacantEntry { key, cache: self }.insert(value))
+        }
+    }
 
-    #[cfg(feature = "sn_fake_clock")]
-    fn sleep(time: u64) {
-        use sn_fake_clock::FakeClock;
-        FakeClock::advance_time(time);
+    /// Much like `insert()`, but reserves the room for the new entry in the recency list first and
+    /// hands the key-value pair back along with the allocation failure if that fails, rather than
+    /// aborting.
+    ///
+    /// The nodes of the underlying `BTreeMap` can't be allocated fallibly, so this only guards
+    /// against the larger allocations of the recency list growing.
+    pub fn try_insert_within_memory(
+        &mut self,
+        key: Key,
+        value: Value,
+    ) -> Result<Option<Value>, ReserveError<Key, Value>> {
+        if let Err(error) = self.list.try_reserve(1) {
+            return Err(ReserveError { key, value, error });
+        }
+        Ok(self.insert(key, value))
+    }
+
+    /// Returns an iterator over all entries that updates the timestamps as values are
+    /// traversed. Also removes expired elements before creating the iterator.
+    /// Values are produced in the most recently used order.
+    ///
+    /// Also, evicts and returns expired entries.
+    pub fn notify_iter(&mut self) -> NotifyIter<'_, Key, Value> {
+        let expiry = self.expiry();
+        NotifyIter::new(
+            &mut self.map,
+            &mut self.list,
+            &mut self.expiries,
+            &mut self.segments,
+            &mut self.events,
+            expiry,
+        )
+    }
+
+    /// Returns an iterator over all entries that updates the timestamps as values are
+    /// traversed. Also removes expired elements before creating the iterator.
+    /// Values are produced in the most recently used order.
+    pub fn iter(&mut self) -> Iter<'_, Key, Value> {
+        self.discard_expired(Instant::now());
+        self.flush_recency();
+        let expiry = self.expiry();
+        Iter::new(&mut self.map, &mut self.list, &mut self.expiries, expiry)
+    }
+
+    /// Returns an iterator over all entries that does not modify the timestamps.
+    pub fn peek_iter(&self) -> PeekIter<'_, Key, Value> {
+        PeekIter::new(&self.map, &self.list, &self.expiries, self.expiry())
+    }
+
+    /// Much like `peek_iter()`, but starts at the entry stored under `key`, which comes first,
+    /// followed by the less recently used ones.  Yields nothing if the key doesn't exist.
+    ///
+    /// Resuming from the last key seen allows paging through a large cache over several calls,
+    /// although entries used in between move out of the page order.
+    pub fn peek_iter_from<Q>(&self, key: &Q) -> PeekIter<'_, Key, Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let end = self
+            .list
+            .iter()
+            .rposition(|listed| listed.borrow() == key)
+            .map_or(0, |index| index + 1);
+        self.peek_iter().truncated(end)
+    }
+
+    /// Much like `peek_iter()`, except each entry comes with the time it was last accessed at and
+    /// the time it has left to live, or `None` if it doesn't expire, e.g. to export the state of
+    /// the cache.
+    pub fn iter_with_meta(&self) -> MetaIter<'_, Key, Value> {
+        MetaIter::new(self.peek_iter())
+    }
+
+    /// Returns an iterator over the unexpired entries which haven't been accessed for longer than
+    /// `min_age`, least recently used first, e.g. to refresh them before they expire.  Does not
+    /// modify the timestamps.
+    pub fn iter_stale(&self, min_age: Duration) -> StaleIter<'_, Key, Value> {
+        StaleIter::new(self.iter_with_meta(), min_age)
+    }
+
+    /// Much like `iter()`, except values are produced in the least recently used order, so the
+    /// entries next in line for eviction come first.  As each traversed entry is moved to the top
+    /// of the cache, a complete traversal leaves the recency order unchanged.
+    pub fn lru_iter(&mut self) -> Rev<Iter<'_, Key, Value>> {
+        self.iter().rev()
+    }
+
+    /// Much like `peek_iter()`, except values are produced in the least recently used order, so
+    /// the entries next in line for eviction come first.
+    pub fn peek_lru_iter(&self) -> Rev<PeekIter<'_, Key, Value>> {
+        self.peek_iter().rev()
     }
 
-    #[cfg(not(feature = "sn_fake_clock"))]
-    fn sleep(time: u64) {
-        use std::thread;
-        thread::sleep(Duration::from_millis(time));
-    }
+    /// Returns an iterator which removes and yields the entries for which `pred` returns `true`.
+    ///
+    /// Entries are visited in the least recently used order, and are removed lazily as the
+    /// iterator is consumed: if it is dropped early, the remaining entries are kept in the cache.
+    /// Expired entries are removed before iterating and are not passed to `pred`.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, Key, Value, F>
+    where
+        F: FnMut(&Key, &mut Value) -> bool,
+    {
+        self.discard_all_expired(Instant::now());
+        ExtractIf::new(
+            &mut self.map,
+            &mut self.list,
+            &mut self.expiries,
+            &mut self.segments,
+            &mut self.events,
+            pred,
+        )
+    }
+
+    /// Removes the entries for which `pred` returns `true`, and returns them least recently used
+    /// first, e.g. to notify peers of what was invalidated.  Also removes expired elements, which
+    /// aren't passed to `pred` nor returned.
+    pub fn remove_where<F>(&mut self, mut pred: F) -> Vec<(Key, Value)>
+    where
+        F: FnMut(&Key, &Value) -> bool,
+    {
+        self.extract_if(|key, value| pred(key, value)).collect()
+    }
+
+    /// Splits the cache in two at the given key.  Returns a new cache holding all the entries with
+    /// keys greater than or equal to `key`, which are removed from this one.
+    ///
+    /// The new cache has the same capacity, time to live and expiry policy as this one.  The moved
+    /// entries keep their timestamps and relative recency order.  Expired entries are removed
+    /// before splitting.
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.discard_all_expired(Instant::now());
+        self.flush_recency();
+        let map = self.map.split_off(key);
+        for (key, entry) in &map {
+            self.events.emit(CacheEvent::Removed(key, &entry.value));
+        }
+        let (list, kept) = self
+            .list
+            .drain(..)
+            .partition(|k| map.contains_key::<Key>(k));
+        self.list = kept;
+
+        let mut other = Self::new(list, self.capacity, self.time_to_live);
+        other.map = map;
+        other.expiry_policy = self.expiry_policy;
+        other.generation = self.generation;
+        let expiry = self.expiry();
+        self.expiries.rebuild(&mut self.map, expiry);
+        other.expiries.rebuild(&mut other.map, expiry);
+        self.segments.rebuild(&self.map);
+        other.segments.rebuild(&other.map);
+        other
+    }
+
+    /// Splits the cache in two according to `pred`.  Returns a new cache holding all the entries
+    /// for which `pred` returns `true`, which are removed from this one.
+    ///
+    /// Like `split_off()`, the new cache has the same capacity, time to live and expiry policy as
+    /// this one, and the moved entries keep their timestamps and relative recency order.  Expired
+    /// entries are removed before splitting.
+    pub fn split_by<F>(&mut self, mut pred: F) -> Self
+    where
+        F: FnMut(&Key, &Value) -> bool,
+    {
+        self.discard_all_expired(Instant::now());
+        self.flush_recency();
+        let (list, kept): (VecDeque<Key>, VecDeque<Key>) = {
+            let map = &self.map;
+            self.list
+                .drain(..)
+                .partition(|key| pred(key, &map[key].value))
+        };
+        self.list = kept;
+
+        let mut map = BTreeMap::new();
+        for key in &list {
+            if let Some((key, entry)) = self.map.remove_entry(key) {
+                self.events.emit(CacheEvent::Removed(&key, &entry.value));
+                let _ = map.insert(key, entry);
+            }
+        }
+        let mut other = Self::new(list, self.capacity, self.time_to_live);
+        other.map = map;
+        other.expiry_policy = self.expiry_policy;
+        other.generation = self.generation;
+        let expiry = self.expiry();
+        self.expiries.rebuild(&mut self.map, expiry);
+        other.expiries.rebuild(&mut other.map, expiry);
+        self.segments.rebuild(&self.map);
+        other.segments.rebuild(&other.map);
+        other
+    }
+
+    /// Moves all the entries of `other` into this cache, leaving `other` empty.
+    ///
+    /// The entries keep their timestamps and are ordered by how recently they were used.  If a key
+    /// is present in both caches, the more recently used entry is kept.  Returns the superseded
+    /// duplicates, followed by the least recently used entries evicted to stay within capacity.
+    /// Expired entries are removed from both caches before merging.
+    pub fn append(&mut self, other: &mut Self) -> Vec<(Key, Value)> {
+        let now = Instant::now();
+        self.discard_all_expired(now);
+        other.discard_all_expired(now);
+        self.flush_recency();
+        other.flush_recency();
+        let next_expiry = self.next_expiry();
+
+        let mut ours = std::mem::take(&mut self.list);
+        let mut theirs = std::mem::take(&mut other.list);
+        let mut dropped = Vec::new();
+        // Both lists are merged least recently used first, so of any two duplicates the older one
+        // is reached first and dropped.
+        loop {
+            let take_ours = match (ours.front(), theirs.front()) {
+                (Some(a), Some(b)) => self.map[a].timestamp <= other.map[b].timestamp,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_ours {
+                if let Some(key) = ours.pop_front() {
+                    if other.map.contains_key(&key) {
+                        if let Some(entry) = self.map.remove(&key) {
+                            self.events.emit(CacheEvent::Removed(&key, &entry.value));
+                            dropped.push((key, entry.value));
+                        }
+                    } else {
+                        self.list.push_back(key);
+                    }
+                }
+            } else if let Some(key) = theirs.pop_front() {
+                if let Some(mut entry) = other.map.remove(&key) {
+                    if self.map.contains_key(&key) {
+                        dropped.push((key, entry.value));
+                    } else {
+                        entry.generation = self.next_generation();
+                        self.events.emit(CacheEvent::Inserted(&key, &entry.value));
+                        self.list.push_back(key.clone());
+                        let _ = self.map.insert(key, entry);
+                    }
+                }
+            }
+        }
+
+        other.expiries.clear();
+        other.segments = SegmentCounts::default();
+        let expiry = self.expiry();
+        self.expiries.rebuild(&mut self.map, expiry);
+        self.segments.rebuild(&self.map);
+        dropped.extend(self.evict_lru(self.map.len().saturating_sub(self.capacity), now));
+        self.reschedule_expiry(next_expiry);
+        dropped
+    }
+
+    /// Returns an iterator over the unexpired entries whose keys fall within `range`, in ascending
+    /// key order.  Does not modify the timestamps.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, Key, Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        Range::new(self.map.range(range), self.expiry())
+    }
+
+    /// Returns a mutable iterator over the unexpired entries whose keys fall within `range`, in
+    /// ascending key order.  Does not modify the timestamps.
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, Key, Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let expiry = self.expiry();
+        RangeMut::new(self.map.range_mut(range), expiry)
+    }
+
+    /// Returns the earliest time at which an entry may get evicted on expiry, or `None` if none of
+    /// the entries expire.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.expiries.next_expiry()
+    }
+
+    /// Returns an iterator over the unexpired entries with less than `within` left to live, along
+    /// with the time they have left, the soonest to expire first, e.g. to renew them in priority
+    /// order.  Does not modify the timestamps.
+    pub fn expiring_within(&self, within: Duration) -> ExpiringIter<'_, Key, Value> {
+        ExpiringIter::new(&self.map, &self.expiries, within)
+    }
+
+    /// Removes and returns all the entries expired as of `now`, least recently used first,
+    /// regardless of the expiry budget.
+    pub fn remove_expired_at(&mut self, now: Instant) -> Vec<(Key, Value)> {
+        self.remove_all_expired(now)
+    }
+
+    /// Removes and returns the entry which expired first, or `None` if no entry has expired.
+    ///
+    /// Unlike the `notify_*` methods, which remove all the expired entries at once, this allows
+    /// the work of draining a large number of expired entries to be spread over time.
+    pub fn pop_expired(&mut self) -> Option<(Key, Value)> {
+        let now = Instant::now();
+        let key = self.expiries.expired(now).next()?.clone();
+        let (key, entry) = self.detach(&key)?;
+        self.events.emit(CacheEvent::Expired(&key, &entry.value));
+        self.stats.expiration(1, now);
+        Some((key, entry.value))
+    }
+
+    /// Returns a stream of the entries which get evicted from the cache because their time to
+    /// live has passed.
+    ///
+    /// The stream has no timer of its own: expired entries are only detected by the cache
+    /// activity, i.e. any call which removes expired entries, so an idle cache yields nothing.
+    /// To have entries yielded as soon as they expire, share the cache with the task spawned by
+    /// `spawn_expiry_task()`, with the `background_expiry` feature enabled.  Entries which are
+    /// handed back to the caller, e.g. by `notify_insert()`, are not duplicated into the stream.
+    /// Only a single stream is fed at a time, so calling this again ends the previously returned
+    /// stream.  The stream ends when the cache is dropped.
+    #[cfg(feature = "async")]
+    pub fn expired_stream(&mut self) -> ExpiredStream<Key, Value> {
+        let (sender, stream) = ExpiredStream::channel();
+        self.expired_sender = Some(sender);
+        stream
+    }
+
+    fn new(list: VecDeque<Key>, capacity: usize, time_to_live: Option<Duration>) -> Self {
+        LruCache {
+            map: BTreeMap::new(),
+            list,
+            expiries: ExpiryIndex::new(),
+            segments: SegmentCounts::default(),
+            ghosts: Ghosts::new(),
+            capacity,
+            time_to_live,
+            expiry_policy: ExpiryPolicy::AfterAccess,
+            expiry_budget: None,
+            expiry_paused_at: None,
+            eviction_policy: EvictionPolicy::Lru,
+            promotion: Promotion::default(),
+            rng: Rng::new(),
+            inflation: 0.0,
+            admission: None,
+            admission_window: false,
+            early_expiry: None,
+            ttl_jitter: None,
+            miss_time_to_live: None,
+            expiry_hook: None,
+            max_entry_weight: None,
+            weigher: None,
+            promotion_buffer: None,
+            pending_promotions: Vec::new(),
+            generation: 0,
+            events: EventHook::new(),
+            stats: StatsRecorder::new(),
+            #[cfg(feature = "async")]
+            expired_sender: None,
+            #[cfg(feature = "tokio")]
+            expiry_notify: None,
+        }
+    }
+
+    /// Like `BTreeMap::get_mut()`, but also returns the stored key.
+    fn entry_mut<'a, Q>(
+        map: &'a mut BTreeMap<Key, CacheEntry<Value>>,
+        key: &Q,
+    ) -> Option<(&'a Key, &'a mut CacheEntry<Value>)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        map.range_mut::<Q, _>((Bound::Included(key), Bound::Included(key)))
+            .next()
+    }
+
+    /// Removes the entry stored under `key`, without telling the subscriber.
+    fn detach<Q>(&mut self, key: &Q) -> Option<(Key, CacheEntry<Value>)>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (key, entry) = self.map.remove_entry(key)?;
+        self.expiries.remove(&key, &entry);
+        self.segments.remove(&entry);
+        let _ = self
+            .list
+            .iter()
+            .position(|l| *l == key)
+            .map(|p| self.list.remove(p));
+        Some((key, entry))
+    }
+
+    fn do_notify_get_mut<Q>(
+        &mut self,
+        key: &Q,
+        now: Instant,
+    ) -> (Option<&mut Value>, Vec<(Key, Value)>)
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let expired = self.remove_expired(now);
+        (self.do_get_mut(key, now), expired)
+    }
+
+    fn do_get_mut<Q>(&mut self, key: &Q, now: Instant) -> Option<&mut Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.do_read(key, now, self.promotion.on_read(), true)
+    }
+
+    /// Much like `do_get_mut()`, but never misses the entry early, for the callers which already
+    /// decided it is there, e.g. with `is_hit()`, or have just inserted it.
+    fn do_get_mut_decided<Q>(&mut self, key: &Q, now: Instant) -> Option<&mut Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.do_lookup(key, now, self.promotion.on_read(), true, false)
+    }
+
+    /// Returns whether a read of `key` at `now` would hit, rolling for early expiry once, without
+    /// counting as an access.
+    fn is_hit<Q>(&mut self, key: &Q, now: Instant) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let deadline = match self.map.get(key) {
+            Some(entry) if !entry.is_expired(self.expiry(), now) => entry.deadline,
+            _ => return false,
+        };
+        match (&mut self.early_expiry, deadline) {
+            (Some(early), Some(deadline)) => !early.expires_early(deadline, now),
+            _ => true,
+        }
+    }
+
+    /// Looks up `key`, optionally moving it to the top of the cache and updating its timestamp.
+    fn do_read<Q>(
+        &mut self,
+        key: &Q,
+        now: Instant,
+        promote: bool,
+        refresh: bool,
+    ) -> Option<&mut Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.do_lookup(key, now, promote, refresh, true)
+    }
+
+    /// Records a lookup at `now` which missed.
+    fn record_miss(&mut self, now: Instant) {
+        instrument::lookup(false);
+        self.stats.lookup(false, now);
+        self.events.miss();
+    }
+
+    /// Implements `do_read()`, only rolling for early expiry if `roll_early` is set.
+    fn do_lookup<Q>(
+        &mut self,
+        key: &Q,
+        now: Instant,
+        promote: bool,
+        refresh: bool,
+        roll_early: bool,
+    ) -> Option<&mut Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let expiry = self.expiry();
+        let defer = promote
+            && self.promotion_buffer.is_some()
+            && self.eviction_policy == EvictionPolicy::Lru;
+        {
+            let (stored_key, entry) = match Self::entry_mut(&mut self.map, key) {
+                Some(found) => found,
+                None => {
+                    self.record_miss(now);
+                    return None;
+                }
+            };
+            if entry.is_expired(expiry, now) {
+                self.record_miss(now);
+                return None;
+            }
+            if let (Some(early), Some(deadline), true) =
+                (&mut self.early_expiry, entry.deadline, roll_early)
+            {
+                if early.expires_early(deadline, now) {
+                    self.record_miss(now);
+                    return None;
+                }
+            }
+            entry.accesses += 1;
+            let read_ttl = self
+                .expiry_hook
+                .as_ref()
+                .and_then(|hook| hook.expire_after_read(stored_key, &entry.value));
+            if let Some(time_to_live) = read_ttl {
+                entry.time_to_live = Some(time_to_live);
+            }
+            if refresh {
+                entry.timestamp = now;
+            }
+            if refresh || read_ttl.is_some() {
+                self.expiries.update(stored_key, entry, expiry);
+            }
+            if let Some(sketch) = &mut self.admission {
+                sketch.increment(stored_key);
+            }
+            self.events.hit(stored_key);
+            if defer {
+                self.pending_promotions.push(stored_key.clone());
+            }
+        }
+        instrument::lookup(true);
+        self.stats.lookup(true, now);
+        if defer {
+            if self
+                .promotion_buffer
+                .is_some_and(|size| self.pending_promotions.len() >= size)
+            {
+                self.flush_recency();
+            }
+        } else if promote {
+            self.promote(key);
+        }
+        self.map.get_mut(key).map(|entry| &mut entry.value)
+    }
+
+    /// Records a hit on the entry stored under `key` as the eviction policy requires, typically by
+    /// moving it to the top of the cache.
+    fn promote<Q>(&mut self, key: &Q)
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.eviction_policy {
+            EvictionPolicy::Lru | EvictionPolicy::Mru | EvictionPolicy::Random => {
+                return recency::touch(&mut self.list, key)
+            }
+            EvictionPolicy::Clock => {
+                if let Some(entry) = self.map.get_mut(key) {
+                    entry.referenced = true;
+                }
+                return;
+            }
+            EvictionPolicy::GreedyDualSizeFrequency => {
+                recency::touch(&mut self.list, key);
+                if let Some(entry) = self.map.get_mut(key) {
+                    entry.priority = self.inflation + entry.worth();
+                }
+                return;
+            }
+            EvictionPolicy::TwoQueue { .. } => {
+                // Entries seen once stay in first-in first-out order.
+                if self
+                    .map
+                    .get(key)
+                    .is_some_and(|entry| entry.segment == Segment::Frequent)
+                {
+                    recency::touch(&mut self.list, key);
+                }
+                return;
+            }
+            EvictionPolicy::Segmented { .. } | EvictionPolicy::Adaptive => {
+                recency::touch(&mut self.list, key)
+            }
+        }
+        match self.map.get_mut(key) {
+            Some(entry) if entry.segment == Segment::Recent => {
+                entry.segment = Segment::Frequent;
+                self.segments.frequent += 1;
+            }
+            _ => return,
+        }
+        if let EvictionPolicy::Segmented { protected_percent } = self.eviction_policy {
+            if self.segments.frequent > policy::share(self.capacity, protected_percent) {
+                self.demote_lru_frequent();
+            }
+        }
+    }
+
+    /// Moves the entries stored under `keys` to the top of the cache in one pass over the list, the
+    /// last key ending up as the most recently used.
+    fn promote_all<'a, Q, I>(&mut self, keys: I)
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized + 'a,
+        I: Iterator<Item = &'a Q>,
+    {
+        let mut order = BTreeMap::new();
+        for (position, key) in keys.enumerate() {
+            if let Some((stored_key, _)) = self.map.get_key_value(key) {
+                let _ = order.insert(stored_key.clone(), position);
+            }
+        }
+        if order.is_empty() {
+            return;
+        }
+        self.list.retain(|key| !order.contains_key::<Key>(key));
+        let mut promoted: Vec<(usize, Key)> = order
+            .into_iter()
+            .map(|(key, position)| (position, key))
+            .collect();
+        promoted.sort_unstable_by_key(|(position, _)| *position);
+        self.list.extend(promoted.into_iter().map(|(_, key)| key));
+    }
+
+    /// Moves the least recently used frequent entry back to the recent segment, as its most
+    /// recently used entry.
+    fn demote_lru_frequent(&mut self) {
+        let map = &mut self.map;
+        let index = match self
+            .list
+            .iter()
+            .position(|key| map[key].segment == Segment::Frequent)
+        {
+            Some(index) => index,
+            None => return,
+        };
+        if let Some(key) = self.list.remove(index) {
+            if let Some(entry) = map.get_mut(&key) {
+                entry.segment = Segment::Recent;
+                self.segments.frequent -= 1;
+            }
+            self.list.push_back(key);
+        }
+    }
+
+    /// Gives the referenced entries at the front of the list a second chance, by clearing their
+    /// mark and moving them to the back, until an unreferenced unpinned one is at the front.
+    fn advance_clock_hand(&mut self) {
+        for _ in 0..self.list.len() * 2 {
+            let map = &mut self.map;
+            let entry = match self.list.front().and_then(|key| map.get_mut(key)) {
+                Some(entry) if entry.referenced || entry.pinned => entry,
+                _ => return,
+            };
+            entry.referenced = false;
+            self.list.rotate_left(1);
+        }
+    }
+
+    /// Returns the index in `list` of the entry to evict next, or `None` if all are pinned.
+    fn victim_index(&mut self) -> Option<usize> {
+        let recent = self.map.len() - self.segments.frequent;
+        let preferred = match self.eviction_policy {
+            EvictionPolicy::Mru => return self.list.iter().rposition(|key| !self.map[key].pinned),
+            EvictionPolicy::Random => return self.random_victim_index(),
+            EvictionPolicy::GreedyDualSizeFrequency => {
+                let map = &self.map;
+                return self
+                    .list
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, key)| !map[*key].pinned)
+                    .fold(None, |lowest: Option<(usize, f64)>, (index, key)| {
+                        let priority = map[key].priority;
+                        match lowest {
+                            Some((_, lowest_priority)) if lowest_priority <= priority => lowest,
+                            _ => Some((index, priority)),
+                        }
+                    })
+                    .map(|(index, _)| index);
+            }
+            EvictionPolicy::Lru | EvictionPolicy::Clock => None,
+            EvictionPolicy::Segmented { .. } => Some(Segment::Recent),
+            EvictionPolicy::Adaptive if recent > self.ghosts.target => Some(Segment::Recent),
+            EvictionPolicy::TwoQueue { recent_percent }
+                if recent > policy::share(self.capacity, recent_percent) =>
+            {
+                Some(Segment::Recent)
+            }
+            EvictionPolicy::Adaptive | EvictionPolicy::TwoQueue { .. } => Some(Segment::Frequent),
+        };
+        let unpinned = |key: &Key| !self.map[key].pinned;
+        preferred
+            .and_then(|segment| {
+                self.list
+                    .iter()
+                    .position(|key| unpinned(key) && self.map[key].segment == segment)
+            })
+            .or_else(|| self.list.iter().position(unpinned))
+    }
+
+    /// Returns the index in `list` of an entry which isn't pinned, chosen uniformly at random among
+    /// the unexpired ones if there are any.
+    fn random_victim_index(&mut self) -> Option<usize> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        let map = &self.map;
+        let candidates: Vec<usize> = self
+            .list
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| !map[*key].pinned && !map[*key].is_expired(expiry, now))
+            .map(|(index, _)| index)
+            .collect();
+        if candidates.is_empty() {
+            return self.list.iter().position(|key| !map[key].pinned);
+        }
+        Some(candidates[self.rng.below(candidates.len())])
+    }
+
+    fn do_notify_insert(
+        &mut self,
+        key: Key,
+        value: Value,
+        now: Instant,
+        check_admission: bool,
+    ) -> InsertOutcome<Key, Value> {
+        let expired = self.remove_expired(now);
+        self.do_insert(key, value, now, check_admission, expired)
+    }
+
+    /// Inserts an entry, assuming the expired entries were just swept into `expired`.
+    fn do_insert(
+        &mut self,
+        key: Key,
+        value: Value,
+        now: Instant,
+        check_admission: bool,
+        mut expired: Vec<(Key, Value)>,
+    ) -> InsertOutcome<Key, Value> {
+        let size = self.weigher.as_ref().map(|weigh| weigh(&key, &value));
+        if check_admission
+            && size
+                .zip(self.max_entry_weight)
+                .is_some_and(|(size, max)| size > max)
+        {
+            instrument::insertion(false, true, 0);
+            return InsertOutcome {
+                replaced: None,
+                expired,
+                evicted: Vec::new(),
+                rejected: Some(value),
+            };
+        }
+        self.stats.insertion(now);
+        self.flush_recency();
+        if let Some(sketch) = &mut self.admission {
+            sketch.increment(&key);
+        }
+        if self
+            .map
+            .get(&key)
+            .is_some_and(|entry| entry.is_expired(self.expiry(), now))
+        {
+            // Left behind by a budgeted sweep, so must not be reported as the replaced value.
+            if let Some((key, entry)) = self.detach(&key) {
+                self.events.emit(CacheEvent::Expired(&key, &entry.value));
+                self.stats.expiration(1, now);
+                expired.push((key, entry.value));
+            }
+        }
+        let next_expiry = self.next_expiry();
+        let mut segment = Segment::Recent;
+        let evicted = if self.map.contains_key(&key) {
+            if self.promotion.on_write() {
+                self.promote(&key);
+            }
+            Vec::new()
+        } else if check_admission && !self.admits(&key) {
+            instrument::insertion(false, true, 0);
+            return InsertOutcome {
+                replaced: None,
+                expired,
+                evicted: Vec::new(),
+                rejected: Some(value),
+            };
+        } else {
+            segment = self.admit_segment(&key);
+            let loser = if check_admission {
+                self.window_loser_index()
+            } else {
+                None
+            };
+            let evicted = match loser {
+                Some(index) => self.evict_at(index, now),
+                None => self.remove_lru(now),
+            };
+            self.list.push_back(key.clone());
+            evicted
+        };
+
+        let mut entry = CacheEntry::new(value, now);
+        entry.generation = self.next_generation();
+        entry.segment = segment;
+        entry.priority = self.inflation + entry.worth();
+        if let Some(miss) = &self.miss_time_to_live {
+            entry.time_to_live = miss.time_to_live(&entry.value);
+        }
+        if let Some(hook) = &self.expiry_hook {
+            let time_to_live = if self.map.contains_key(&key) {
+                hook.expire_after_update(&key, &entry.value)
+            } else {
+                hook.expire_after_create(&key, &entry.value)
+            };
+            if time_to_live.is_some() {
+                entry.time_to_live = time_to_live;
+            }
+        }
+        if let Some(fraction) = self.ttl_jitter {
+            if let Some(time_to_live) = entry.time_to_live.or(self.time_to_live) {
+                entry.time_to_live = Some(self.rng.jitter(time_to_live, fraction));
+            }
+        }
+        if let Some(old) = self.map.get(&key) {
+            self.expiries.remove(&key, old);
+            entry.segment = old.segment;
+            entry.referenced = old.referenced;
+            entry.pinned = old.pinned;
+            entry.inserted = old.inserted;
+            entry.accesses = old.accesses;
+            entry.cost = old.cost;
+            entry.size = old.size;
+            entry.priority = old.priority;
+        } else if segment == Segment::Frequent {
+            self.segments.frequent += 1;
+        }
+        if let Some(size) = size {
+            entry.size = size.max(1);
+            entry.priority = self.inflation + entry.worth();
+        }
+        self.expiries.update(&key, &mut entry, self.expiry());
+        match self.map.get(&key) {
+            Some(old) => self
+                .events
+                .emit(CacheEvent::Replaced(&key, &old.value, &entry.value)),
+            None => self.events.emit(CacheEvent::Inserted(&key, &entry.value)),
+        }
+        let replaced = self.map.insert(key, entry).map(|old| old.value);
+        self.reschedule_expiry(next_expiry);
+        instrument::insertion(replaced.is_some(), false, evicted.len());
+        InsertOutcome {
+            replaced,
+            expired,
+            evicted,
+            rejected: None,
+        }
+    }
+
+    /// Returns the segment the new entry `key` starts in under the eviction policy.
+    fn admit_segment(&mut self, key: &Key) -> Segment {
+        match self.eviction_policy {
+            EvictionPolicy::Adaptive => self.ghosts.admit(key, self.capacity),
+            EvictionPolicy::TwoQueue { .. } => self.ghosts.admit_two_queue(key),
+            _ => Segment::Recent,
+        }
+    }
+
+    /// Returns whether the admission policy lets the new entry `key` into the cache.
+    fn admits(&mut self, key: &Key) -> bool {
+        if self.admission.is_none() || self.admission_window || self.map.len() < self.capacity {
+            return true;
+        }
+        match (self.victim_index(), &self.admission) {
+            (Some(index), Some(sketch)) => {
+                sketch.frequency(key) > sketch.frequency(&self.list[index])
+            }
+            _ => true,
+        }
+    }
+
+    fn do_peek<Q>(&self, key: &Q, now: Instant) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map
+            .get(key)
+            .filter(|entry| !entry.is_expired(self.expiry(), now))
+            .map(|entry| &entry.value)
+    }
+
+    /// Returns the generation of a value about to be written.
+    fn next_generation(&mut self) -> u64 {
+        self.generation += 1;
+        self.generation
+    }
+
+    fn set_pinned<Q>(&mut self, key: &Q, pinned: bool) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = Instant::now();
+        let next_expiry = self.next_expiry();
+        let expiry = self.expiry();
+        match Self::entry_mut(&mut self.map, key) {
+            Some((key, entry)) if !entry.is_expired(expiry, now) => {
+                entry.pinned = pinned;
+                self.expiries.update(key, entry, expiry);
+            }
+            _ => return false,
+        }
+        self.reschedule_expiry(next_expiry);
+        true
+    }
+
+    /// Replaces the time to live of the unexpired entry stored under `key` with the result of
+    /// `update` applied to its current time to live.
+    fn update_entry_ttl<Q, F>(&mut self, key: &Q, now: Instant, update: F) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        F: FnOnce(Option<Duration>) -> Option<Duration>,
+    {
+        let next_expiry = self.next_expiry();
+        let expiry = self.expiry();
+        let (key, entry) = match Self::entry_mut(&mut self.map, key) {
+            Some((key, entry)) if !entry.is_expired(expiry, now) => (key, entry),
+            _ => return false,
+        };
+        entry.time_to_live = update(entry.time_to_live.or(expiry.time_to_live));
+        self.expiries.update(key, entry, expiry);
+        self.reschedule_expiry(next_expiry);
+        true
+    }
+
+    fn expiry(&self) -> ExpirySettings {
+        ExpirySettings {
+            time_to_live: self.time_to_live,
+            policy: self.expiry_policy,
+            paused: self.expiry_paused_at.is_some(),
+        }
+    }
+
+    fn do_expiry_time<Q>(&self, key: &Q, now: Instant) -> Option<Instant>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map
+            .get(key)
+            .and_then(|entry| entry.expiry_time(self.expiry()))
+            .filter(|expiry_time| *expiry_time >= now)
+    }
+
+    /// Removes expired items from the cache within the expiry budget and returns them, least
+    /// recently used first.
+    fn remove_expired(&mut self, now: Instant) -> Vec<(Key, Value)> {
+        self.remove_expired_up_to(now, self.expiry_budget.unwrap_or(usize::MAX))
+    }
+
+    /// Removes all expired items from the cache regardless of the expiry budget and returns them,
+    /// least recently used first.
+    fn remove_all_expired(&mut self, now: Instant) -> Vec<(Key, Value)> {
+        self.remove_expired_up_to(now, usize::MAX)
+    }
+
+    /// Removes up to `limit` expired items, the earliest expired first, and returns them least
+    /// recently used first.
+    fn remove_expired_up_to(&mut self, now: Instant, limit: usize) -> Vec<(Key, Value)> {
+        let keys: Vec<Key> = self.expiries.expired(now).take(limit).cloned().collect();
+        if keys.is_empty() {
+            return Vec::new();
+        }
+        let timer = SweepTimer::start();
+
+        let mut removed = BTreeMap::new();
+        for key in keys {
+            if let Some((key, entry)) = self.map.remove_entry(&key) {
+                self.expiries.remove(&key, &entry);
+                self.segments.remove(&entry);
+                self.events.emit(CacheEvent::Expired(&key, &entry.value));
+                let _ = removed.insert(key, entry.value);
+            }
+        }
+
+        // Expired entries may be anywhere in the list, but are usually close to its front, so only
+        // the part of it up to the last of them is taken apart, keeping the relative order of the
+        // remaining ones.
+        let mut pending = removed.len();
+        let end = self
+            .list
+            .iter()
+            .position(|key| {
+                pending -= usize::from(removed.contains_key(key));
+                pending == 0
+            })
+            .map_or(0, |last| last + 1);
+        let mut expired = Vec::with_capacity(removed.len());
+        let mut kept = Vec::new();
+        for key in self.list.drain(..end) {
+            match removed.remove(&key) {
+                Some(value) => expired.push((key, value)),
+                None => kept.push(key),
+            }
+        }
+        for key in kept.into_iter().rev() {
+            self.list.push_front(key);
+        }
+        timer.finish(expired.len());
+        self.stats.expiration(expired.len(), now);
+        expired
+    }
+
+    /// Removes expired items from the cache within the expiry budget, handing them over to the
+    /// expired entries stream if there is one.
+    fn discard_expired(&mut self, now: Instant) {
+        let expired = self.remove_expired(now);
+        self.forward_expired(expired);
+    }
+
+    /// Like `discard_expired()`, but regardless of the expiry budget.
+    fn discard_all_expired(&mut self, now: Instant) {
+        let expired = self.remove_all_expired(now);
+        self.forward_expired(expired);
+    }
+
+    #[cfg(feature = "async")]
+    fn forward_expired(&mut self, expired: Vec<(Key, Value)>) {
+        if let Some(sender) = &self.expired_sender {
+            for entry in expired {
+                if sender.unbounded_send(entry).is_err() {
+                    // The stream has been dropped.
+                    self.expired_sender = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn forward_expired(&mut self, _expired: Vec<(Key, Value)>) {}
+
+    /// Wakes up the background expiry task if an entry now expires before `previous`.
+    #[cfg(feature = "tokio")]
+    fn reschedule_expiry(&self, previous: Option<Instant>) {
+        if let (Some(notify), Some(next)) = (&self.expiry_notify, self.next_expiry()) {
+            if previous.map_or(true, |previous| next < previous) {
+                notify.wake();
+            }
+        }
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    fn reschedule_expiry(&self, _previous: Option<Instant>) {}
+
+    /// Removes least recently used items to make space for a new one and returns them.
+    fn remove_lru(&mut self, now: Instant) -> Vec<(Key, Value)> {
+        match recency::overflow(self.map.len(), self.capacity) {
+            0 => Vec::new(),
+            count => self.evict_lru(count, now),
+        }
+    }
+
+    /// Removes up to `count` items which are not pinned, chosen by the eviction policy, and
+    /// returns them.
+    fn evict_lru(&mut self, count: usize, now: Instant) -> Vec<(Key, Value)> {
+        self.flush_recency();
+        let mut evicted = Vec::new();
+        while evicted.len() < count {
+            if self.eviction_policy == EvictionPolicy::Clock {
+                self.advance_clock_hand();
+            }
+            let index = match self.victim_index() {
+                Some(index) => index,
+                None => break,
+            };
+            match self.evict_index(index) {
+                Some(pair) => evicted.push(pair),
+                None => break,
+            }
+        }
+        instrument::eviction(evicted.len());
+        self.stats.eviction(evicted.len(), now);
+        evicted
+    }
+
+    /// Removes the entry at `index` in `list`, whatever the eviction policy would have chosen,
+    /// and returns it.
+    fn evict_at(&mut self, index: usize, now: Instant) -> Vec<(Key, Value)> {
+        let evicted: Vec<_> = self.evict_index(index).into_iter().collect();
+        instrument::eviction(evicted.len());
+        self.stats.eviction(evicted.len(), now);
+        evicted
+    }
+
+    /// Implements `evict_lru()` and `evict_at()` for a single entry, without recording it.
+    fn evict_index(&mut self, index: usize) -> Option<(Key, Value)> {
+        let key = self.list.remove(index)?;
+        let entry = self.map.remove(&key)?;
+        self.expiries.remove(&key, &entry);
+        self.segments.remove(&entry);
+        match self.eviction_policy {
+            EvictionPolicy::Adaptive => self.ghosts.push(key.clone(), entry.segment, self.capacity),
+            EvictionPolicy::TwoQueue { .. } if entry.segment == Segment::Recent => {
+                let limit = policy::share(self.capacity, 50);
+                self.ghosts.push(key.clone(), entry.segment, limit)
+            }
+            EvictionPolicy::GreedyDualSizeFrequency => {
+                self.inflation = self.inflation.max(entry.priority)
+            }
+            _ => (),
+        }
+        self.events.emit(CacheEvent::Evicted(&key, &entry.value));
+        Some((key, entry.value))
+    }
+}
+
+impl<Key, Value> Clone for LruCache<Key, Value>
+where
+    Key: Clone,
+    Value: Clone,
+{
+    fn clone(&self) -> LruCache<Key, Value> {
+        LruCache {
+            map: self.map.clone(),
+            list: self.list.clone(),
+            expiries: self.expiries.clone(),
+            segments: self.segments.clone(),
+            ghosts: self.ghosts.clone(),
+            capacity: self.capacity,
+            time_to_live: self.time_to_live,
+            expiry_policy: self.expiry_policy,
+            expiry_budget: self.expiry_budget,
+            expiry_paused_at: self.expiry_paused_at,
+            eviction_policy: self.eviction_policy,
+            promotion: self.promotion,
+            rng: self.rng.clone(),
+            inflation: self.inflation,
+            admission: self.admission.clone(),
+            admission_window: self.admission_window,
+            early_expiry: self.early_expiry.clone(),
+            ttl_jitter: self.ttl_jitter,
+            miss_time_to_live: self.miss_time_to_live.clone(),
+            expiry_hook: self.expiry_hook.clone(),
+            max_entry_weight: self.max_entry_weight,
+            weigher: self.weigher.clone(),
+            promotion_buffer: self.promotion_buffer,
+            pending_promotions: self.pending_promotions.clone(),
+            generation: self.generation,
+            events: EventHook::new(),
+            stats: self.stats.clone(),
+            #[cfg(feature = "async")]
+            expired_sender: None,
+            #[cfg(feature = "tokio")]
+            expiry_notify: None,
+        }
+    }
+}
+
+impl<Key, Value> Default for LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Same as `LruCache::unbounded()`.
+    fn default() -> Self {
+        LruCache::unbounded()
+    }
+}
+
+impl<Key, Value> Extend<(Key, Value)> for LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Inserts all the key-value pairs into the cache, in iteration order, so the last pair ends up
+    /// as the most recently used entry.
+    fn extend<I: IntoIterator<Item = (Key, Value)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for (key, value) in iter {
+            let _ = self.insert(key, value);
+        }
+    }
+}
+
+impl<Key, Value> FromIterator<(Key, Value)> for LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Creates a cache with neither capacity nor time limit holding all the key-value pairs.
+    fn from_iter<I: IntoIterator<Item = (Key, Value)>>(iter: I) -> Self {
+        let mut cache = LruCache::unbounded();
+        cache.extend(iter);
+        cache
+    }
+}
+
+impl<Key, Value> From<BTreeMap<Key, Value>> for LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Creates a cache with neither capacity nor time limit holding all the entries of `map`, to
+    /// be given limits by `set_capacity()` and `set_time_to_live()` if need be.  The entries are
+    /// used in ascending key order, so the largest key ends up as the most recently used.
+    fn from(map: BTreeMap<Key, Value>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<Key, Value> From<Vec<(Key, Value)>> for LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Creates a cache with neither capacity nor time limit holding all the key-value pairs, to be
+    /// given limits by `set_capacity()` and `set_time_to_live()` if need be.  The pairs are used
+    /// in order, so the last pair ends up as the most recently used, and wins over any earlier
+    /// pair for the same key.
+    fn from(pairs: Vec<(Key, Value)>) -> Self {
+        pairs.into_iter().collect()
+    }
+}
+
+impl<Key, Value, Q> Index<&Q> for LruCache<Key, Value>
+where
+    Key: Ord + Clone + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    type Output = Value;
+
+    /// Returns a reference to the value corresponding to the supplied key, without updating its
+    /// timestamp.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not present in the cache or has expired.
+    fn index(&self, key: &Q) -> &Value {
+        self.peek(key).expect("no entry found for key")
+    }
+}
+
+/// Only available with the `index_mut` feature, since unlike `Index` this has the side effects of
+/// `LruCache::get_mut()`.
+#[cfg(feature = "index_mut")]
+impl<Key, Value, Q> IndexMut<&Q> for LruCache<Key, Value>
+where
+    Key: Ord + Clone + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    /// Returns a mutable reference to the value corresponding to the supplied key.  Also removes
+    /// expired elements and updates the time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not present in the cache or has expired.
+    fn index_mut(&mut self, key: &Q) -> &mut Value {
+        self.get_mut(key).expect("no entry found for key")
+    }
+}
+
+impl<Key, Value> IntoIterator for LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    type Item = (Key, Value);
+    type IntoIter = IntoIter<Key, Value>;
+
+    /// Consumes the cache, producing its unexpired entries in the most recently used order.
+    fn into_iter(self) -> IntoIter<Key, Value> {
+        let expiry = self.expiry();
+        IntoIter::new(self.map, self.list, expiry)
+    }
+}
+
+impl<'a, Key, Value> IntoIterator for &'a LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    type Item = (&'a Key, &'a Value);
+    type IntoIter = PeekIter<'a, Key, Value>;
+
+    /// Same as `LruCache::peek_iter()`.
+    fn into_iter(self) -> PeekIter<'a, Key, Value> {
+        self.peek_iter()
+    }
+}
+
+impl<'a, Key, Value> IntoIterator for &'a mut LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    type Item = (&'a Key, &'a Value);
+    type IntoIter = Iter<'a, Key, Value>;
+
+    /// Same as `LruCache::iter()`.
+    fn into_iter(self) -> Iter<'a, Key, Value> {
+        self.iter()
+    }
+}
+
+impl<'a, Key: Ord + Clone, Value> VacantEntry<'a, Key, Value> {
+    /// Inserts a value
+    ///
+    /// The value is always inserted, regardless of the admission policy and maximum entry weight.
+    pub fn insert(self, value: Value) -> &'a mut Value {
+        let now = Instant::now();
+        let outcome = self
+            .cache
+            .do_notify_insert(self.key.clone(), value, now, false);
+        self.cache.forward_expired(outcome.expired);
+        self.cache
+            .do_get_mut_decided(&self.key, now)
+            .expect("key not found")
+    }
+}
+
+impl<'a, Value> OccupiedEntry<'a, Value> {
+    /// Converts the entry into a mutable reference to its value.
+    pub fn into_mut(self) -> &'a mut Value {
+        self.value
+    }
+}
+
+impl<'a, Key: Debug, Value: Debug> Debug for OccupiedError<'a, Key, Value> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("OccupiedError")
+            .field("key", &self.key)
+            .field("old_value", &self.entry.value)
+            .field("new_value", &self.value)
+            .finish()
+    }
+}
+
+impl<'a, Key: Debug, Value: Debug> Display for OccupiedError<'a, Key, Value> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "failed to insert {:?}, key {:?} already exists with value {:?}",
+            self.value, self.key, self.entry.value,
+        )
+    }
+}
+
+impl<'a, Key: Debug, Value: Debug> Error for OccupiedError<'a, Key, Value> {}
+
+impl<Key: Debug, Value: Debug> Debug for ReserveError<Key, Value> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("ReserveError")
+            .field("key", &self.key)
+            .field("value", &self.value)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<Key: Debug, Value: Debug> Display for ReserveError<Key, Value> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "failed to insert {:?} under key {:?}: {}",
+            self.value, self.key, self.error,
+        )
+    }
+}
+
+impl<Key: Debug, Value: Debug> Error for ReserveError<Key, Value> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<'a, Key: Ord + Clone, Value> Entry<'a, Key, Value> {
+    /// Ensures a value is in the entry by inserting the default if empty, and returns
+    /// a mutable reference to the value in the entry.
+    pub fn or_insert(self, default: Value) -> &'a mut Value {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default function if empty,
+    /// and returns a mutable reference to the value in the entry.
+    pub fn or_insert_with<F: FnOnce() -> Value>(self, default: F) -> &'a mut Value {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+}
+
+#[cfg(test)]
+// The original tests predate these lints.
+#[allow(unused_qualifications, clippy::bool_assert_comparison)]
+mod test {
+    use super::*;
+    use rand::distributions::{Distribution, Standard};
+    use rand::thread_rng;
+    use std::time::Duration;
+
+    #[cfg(feature = "sn_fake_clock")]
+    fn sleep(time: u64) {
+        use sn_fake_clock::FakeClock;
+        FakeClock::advance_time(time);
+    }
+
+    #[cfg(not(feature = "sn_fake_clock"))]
+    fn sleep(time: u64) {
+        use std::thread;
+        thread::sleep(Duration::from_millis(time));
+    }
+
+    fn generate_random_vec<T>(len: usize) -> Vec<T>
+    where
+        Standard: Distribution<T>,
+    {
+        let mut rng = thread_rng();
+        let v: Vec<T> = Standard.sample_iter(&mut rng).take(len).collect();
+        v
+    }
+
+    /// Capacity based cache evicting its entries under `policy`.
+    fn policy_cache<Value>(policy: EvictionPolicy, capacity: usize) -> LruCache<usize, Value> {
+        let mut lru_cache = LruCache::with_capacity(capacity);
+        lru_cache.set_eviction_policy(policy);
+        lru_cache
+    }
+
+    #[test]
+    fn size_only() {
+        let size = 10usize;
+        let mut lru_cache = super::LruCache::<usize, usize>::with_capacity(size);
+
+        for i in 0..10 {
+            assert_eq!(lru_cache.len(), i);
+            let _ = lru_cache.insert(i, i);
+            assert_eq!(lru_cache.len(), i + 1);
+        }
+
+        for i in 10..1000 {
+            let _ = lru_cache.insert(i, i);
+            assert_eq!(lru_cache.len(), size);
+        }
+
+        for _ in (0..1000).rev() {
+            assert!(lru_cache.contains_key(&(1000 - 1)));
+            assert!(lru_cache.get(&(1000 - 1)).is_some());
+            assert_eq!(*lru_cache.get(&(1000 - 1)).unwrap(), 1000 - 1);
+        }
+    }
+
+    #[test]
+    fn time_only() {
+        let time_to_live = Duration::from_millis(100);
+        let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+
+        for i in 0..10 {
+            assert_eq!(lru_cache.len(), i);
+            let _ = lru_cache.insert(i, i);
+            assert_eq!(lru_cache.len(), i + 1);
+        }
+
+        sleep(101);
+        let _ = lru_cache.insert(11, 11);
+
+        assert_eq!(lru_cache.len(), 1);
+
+        for i in 0..10 {
+            assert!(!lru_cache.is_empty());
+            assert_eq!(lru_cache.len(), i + 1);
+            let _ = lru_cache.insert(i, i);
+            assert_eq!(lru_cache.len(), i + 2);
+        }
+
+        sleep(101);
+        assert_eq!(0, lru_cache.len());
+        assert!(lru_cache.is_empty());
+    }
+
+    #[test]
+    fn time_only_check() {
+        let time_to_live = Duration::from_millis(50);
+        let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+
+        assert_eq!(lru_cache.len(), 0);
+        let _ = lru_cache.insert(0, 0);
+        assert_eq!(lru_cache.len(), 1);
+
+        sleep(101);
+
+        assert!(!lru_cache.contains_key(&0));
+        assert_eq!(lru_cache.len(), 0);
+    }
+
+    #[test]
+    fn time_and_size() {
+        let size = 10usize;
+        let time_to_live = Duration::from_millis(100);
+        let mut lru_cache =
+            super::LruCache::<usize, usize>::with_expiry_duration_and_capacity(time_to_live, size);
+
+        for i in 0..1000 {
+            if i < size {
+                assert_eq!(lru_cache.len(), i);
+            }
+
+            let _ = lru_cache.insert(i, i);
+
+            if i < size {
+                assert_eq!(lru_cache.len(), i + 1);
+            } else {
+                assert_eq!(lru_cache.len(), size);
+            }
+        }
+
+        sleep(101);
+        let _ = lru_cache.insert(1, 1);
+
+        assert_eq!(lru_cache.len(), 1);
+    }
+
+    #[derive(PartialEq, PartialOrd, Ord, Clone, Eq)]
+    struct Temp {
+        id: Vec<u8>,
+    }
+
+    #[test]
+    fn time_size_struct_value() {
+        let size = 100usize;
+        let time_to_live = Duration::from_millis(100);
+
+        let mut lru_cache =
+            super::LruCache::<Temp, usize>::with_expiry_duration_and_capacity(time_to_live, size);
+
+        for i in 0..1000 {
+            if i < size {
+                assert_eq!(lru_cache.len(), i);
+            }
+
+            let _ = lru_cache.insert(
+                Temp {
+                    id: generate_random_vec::<u8>(64),
+                },
+                i,
+            );
+
+            if i < size {
+                assert_eq!(lru_cache.len(), i + 1);
+            } else {
+                assert_eq!(lru_cache.len(), size);
+            }
+        }
+
+        sleep(101);
+        let _ = lru_cache.insert(
+            Temp {
+                id: generate_random_vec::<u8>(64),
+            },
+            1,
+        );
+
+        assert_eq!(lru_cache.len(), 1);
+    }
+
+    mod notify_insert {
+        use super::*;
+
+        #[test]
+        fn it_removes_expired_entries() {
+            let ttl = Duration::from_millis(200);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(250);
+
+            let _ = lru_cache.notify_insert(3, 3);
+
+            assert_eq!(lru_cache.map.len(), 1);
+            assert_eq!(lru_cache.map[&3].value, 3);
+        }
+
+        #[test]
+        fn it_returns_removed_expired_entries() {
+            let ttl = Duration::from_millis(200);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(250);
+
+            let (_replaced, expired) = lru_cache.notify_insert(3, 3);
+
+            assert_eq!(expired.len(), 2);
+            assert_eq!(expired[0], (1, 1));
+            assert_eq!(expired[1], (2, 2));
+        }
+    }
+
+    mod iter {
+        use super::*;
+
+        #[test]
+        fn it_returns_none_when_cache_is_empty() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+
+            let next = lru_cache.iter().next();
+
+            assert!(next.is_none());
+        }
+
+        #[test]
+        fn it_updates_item_timestamps_of_traversed_items() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            let _ = lru_cache.insert(0, 0);
+            sleep(1);
+            let _ = lru_cache.insert(1, 1);
+            sleep(1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(1);
+
+            let initial_instant0 = lru_cache.map[&0].timestamp;
+            let initial_instant2 = lru_cache.map[&2].timestamp;
+            sleep(1);
+
+            // only the first two entries should have their timestamp updated (and position in list)
+            let _ = lru_cache.iter().take(2).all(|_| true);
+
+            assert_ne!(lru_cache.map[&2].timestamp, initial_instant2);
+            assert_eq!(lru_cache.map[&0].timestamp, initial_instant0);
+        }
+
+        #[test]
+        fn it_moves_traversed_items_to_the_top_of_the_cache() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+
+            let _ = lru_cache.iter().take(2).all(|_| true);
+
+            assert_eq!(*lru_cache.list.front().unwrap(), 0);
+            assert_eq!(*lru_cache.list.back().unwrap(), 1);
+        }
+
+        #[test]
+        fn it_yields_the_most_recent_items_first() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(4);
+            let _ = lru_cache.insert(2, 2);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(3, 3);
+            let _ = lru_cache.insert(1, 1);
+
+            let cached = lru_cache.iter().collect::<Vec<_>>();
+
+            assert_eq!(cached, vec![(&1, &1), (&3, &3), (&0, &0), (&2, &2)]);
+        }
+
+        #[test]
+        fn it_removes_expired_items() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(3));
+            let _ = lru_cache.insert(0, 0);
+            sleep(1);
+            let _ = lru_cache.insert(1, 1);
+            sleep(4);
+            let _ = lru_cache.insert(2, 2);
+
+            let items: Vec<_> = lru_cache.iter().collect();
+
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0], (&2, &2));
+        }
+    }
+
+    mod notify_iter {
+        use super::*;
+
+        #[test]
+        fn it_returns_none_when_cache_is_empty() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+
+            let next = lru_cache.notify_iter().next();
+
+            assert!(next.is_none());
+        }
+
+        #[test]
+        fn it_yields_the_most_recent_items_first() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(4);
+            let _ = lru_cache.insert(2, 2);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(3, 3);
+            let _ = lru_cache.insert(1, 1);
+
+            let cached = lru_cache
+                .notify_iter()
+                .map(|entry| match entry {
+                    TimedEntry::Valid(key, value) => (key, value),
+                    _ => panic!("Unexpected expired entry"),
+                })
+                .collect::<Vec<_>>();
+
+            assert_eq!(cached, vec![(&1, &1), (&3, &3), (&0, &0), (&2, &2)]);
+        }
+
+        #[test]
+        fn it_produces_expired_and_valid_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(300));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(250);
+            let _ = lru_cache.insert(2, 2);
+            sleep(60);
+
+            let expired: Vec<_> = lru_cache
+                .notify_iter()
+                .filter_map(|entry| match entry {
+                    TimedEntry::Expired(key, value) => Some((key, value)),
+                    _ => None,
+                })
+                .collect();
+            let valid: Vec<_> = lru_cache
+                .notify_iter()
+                .filter_map(|entry| match entry {
+                    TimedEntry::Valid(key, value) => Some((key, value)),
+                    _ => None,
+                })
+                .collect();
+
+            assert_eq!(valid.len(), 1);
+            assert_eq!(valid[0], (&2, &2));
+            assert_eq!(expired.len(), 2);
+            assert_eq!(expired[0], (1, 1));
+            assert_eq!(expired[1], (0, 0));
+        }
+
+        #[test]
+        fn it_removes_expired_items() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(300));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(250);
+            let _ = lru_cache.insert(2, 2);
+            sleep(60);
+
+            let _items: Vec<_> = lru_cache.notify_iter().collect();
+
+            assert!(lru_cache.get(&0).is_none());
+            assert!(lru_cache.get(&1).is_none());
+        }
+    }
+
+    mod peek_iter {
+        use super::*;
+
+        #[test]
+        fn it_yields_cached_entries_in_most_recently_used_order() {
+            let time_to_live = Duration::from_millis(500);
+            let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            let _ = lru_cache.insert(3, 3);
+
+            assert_eq!(
+                vec![(&3, &3), (&2, &2), (&1, &1)],
+                lru_cache.peek_iter().collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn it_yields_only_unexpired_entries() {
+            let time_to_live = Duration::from_millis(500);
+            let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(300);
+            let _ = lru_cache.insert(3, 3);
+            sleep(300);
+
+            let entries = lru_cache.peek_iter().collect::<Vec<_>>();
+
+            assert_eq!(entries, vec![(&3, &3)]);
+        }
+
+        #[test]
+        fn it_doesnt_modify_entry_update_time() {
+            let time_to_live = Duration::from_millis(500);
+            let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+
+            let _ = lru_cache.insert(1, 1);
+            let expected_time = lru_cache
+                .map
+                .values()
+                .map(|entry| entry.timestamp)
+                .next()
+                .unwrap();
+
+            let _ = lru_cache.peek_iter().collect::<Vec<_>>();
+
+            let real_time = lru_cache
+                .map
+                .values()
+                .map(|entry| entry.timestamp)
+                .next()
+                .unwrap();
+            assert_eq!(real_time, expected_time);
+        }
+    }
+
+    #[cfg(feature = "async")]
+    mod expired_stream {
+        use super::*;
+        use futures::executor::block_on;
+        use futures::StreamExt;
+
+        #[test]
+        fn it_yields_entries_expired_by_cache_activity() {
+            let ttl = Duration::from_millis(20);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let mut expired = lru_cache.expired_stream();
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(30);
+
+            let _ = lru_cache.insert(3, 3);
+
+            assert_eq!(block_on(expired.next()), Some((1, 1)));
+            assert_eq!(block_on(expired.next()), Some((2, 2)));
+        }
+
+        #[test]
+        fn it_doesnt_duplicate_entries_returned_to_the_caller() {
+            let ttl = Duration::from_millis(20);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let mut expired = lru_cache.expired_stream();
+            let _ = lru_cache.insert(1, 1);
+            sleep(30);
+
+            let (_, returned) = lru_cache.notify_insert(2, 2);
+            drop(lru_cache);
+
+            assert_eq!(returned, vec![(1, 1)]);
+            assert_eq!(block_on(expired.next()), None);
+        }
+    }
+
+    #[cfg(all(feature = "background_expiry", not(feature = "sn_fake_clock")))]
+    mod background_expiry {
+        use super::*;
+        use std::sync::Arc;
+
+        #[test]
+        fn it_removes_expired_entries_periodically() {
+            let ttl = Duration::from_millis(20);
+            let lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl)
+                .with_background_expiry(Duration::from_millis(10));
+            let _ = lru_cache.lock().unwrap().insert(1, 1);
+
+            sleep(60);
+
+            assert!(lru_cache.lock().unwrap().map.is_empty());
+        }
+
+        #[test]
+        fn it_does_not_keep_the_cache_alive() {
+            let lru_cache = LruCache::<usize, usize>::with_capacity(1)
+                .with_background_expiry(Duration::from_millis(10));
+            let weak = Arc::downgrade(&lru_cache);
+            drop(lru_cache);
+
+            assert!(weak.upgrade().is_none());
+        }
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "sn_fake_clock")))]
+    mod spawn_expiry_task {
+        use super::*;
+        use std::sync::{Arc, Mutex};
+        use tokio::runtime::Builder;
+
+        #[test]
+        fn it_removes_entries_once_they_expire() {
+            let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+            let ttl = Duration::from_millis(20);
+            let lru_cache = Arc::new(Mutex::new(LruCache::<usize, usize>::with_expiry_duration(
+                ttl,
+            )));
+
+            runtime.block_on(async {
+                let _task = LruCache::spawn_expiry_task(&lru_cache);
+                let _ = lru_cache.lock().unwrap().insert(1, 1);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let _ = lru_cache.lock().unwrap().insert(2, 2);
+                tokio::time::sleep(Duration::from_millis(15)).await;
+
+                assert_eq!(lru_cache.lock().unwrap().map.len(), 1);
+
+                tokio::time::sleep(Duration::from_millis(15)).await;
+
+                assert!(lru_cache.lock().unwrap().map.is_empty());
+            });
+        }
+
+        #[test]
+        fn it_finishes_when_the_cache_is_dropped() {
+            let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+            let lru_cache = Arc::new(Mutex::new(LruCache::<usize, usize>::with_capacity(1)));
+
+            runtime.block_on(async move {
+                let task = LruCache::spawn_expiry_task(&lru_cache);
+                drop(lru_cache);
+
+                assert!(task.await.is_ok());
+            });
+        }
+    }
+
+    mod set_capacity {
+        use super::*;
+
+        #[test]
+        fn it_evicts_and_returns_least_recently_used_entries() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(4);
+            for i in 0..4 {
+                let _ = lru_cache.insert(i, i);
+            }
+            let _ = lru_cache.get(&0);
+
+            let evicted = lru_cache.set_capacity(2);
+
+            assert_eq!(evicted, vec![(1, 1), (2, 2)]);
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&0, &0), (&3, &3)]
+            );
+        }
+
+        #[test]
+        fn it_applies_the_new_capacity_to_later_inserts() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            let _ = lru_cache.insert(0, 0);
+
+            assert!(lru_cache.set_capacity(3).is_empty());
+            for i in 1..4 {
+                let _ = lru_cache.insert(i, i);
+            }
+
+            assert_eq!(lru_cache.len(), 3);
+            assert!(!lru_cache.contains_key(&0));
+        }
+    }
+
+    #[test]
+    fn update_time_check() {
+        let time_to_live = Duration::from_millis(500);
+        let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+
+        assert_eq!(lru_cache.len(), 0);
+        let _ = lru_cache.insert(0, 0);
+        assert_eq!(lru_cache.len(), 1);
+
+        sleep(300);
+        assert_eq!(Some(&0), lru_cache.get(&0));
+        sleep(300);
+        assert_eq!(Some(&0), lru_cache.peek(&0));
+        sleep(300);
+        assert_eq!(None, lru_cache.peek(&0));
+    }
+
+    #[test]
+    fn deref_coercions() {
+        let mut lru_cache = super::LruCache::<String, usize>::with_capacity(1);
+        let _ = lru_cache.insert("foo".to_string(), 0);
+        assert_eq!(true, lru_cache.contains_key("foo"));
+        assert_eq!(Some(&0), lru_cache.get("foo"));
+        assert_eq!(Some(&mut 0), lru_cache.get_mut("foo"));
+        assert_eq!(Some(&0), lru_cache.peek("foo"));
+        assert_eq!(Some(0), lru_cache.remove("foo"));
+    }
+
+    mod remove_expired {
+        use super::*;
+
+        #[test]
+        fn it_removes_expired_entries_from_the_map() {
+            let ttl = Duration::from_millis(200);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(150);
+            let _ = lru_cache.insert(3, 3);
+            let _ = lru_cache.insert(4, 4);
+            sleep(60);
+
+            let now = Instant::now();
+            let _ = lru_cache.remove_expired(now);
+
+            assert_eq!(lru_cache.map.len(), 2);
+            assert_eq!(lru_cache.map[&3].value, 3);
+            assert_eq!(lru_cache.map[&4].value, 4);
+        }
+
+        #[test]
+        fn it_removes_expired_entries_from_the_list() {
+            let ttl = Duration::from_millis(200);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(150);
+            let _ = lru_cache.insert(3, 3);
+            let _ = lru_cache.insert(4, 4);
+            sleep(60);
+
+            let now = Instant::now();
+            let _ = lru_cache.remove_expired(now);
+
+            assert_eq!(lru_cache.list.len(), 2);
+            assert_eq!(lru_cache.list[0], 3);
+            assert_eq!(lru_cache.list[1], 4);
+        }
+
+        #[test]
+        fn it_returns_expired_entries() {
+            let ttl = Duration::from_millis(200);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(150);
+            let _ = lru_cache.insert(3, 3);
+            sleep(60);
+
+            let now = Instant::now();
+            let expired = lru_cache.remove_expired(now);
+
+            assert_eq!(expired.len(), 2);
+            assert_eq!(expired[0], (1, 1));
+            assert_eq!(expired[1], (2, 2));
+        }
+    }
+
+    mod set_time_to_live {
+        use super::*;
+
+        #[test]
+        fn it_evicts_entries_expired_under_the_new_ttl() {
+            let ttl = Duration::from_millis(500);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(100);
+            let _ = lru_cache.insert(3, 3);
+
+            let expired = lru_cache.set_time_to_live(Some(Duration::from_millis(50)));
+
+            assert_eq!(expired, vec![(1, 1), (2, 2)]);
+            assert_eq!(lru_cache.peek_iter().collect::<Vec<_>>(), vec![(&3, &3)]);
+        }
+
+        #[test]
+        fn it_lifts_the_time_limit() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            let _ = lru_cache.insert(1, 1);
+
+            assert!(lru_cache.set_time_to_live(Some(ttl)).is_empty());
+            assert!(lru_cache.set_time_to_live(None).is_empty());
+            sleep(100);
+
+            assert_eq!(lru_cache.get(&1), Some(&1));
+        }
+    }
+
+    #[test]
+    fn configuration_getters() {
+        let ttl = Duration::from_millis(100);
+
+        let lru_cache = LruCache::<usize, usize>::with_capacity(10);
+        assert_eq!(lru_cache.capacity(), 10);
+        assert!(lru_cache.is_capacity_limited());
+        assert_eq!(lru_cache.time_to_live(), None);
+
+        let lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+        assert_eq!(lru_cache.capacity(), usize::MAX);
+        assert!(!lru_cache.is_capacity_limited());
+        assert_eq!(lru_cache.time_to_live(), Some(ttl));
+
+        let lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 5);
+        assert_eq!(lru_cache.capacity(), 5);
+        assert!(lru_cache.is_capacity_limited());
+        assert_eq!(lru_cache.time_to_live(), Some(ttl));
+    }
+
+    #[test]
+    fn unbounded_and_default() {
+        #[derive(Default)]
+        struct Holder {
+            cache: LruCache<usize, usize>,
+        }
+
+        for mut lru_cache in [
+            LruCache::<usize, usize>::unbounded(),
+            Holder::default().cache,
+        ] {
+            assert!(!lru_cache.is_capacity_limited());
+            assert_eq!(lru_cache.time_to_live(), None);
+            lru_cache.extend((0..100).map(|i| (i, i)));
+            assert_eq!(lru_cache.len(), 100);
+            assert_eq!(lru_cache.set_capacity(10).len(), 90);
+            assert_eq!(lru_cache.len(), 10);
+        }
+    }
+
+    #[test]
+    fn std_map_conversions() {
+        use std::collections::HashMap;
+
+        let map: BTreeMap<usize, usize> = (0..5).map(|i| (i, i * 10)).collect();
+        let lru_cache = LruCache::from(map.clone());
+        assert_eq!(lru_cache.list, vec![0, 1, 2, 3, 4]);
+        assert_eq!(lru_cache.into_btree_map(), map);
+
+        let mut lru_cache = LruCache::from(vec![(1, "one"), (0, "zero"), (1, "uno")]);
+        assert_eq!(lru_cache.list, vec![0, 1]);
+        let _ = lru_cache.set_time_to_live(Some(Duration::from_millis(20)));
+        let _ = lru_cache.insert(2, "two");
+        assert!(lru_cache.set_entry_ttl(&2, Duration::from_secs(10)));
+        sleep(30);
+        assert_eq!(
+            lru_cache.into_hash_map(),
+            vec![(2, "two")].into_iter().collect::<HashMap<_, _>>()
+        );
+    }
+
+    mod extend {
+        use super::*;
+
+        #[test]
+        fn it_inserts_pairs_respecting_the_capacity() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            let _ = lru_cache.insert(0, 0);
+
+            lru_cache.extend((1..5).map(|i| (i, i)));
+
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&4, &4), (&3, &3), (&2, &2)]
+            );
+        }
+
+        #[test]
+        fn from_iter_creates_an_unlimited_cache() {
+            let lru_cache: LruCache<usize, usize> = (0..100).map(|i| (i, i * 2)).collect();
+
+            assert_eq!(lru_cache.len(), 100);
+            assert!(!lru_cache.is_capacity_limited());
+            assert_eq!(lru_cache.time_to_live(), None);
+            assert_eq!(lru_cache.peek(&99), Some(&198));
+        }
+    }
+
+    mod into_iter {
+        use super::*;
+
+        #[test]
+        fn it_yields_owned_unexpired_entries_in_most_recently_used_order() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, String>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, "zero".to_string());
+            sleep(150);
+            let _ = lru_cache.insert(2, "two".to_string());
+            let _ = lru_cache.insert(1, "one".to_string());
+
+            let entries: Vec<_> = lru_cache.into_iter().collect();
+
+            assert_eq!(
+                entries,
+                vec![(1, "one".to_string()), (2, "two".to_string())]
+            );
+        }
+
+        #[test]
+        fn references_iterate_in_for_loops() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            lru_cache.extend(vec![(0, 0), (1, 1), (2, 2)]);
+
+            let mut peeked = Vec::new();
+            for (key, _) in &lru_cache {
+                peeked.push(*key);
+            }
+            let mut traversed = Vec::new();
+            for (key, _) in &mut lru_cache {
+                traversed.push(*key);
+            }
+
+            assert_eq!(peeked, vec![2, 1, 0]);
+            assert_eq!(traversed, vec![2, 1, 0]);
+            assert_eq!(lru_cache.list, vec![2, 1, 0]);
+        }
+    }
+
+    mod index {
+        use super::*;
+
+        #[test]
+        fn it_returns_the_value_without_updating_recency() {
+            let mut lru_cache = LruCache::<String, usize>::with_capacity(2);
+            let _ = lru_cache.insert("foo".to_string(), 0);
+            let _ = lru_cache.insert("bar".to_string(), 1);
+
+            assert_eq!(lru_cache["foo"], 0);
+            assert_eq!(lru_cache.list[0], "foo");
+        }
+
+        #[test]
+        #[should_panic(expected = "no entry found for key")]
+        fn it_panics_on_expired_entries() {
+            let ttl = Duration::from_millis(20);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            sleep(30);
+
+            let _ = lru_cache[&0];
+        }
+
+        #[cfg(feature = "index_mut")]
+        #[test]
+        fn index_mut_updates_the_value_and_recency() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+
+            lru_cache[&0] += 10;
+
+            assert_eq!(lru_cache.peek(&0), Some(&10));
+            assert_eq!(lru_cache.list[1], 0);
+        }
+    }
+
+    mod range {
+        use super::*;
+
+        #[test]
+        fn it_yields_unexpired_entries_within_the_range_in_key_order() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(3, 3);
+            sleep(150);
+            for i in (0..10).rev() {
+                if i != 3 {
+                    let _ = lru_cache.insert(i, i);
+                }
+            }
+
+            let entries: Vec<_> = lru_cache.range(2..6).collect();
+
+            assert_eq!(entries, vec![(&2, &2), (&4, &4), (&5, &5)]);
+        }
+
+        #[test]
+        fn range_mut_doesnt_update_recency() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(5);
+            lru_cache.extend((0..5).map(|i| (i, i)));
+
+            for (_, value) in lru_cache.range_mut(..=1) {
+                *value += 10;
+            }
+
+            assert_eq!(lru_cache.peek(&0), Some(&10));
+            assert_eq!(lru_cache.peek(&1), Some(&11));
+            assert_eq!(lru_cache.list, vec![0, 1, 2, 3, 4]);
+        }
+    }
+
+    mod touch {
+        use super::*;
+
+        #[test]
+        fn it_refreshes_the_entry() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 2);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(60);
+
+            assert!(lru_cache.touch(&0));
+            sleep(60);
+
+            assert_eq!(lru_cache.list, vec![1, 0]);
+            assert!(lru_cache.contains_key(&0));
+            assert!(!lru_cache.contains_key(&1));
+        }
+
+        #[test]
+        fn it_returns_false_for_missing_and_expired_entries() {
+            let ttl = Duration::from_millis(20);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            sleep(30);
+
+            assert!(!lru_cache.touch(&0));
+            assert!(!lru_cache.touch(&1));
+        }
+    }
+
+    mod remaining_ttl {
+        use super::*;
+
+        #[test]
+        fn it_is_computed_from_the_last_access() {
+            let ttl = Duration::from_millis(500);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let inserted_at = lru_cache.map[&0].timestamp;
+            sleep(100);
+
+            let remaining = lru_cache.remaining_ttl(&0).unwrap();
+
+            assert!(remaining <= Duration::from_millis(400));
+            assert!(remaining > Duration::from_millis(300));
+            assert_eq!(lru_cache.expiry_time(&0), Some(inserted_at + ttl));
+        }
+
+        #[test]
+        fn it_is_none_without_an_unexpired_entry_or_ttl() {
+            let ttl = Duration::from_millis(20);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            sleep(30);
+
+            assert_eq!(lru_cache.remaining_ttl(&0), None);
+            assert_eq!(lru_cache.expiry_time(&1), None);
+
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            let _ = lru_cache.insert(0, 0);
+            assert_eq!(lru_cache.remaining_ttl(&0), None);
+        }
+    }
+
+    mod entry_ttl {
+        use super::*;
+
+        #[test]
+        fn set_entry_ttl_overrides_the_cache_wide_ttl() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+
+            assert!(lru_cache.set_entry_ttl(&0, Duration::from_millis(300)));
+            assert!(lru_cache.set_entry_ttl(&2, Duration::from_millis(20)));
+            assert!(!lru_cache.set_entry_ttl(&3, Duration::from_millis(300)));
+            sleep(50);
+
+            let (_, expired) = lru_cache.notify_insert(3, 3);
+            assert_eq!(expired, vec![(2, 2)]);
+            sleep(110);
+
+            assert_eq!(lru_cache.peek_iter().collect::<Vec<_>>(), vec![(&0, &0)]);
+        }
+
+        #[test]
+        fn set_entry_ttl_expires_entries_of_a_capacity_based_cache() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+
+            assert!(lru_cache.set_entry_ttl(&0, Duration::from_millis(20)));
+            sleep(30);
+
+            assert_eq!(lru_cache.len(), 1);
+            assert_eq!(lru_cache.get(&0), None);
+            assert_eq!(lru_cache.get(&1), Some(&1));
+        }
+
+        #[test]
+        fn extend_entry_ttl_adds_to_the_current_ttl() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let inserted_at = lru_cache.map[&0].timestamp;
+
+            assert!(lru_cache.extend_entry_ttl(&0, Duration::from_millis(50)));
+            assert!(lru_cache.extend_entry_ttl(&0, Duration::from_millis(50)));
+
+            assert_eq!(
+                lru_cache.expiry_time(&0),
+                Some(inserted_at + Duration::from_millis(200))
+            );
+        }
+
+        #[test]
+        fn insert_resets_the_entry_ttl() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            assert!(lru_cache.set_entry_ttl(&0, Duration::from_millis(20)));
+
+            let _ = lru_cache.insert(0, 1);
+            sleep(30);
+
+            assert_eq!(lru_cache.get(&0), Some(&1));
+        }
+    }
+
+    mod pin {
+        use super::*;
+
+        #[test]
+        fn pinned_entries_are_not_evicted_to_make_room() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            lru_cache.extend((0..3).map(|i| (i, i)));
+
+            assert!(lru_cache.pin(&0));
+            lru_cache.extend((3..6).map(|i| (i, i)));
+
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&5, &5), (&4, &4), (&0, &0)]
+            );
+            assert_eq!(lru_cache.set_capacity(1), vec![(4, 4), (5, 5)]);
+            assert_eq!(lru_cache.len(), 1);
+        }
+
+        #[test]
+        fn pinned_entries_dont_expire() {
+            let ttl = Duration::from_millis(20);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+
+            assert!(lru_cache.pin(&1));
+            assert!(lru_cache.is_pinned(&1));
+            sleep(30);
+
+            assert_eq!(lru_cache.len(), 1);
+            assert_eq!(lru_cache.remaining_ttl(&1), None);
+            assert_eq!(lru_cache.get(&1), Some(&1));
+
+            assert!(lru_cache.unpin(&1));
+            sleep(30);
+
+            assert!(lru_cache.is_empty());
+        }
+
+        #[test]
+        fn the_capacity_is_exceeded_if_all_entries_are_pinned() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            let _ = lru_cache.insert(0, 0);
+
+            assert!(lru_cache.pin(&0));
+            assert!(!lru_cache.pin(&1));
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&2, &2), (&0, &0)]
+            );
+        }
+
+        #[test]
+        fn replacing_keeps_the_pin() {
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(
+                Duration::from_millis(20),
+                2,
+            );
+            let _ = lru_cache.insert(0, 0);
+            assert!(lru_cache.pin(&0));
+            assert_eq!(lru_cache.insert(0, 10), Some(0));
+            assert!(lru_cache.is_pinned(&0));
+
+            lru_cache.extend((1..4).map(|i| (i, i)));
+            sleep(30);
+            assert_eq!(lru_cache.peek_iter().collect::<Vec<_>>(), vec![(&0, &10)]);
+        }
+    }
+
+    mod expiry_policy {
+        use super::*;
+
+        #[test]
+        fn after_write_ignores_reads() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            assert_eq!(lru_cache.expiry_policy(), ExpiryPolicy::AfterAccess);
+            assert!(lru_cache
+                .set_expiry_policy(ExpiryPolicy::AfterWrite)
+                .is_empty());
+            let _ = lru_cache.insert(0, 0);
+
+            sleep(60);
+            assert_eq!(lru_cache.get(&0), Some(&0));
+            sleep(60);
+            assert_eq!(lru_cache.get(&0), None);
+        }
+
+        #[test]
+        fn after_write_is_reset_by_replacing_the_value() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.set_expiry_policy(ExpiryPolicy::AfterWrite);
+            let _ = lru_cache.insert(0, 0);
+
+            sleep(60);
+            assert_eq!(lru_cache.insert(0, 1), Some(0));
+            sleep(60);
+            assert_eq!(lru_cache.get(&0), Some(&1));
+        }
+
+        #[test]
+        fn after_write_or_idle() {
+            let ttl = Duration::from_millis(200);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let idle = Duration::from_millis(50);
+            let _ = lru_cache.set_expiry_policy(ExpiryPolicy::AfterWriteOrIdle(idle));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+
+            for _ in 0..3 {
+                sleep(30);
+                assert_eq!(lru_cache.get(&0), Some(&0));
+            }
+            assert!(!lru_cache.contains_key(&1));
+
+            for _ in 0..5 {
+                sleep(30);
+                let _ = lru_cache.get(&0);
+            }
+            assert!(lru_cache.is_empty());
+        }
+
+        #[test]
+        fn switching_policy_evicts_expired_entries() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            sleep(60);
+            let _ = lru_cache.get(&0);
+            sleep(60);
+
+            assert_eq!(
+                lru_cache.set_expiry_policy(ExpiryPolicy::AfterWrite),
+                vec![(0, 0)]
+            );
+        }
+    }
+
+    mod read_variants {
+        use super::*;
+
+        #[test]
+        fn get_and_promote_does_not_extend_life() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 2);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+
+            sleep(60);
+            assert_eq!(lru_cache.get_and_promote(&0), Some(&0));
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&0, &0), (&1, &1)]
+            );
+            sleep(60);
+            assert_eq!(lru_cache.get_and_promote(&0), None);
+        }
+
+        #[test]
+        fn get_and_refresh_does_not_promote() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 2);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+
+            sleep(60);
+            assert_eq!(lru_cache.get_and_refresh(&0), Some(&0));
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&1, &1), (&0, &0)]
+            );
+            sleep(60);
+            assert_eq!(lru_cache.peek(&0), Some(&0));
+            assert_eq!(lru_cache.peek(&1), None);
+        }
+    }
+
+    mod peek_mut {
+        use super::*;
+
+        #[test]
+        fn neither_promotes_nor_refreshes() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 2);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+
+            sleep(60);
+            *lru_cache.peek_mut(&0).unwrap() = 10;
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&1, &1), (&0, &10)]
+            );
+            sleep(60);
+            assert_eq!(lru_cache.peek_mut(&0), None);
+        }
+
+        #[test]
+        fn leaves_expired_entries_in_place() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_millis(200)));
+            sleep(60);
+
+            assert_eq!(lru_cache.peek_mut(&1), Some(&mut 1));
+            assert_eq!(lru_cache.map.len(), 2);
+        }
+    }
+
+    mod extract_if {
+        use super::*;
+
+        #[test]
+        fn removes_matching_entries() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..6).map(|i| (i, i * 10)));
+
+            let extracted: Vec<_> = lru_cache.extract_if(|key, _| key % 2 == 0).collect();
+
+            assert_eq!(extracted, vec![(0, 0), (2, 20), (4, 40)]);
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&5, &50), (&3, &30), (&1, &10)]
+            );
+        }
+
+        #[test]
+        fn is_lazy() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..6).map(|i| (i, i)));
+
+            assert_eq!(lru_cache.extract_if(|_, _| true).next(), Some((0, 0)));
+            assert_eq!(lru_cache.len(), 5);
+        }
+
+        #[test]
+        fn skips_expired_entries() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_millis(200)));
+            sleep(60);
+
+            let mut visited = Vec::new();
+            let extracted: Vec<_> = lru_cache
+                .extract_if(|key, _| {
+                    visited.push(*key);
+                    true
+                })
+                .collect();
+
+            assert_eq!(visited, vec![1]);
+            assert_eq!(extracted, vec![(1, 1)]);
+            assert!(lru_cache.map.is_empty());
+        }
+    }
+
+    mod split_off_and_append {
+        use super::*;
+
+        #[test]
+        fn split_off() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..6).map(|i| (i, i)));
+            let _ = lru_cache.get(&4);
+            let _ = lru_cache.get(&1);
+
+            let upper = lru_cache.split_off(&3);
+
+            assert_eq!(upper.capacity(), 10);
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&1, &1), (&2, &2), (&0, &0)]
+            );
+            assert_eq!(
+                upper.peek_iter().collect::<Vec<_>>(),
+                vec![(&4, &4), (&5, &5), (&3, &3)]
+            );
+        }
+
+        #[test]
+        fn split_off_keeps_timestamps() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(60);
+
+            let mut upper = lru_cache.split_off(&1);
+            sleep(60);
+
+            assert!(lru_cache.is_empty());
+            assert!(upper.is_empty());
+            assert_eq!(upper.get(&1), None);
+        }
+
+        #[test]
+        fn split_by() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..6).map(|i| (i, i * 10)));
+            let _ = lru_cache.get(&4);
+            let _ = lru_cache.get(&1);
+
+            let odd = lru_cache.split_by(|key, value| key % 2 == 1 && *value != 30);
+
+            assert_eq!(odd.capacity(), 10);
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&4, &40), (&3, &30), (&2, &20), (&0, &0)]
+            );
+            assert_eq!(
+                odd.peek_iter().collect::<Vec<_>>(),
+                vec![(&1, &10), (&5, &50)]
+            );
+        }
+
+        #[test]
+        fn split_by_keeps_timestamps() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(60);
+
+            let mut ones = lru_cache.split_by(|key, _| *key == 1);
+            sleep(60);
+
+            assert!(lru_cache.is_empty());
+            assert_eq!(ones.get(&1), None);
+        }
+
+        #[test]
+        fn append_orders_by_recency() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            let mut other = LruCache::<usize, usize>::with_capacity(10);
+            let _ = lru_cache.insert(0, 0);
+            sleep(10);
+            let _ = other.insert(1, 1);
+            sleep(10);
+            let _ = lru_cache.insert(2, 2);
+            sleep(10);
+            let _ = other.insert(3, 3);
+
+            assert!(lru_cache.append(&mut other).is_empty());
+
+            assert!(other.is_empty());
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&3, &3), (&2, &2), (&1, &1), (&0, &0)]
+            );
+        }
+
+        #[test]
+        fn append_keeps_most_recent_duplicate() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            let mut other = LruCache::<usize, usize>::with_capacity(10);
+            let _ = lru_cache.insert(0, 0);
+            let _ = other.insert(1, 10);
+            sleep(10);
+            let _ = other.insert(0, 100);
+            let _ = lru_cache.insert(1, 1);
+
+            let mut dropped = lru_cache.append(&mut other);
+            dropped.sort();
+
+            assert_eq!(dropped, vec![(0, 0), (1, 10)]);
+            assert_eq!(lru_cache.peek(&0), Some(&100));
+            assert_eq!(lru_cache.peek(&1), Some(&1));
+        }
+
+        #[test]
+        fn append_evicts_over_capacity() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let mut other = LruCache::<usize, usize>::with_capacity(2);
+            let _ = lru_cache.insert(0, 0);
+            sleep(10);
+            let _ = other.insert(1, 1);
+            sleep(10);
+            let _ = lru_cache.insert(2, 2);
+
+            assert_eq!(lru_cache.append(&mut other), vec![(0, 0)]);
+            assert_eq!(lru_cache.len(), 2);
+        }
+    }
+
+    mod pop_expired {
+        use super::*;
+
+        #[test]
+        fn pops_one_entry_at_a_time_in_expiry_order() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            sleep(10);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_millis(20)));
+            assert!(lru_cache.set_entry_ttl(&2, Duration::from_millis(200)));
+            assert_eq!(lru_cache.pop_expired(), None);
+
+            sleep(60);
+
+            assert_eq!(lru_cache.pop_expired(), Some((1, 1)));
+            assert_eq!(lru_cache.map.len(), 2);
+            assert_eq!(lru_cache.pop_expired(), Some((0, 0)));
+            assert_eq!(lru_cache.pop_expired(), None);
+            assert_eq!(lru_cache.peek(&2), Some(&2));
+        }
+    }
+
+    mod at_methods {
+        use super::*;
+
+        #[test]
+        fn drive_time_explicitly() {
+            let ttl = Duration::from_secs(10);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let start = Instant::now();
+            assert_eq!(lru_cache.insert_at(0, 0, start), None);
+            assert_eq!(lru_cache.insert_at(1, 1, start), None);
+
+            let later = start + Duration::from_secs(8);
+            assert_eq!(lru_cache.get_at(&1, later), Some(&1));
+            let expired_at = start + Duration::from_secs(11);
+            assert_eq!(lru_cache.peek_at(&0, expired_at), None);
+            assert_eq!(lru_cache.peek_at(&1, expired_at), Some(&1));
+            assert_eq!(lru_cache.peek(&0), Some(&0));
+
+            assert_eq!(lru_cache.remove_expired_at(expired_at), vec![(0, 0)]);
+            assert_eq!(lru_cache.insert_at(1, 2, expired_at), Some(1));
+            assert_eq!(
+                lru_cache.remove_expired_at(expired_at + ttl + Duration::from_secs(1)),
+                vec![(1, 2)]
+            );
+            assert!(lru_cache.is_empty());
+        }
+    }
+
+    mod expiring_within {
+        use super::*;
+
+        #[test]
+        fn yields_entries_close_to_expiry_soonest_first() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert_permanent(2, 2);
+            let _ = lru_cache.insert(3, 3);
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_millis(30)));
+            assert!(lru_cache.set_entry_ttl(&3, Duration::from_millis(10)));
+            sleep(20);
+
+            let items: Vec<_> = lru_cache
+                .expiring_within(Duration::from_millis(150))
+                .collect();
+            let keys: Vec<_> = items.iter().map(|(key, ..)| **key).collect();
+            assert_eq!(keys, vec![1, 0]);
+            assert!(items[0].2 <= Duration::from_millis(10));
+            assert_eq!(
+                lru_cache.expiring_within(Duration::from_millis(40)).count(),
+                1
+            );
+            assert_eq!(lru_cache.expiring_within(Duration::MAX).count(), 2);
+        }
+    }
+
+    mod notify_insert_full {
+        use super::*;
+
+        #[test]
+        fn reports_capacity_evictions() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+
+            assert_eq!(
+                lru_cache.notify_insert_full(3, 3),
+                InsertOutcome {
+                    replaced: None,
+                    expired: vec![],
+                    evicted: vec![(1, 1)],
+                    rejected: None,
+                }
+            );
+            assert_eq!(
+                lru_cache.notify_insert_full(3, 4),
+                InsertOutcome {
+                    replaced: Some(3),
+                    expired: vec![],
+                    evicted: vec![],
+                    rejected: None,
+                }
+            );
+        }
+
+        #[test]
+        fn reports_expired_entries() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 2);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(60);
+
+            assert_eq!(
+                lru_cache.notify_insert_full(2, 2),
+                InsertOutcome {
+                    replaced: None,
+                    expired: vec![(0, 0), (1, 1)],
+                    evicted: vec![],
+                    rejected: None,
+                }
+            );
+        }
+    }
+
+    mod expiry_budget {
+        use super::*;
+
+        #[test]
+        fn limits_removals_per_operation() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            lru_cache.set_expiry_budget(Some(2));
+            assert_eq!(lru_cache.expiry_budget(), Some(2));
+            lru_cache.extend((0..5).map(|i| (i, i)));
+            sleep(60);
+
+            let (_, expired) = lru_cache.notify_insert(10, 10);
+            assert_eq!(expired, vec![(0, 0), (1, 1)]);
+            assert_eq!(lru_cache.len(), 1);
+            assert_eq!(lru_cache.get(&4), None);
+            assert_eq!(lru_cache.map.len(), 2);
+            let (_, expired) = lru_cache.notify_get(&10);
+            assert_eq!(expired, vec![(4, 4)]);
+        }
+
+        #[test]
+        fn expired_leftovers_are_not_reported_as_replaced() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            lru_cache.set_expiry_budget(Some(1));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(60);
+
+            let (old_value, expired) = lru_cache.notify_insert(1, 10);
+            assert_eq!(old_value, None);
+            assert_eq!(expired, vec![(0, 0), (1, 1)]);
+        }
+
+        #[test]
+        fn reconfiguring_removes_all_expired_entries() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            lru_cache.set_expiry_budget(Some(1));
+            lru_cache.extend((0..5).map(|i| (i, i)));
+            sleep(60);
+
+            assert_eq!(lru_cache.set_time_to_live(Some(ttl)).len(), 5);
+            assert!(lru_cache.map.is_empty());
+        }
+    }
+
+    mod pause_expiry {
+        use super::*;
+
+        #[test]
+        fn entries_outlive_their_ttl_while_paused() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(30);
+            lru_cache.pause_expiry();
+            assert!(lru_cache.is_expiry_paused());
+            assert_eq!(lru_cache.remaining_ttl(&0), None);
+            assert_eq!(lru_cache.next_expiry(), None);
+
+            sleep(60);
+            assert_eq!(lru_cache.len(), 2);
+            let _ = lru_cache.get(&1);
+            lru_cache.resume_expiry();
+            assert!(!lru_cache.is_expiry_paused());
+            assert!(lru_cache.remaining_ttl(&0).unwrap() <= Duration::from_millis(20));
+            assert!(lru_cache.remaining_ttl(&1).unwrap() > Duration::from_millis(30));
+
+            sleep(30);
+            assert_eq!(lru_cache.peek(&0), None);
+            assert_eq!(lru_cache.peek(&1), Some(&1));
+        }
+
+        #[test]
+        fn removes_expired_entries_when_pausing() {
+            let ttl = Duration::from_millis(20);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            sleep(30);
+            lru_cache.pause_expiry();
+            assert!(lru_cache.map.is_empty());
+            lru_cache.resume_expiry();
+            lru_cache.resume_expiry();
+            assert!(lru_cache.is_empty());
+        }
+    }
+
+    mod expiry_index {
+        use super::*;
+
+        fn assert_index_in_sync(lru_cache: &LruCache<usize, usize>) {
+            let expiry = lru_cache.expiry();
+            let mut deadlines: Vec<_> = lru_cache
+                .map
+                .iter()
+                .filter_map(|(key, entry)| Some((entry.expiry_time(expiry)?, *key)))
+                .collect();
+            deadlines.sort();
+            for entry in lru_cache.map.values() {
+                assert_eq!(entry.deadline, entry.expiry_time(expiry));
+            }
+            assert_eq!(
+                lru_cache
+                    .expiries
+                    .expired(Instant::now() + Duration::from_secs(60))
+                    .count(),
+                deadlines.len()
+            );
+            assert_eq!(lru_cache.next_expiry(), deadlines.first().map(|(t, _)| *t));
+        }
+
+        #[test]
+        fn stays_in_sync_with_the_entries() {
+            let ttl = Duration::from_millis(30);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 8);
+            for i in 0..40 {
+                let key = i % 11;
+                match i % 7 {
+                    0 => {
+                        let _ = lru_cache.get(&key);
+                    }
+                    1 => {
+                        let _ = lru_cache.set_entry_ttl(&key, Duration::from_millis(50));
+                    }
+                    2 => {
+                        let _ = lru_cache.pin(&key);
+                    }
+                    3 => {
+                        let _ = lru_cache.remove(&key);
+                    }
+                    4 => {
+                        let _ = lru_cache.iter().count();
+                    }
+                    _ => {
+                        let _ = lru_cache.insert(key, i);
+                    }
+                }
+                if i % 10 == 0 {
+                    sleep(10);
+                }
+                assert_index_in_sync(&lru_cache);
+            }
+
+            let _ = lru_cache.set_expiry_policy(ExpiryPolicy::AfterWrite);
+            assert_index_in_sync(&lru_cache);
+            let upper = lru_cache.split_off(&5);
+            assert_index_in_sync(&lru_cache);
+            assert_index_in_sync(&upper);
+        }
+
+        #[test]
+        fn expired_entries_are_found_regardless_of_recency() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.set_expiry_policy(ExpiryPolicy::AfterWrite);
+            let _ = lru_cache.insert(0, 0);
+            sleep(60);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.get(&0);
+            sleep(60);
+
+            assert_eq!(lru_cache.len(), 1);
+            let (_, expired) = lru_cache.notify_get(&1);
+            assert_eq!(expired, vec![(0, 0)]);
+        }
+
+        #[test]
+        fn sweeps_keep_the_order_of_the_remaining_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            lru_cache.extend((0..6).map(|i| (i, i)));
+            for key in &[1, 3] {
+                assert!(lru_cache.set_entry_ttl(key, Duration::from_millis(10)));
+            }
+            sleep(20);
+
+            let (_, expired) = lru_cache.notify_get(&10);
+            assert_eq!(expired, vec![(1, 1), (3, 3)]);
+            assert_eq!(lru_cache.list, vec![0, 2, 4, 5]);
+            assert_index_in_sync(&lru_cache);
+        }
+    }
+
+    mod segmented {
+        use super::*;
+
+        fn segmented_cache(capacity: usize) -> LruCache<usize, usize> {
+            let policy = EvictionPolicy::Segmented {
+                protected_percent: 50,
+            };
+            policy_cache(policy, capacity)
+        }
+
+        #[test]
+        fn scans_dont_flush_protected_entries() {
+            let mut lru_cache = segmented_cache(4);
+            lru_cache.extend((0..2).map(|i| (i, i)));
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.get(&1);
+
+            lru_cache.extend((10..20).map(|i| (i, i)));
+
+            assert!(lru_cache.contains_key(&0));
+            assert!(lru_cache.contains_key(&1));
+            assert_eq!(lru_cache.len(), 4);
+        }
+
+        #[test]
+        fn protected_overflow_is_demoted_to_probation() {
+            let mut lru_cache = segmented_cache(4);
+            lru_cache.extend((0..3).map(|i| (i, i)));
+            for key in 0..3 {
+                let _ = lru_cache.get(&key);
+            }
+            assert_eq!(lru_cache.segments.frequent, 2);
+            assert_eq!(lru_cache.map[&0].segment, Segment::Recent);
+
+            let outcome = lru_cache.notify_insert_full(3, 3);
+            assert!(outcome.evicted.is_empty());
+            let outcome = lru_cache.notify_insert_full(4, 4);
+            assert_eq!(outcome.evicted, vec![(0, 0)]);
+        }
+
+        #[test]
+        fn counts_follow_removals() {
+            let mut lru_cache = segmented_cache(4);
+            lru_cache.extend((0..2).map(|i| (i, i)));
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.get(&1);
+            assert_eq!(lru_cache.remove(&1), Some(1));
+            assert_eq!(lru_cache.extract_if(|_, _| true).count(), 1);
+
+            assert_eq!(lru_cache.segments.frequent, 0);
+        }
+    }
+
+    mod admission {
+        use super::*;
+
+        #[test]
+        fn rare_keys_dont_displace_popular_ones() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            lru_cache.set_admission_policy(AdmissionPolicy::TinyLfu);
+            assert_eq!(lru_cache.admission_policy(), AdmissionPolicy::TinyLfu);
+            lru_cache.extend((0..2).map(|i| (i, i)));
+            for _ in 0..3 {
+                let _ = lru_cache.get(&0);
+                let _ = lru_cache.get(&1);
+            }
+
+            let outcome = lru_cache.notify_insert_full(2, 2);
+
+            assert_eq!(outcome.rejected, Some(2));
+            assert!(outcome.evicted.is_empty());
+            assert!(!lru_cache.contains_key(&2));
+            assert_eq!(lru_cache.len(), 2);
+        }
+
+        #[test]
+        fn frequently_seen_keys_are_admitted() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            lru_cache.set_admission_policy(AdmissionPolicy::TinyLfu);
+            lru_cache.extend((0..2).map(|i| (i, i)));
+
+            for _ in 0..3 {
+                let _ = lru_cache.insert(2, 2);
+            }
+
+            assert!(lru_cache.contains_key(&2));
+            assert!(!lru_cache.contains_key(&0));
+        }
+
+        #[test]
+        fn entry_api_bypasses_admission() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            lru_cache.set_admission_policy(AdmissionPolicy::TinyLfu);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.get(&0);
+
+            assert_eq!(*lru_cache.entry(1).or_insert(1), 1);
+            assert_eq!(lru_cache.len(), 1);
+        }
+
+        /// Returns a full W-TinyLFU cache whose entries have all been read `reads` times.
+        fn window_cache(reads: usize) -> LruCache<usize, usize> {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(100);
+            lru_cache.set_admission_policy(AdmissionPolicy::WindowTinyLfu);
+            assert_eq!(lru_cache.admission_policy(), AdmissionPolicy::WindowTinyLfu);
+            lru_cache.extend((0..100).map(|i| (i, i)));
+            for _ in 0..reads {
+                for i in 0..100 {
+                    let _ = lru_cache.get(&i);
+                }
+            }
+            lru_cache
+        }
+
+        /// Returns the number of hits on popular keys read between scans of one-off keys.
+        fn popular_hits(policy: AdmissionPolicy) -> usize {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(100);
+            lru_cache.set_admission_policy(policy);
+            let mut hits = 0;
+            for round in 0..10 {
+                for i in 0..50 {
+                    match lru_cache.get(&i) {
+                        Some(_) => hits += 1,
+                        None => {
+                            let _ = lru_cache.insert(i, i);
+                        }
+                    }
+                }
+                for i in 0..200 {
+                    let outcome = lru_cache.notify_insert_full(1000 + round * 200 + i, i);
+                    assert_eq!(outcome.rejected, None);
+                }
+            }
+            hits
+        }
+
+        #[test]
+        fn scans_only_pass_through_the_window() {
+            assert_eq!(popular_hits(AdmissionPolicy::Always), 0);
+            assert!(popular_hits(AdmissionPolicy::WindowTinyLfu) > 9 * 40);
+        }
+
+        #[test]
+        fn keys_seen_in_the_window_are_kept() {
+            let mut lru_cache = window_cache(3);
+            let _ = lru_cache.insert(100, 100);
+            for _ in 0..15 {
+                let _ = lru_cache.get(&100);
+            }
+
+            let outcome = lru_cache.notify_insert_full(101, 101);
+
+            assert_eq!(outcome.evicted.len(), 1);
+            assert!(lru_cache.contains_key(&100));
+            assert!(lru_cache.contains_key(&101));
+            assert_eq!(lru_cache.len(), 100);
+        }
+    }
+
+    mod adaptive {
+        use super::*;
+
+        fn adaptive_cache(capacity: usize) -> LruCache<usize, usize> {
+            policy_cache(EvictionPolicy::Adaptive, capacity)
+        }
+
+        #[test]
+        fn scans_dont_flush_frequent_entries() {
+            let mut lru_cache = adaptive_cache(4);
+            lru_cache.extend((0..2).map(|i| (i, i)));
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.get(&1);
+
+            lru_cache.extend((10..20).map(|i| (i, i)));
+
+            assert!(lru_cache.contains_key(&0));
+            assert!(lru_cache.contains_key(&1));
+            assert_eq!(lru_cache.len(), 4);
+        }
+
+        #[test]
+        fn recently_evicted_keys_grow_their_segment() {
+            let mut lru_cache = adaptive_cache(2);
+            lru_cache.extend((0..3).map(|i| (i, i)));
+            assert_eq!(lru_cache.ghosts.target, 0);
+
+            let outcome = lru_cache.notify_insert_full(0, 0);
+
+            assert_eq!(outcome.evicted, vec![(1, 1)]);
+            assert_eq!(lru_cache.ghosts.target, 1);
+            assert_eq!(lru_cache.map[&0].segment, Segment::Frequent);
+            assert_eq!(lru_cache.segments.frequent, 1);
+        }
+
+        #[test]
+        fn adapts_towards_recency() {
+            let mut lru_cache = adaptive_cache(2);
+            lru_cache.extend((0..2).map(|i| (i, i)));
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.get(&1);
+            // The recent segment is empty, so a frequent entry makes room for 2.
+            let _ = lru_cache.insert(2, 2);
+            assert_eq!(lru_cache.ghosts.frequent.len(), 1);
+            // The recent segment is over its zero target, so 2 makes room for 3.
+            let _ = lru_cache.insert(3, 3);
+            assert_eq!(lru_cache.ghosts.recent.len(), 1);
+
+            // A miss on the recently evicted recent entry raises the target.
+            let _ = lru_cache.insert(2, 2);
+            assert_eq!(lru_cache.ghosts.target, 1);
+        }
+    }
+
+    mod two_queue {
+        use super::*;
+
+        fn two_queue_cache(capacity: usize) -> LruCache<usize, usize> {
+            policy_cache(EvictionPolicy::TwoQueue { recent_percent: 25 }, capacity)
+        }
+
+        #[test]
+        fn hits_dont_reorder_entries_seen_once() {
+            let mut lru_cache = two_queue_cache(4);
+            lru_cache.extend((0..4).map(|i| (i, i)));
+            let _ = lru_cache.get(&0);
+
+            let outcome = lru_cache.notify_insert_full(4, 4);
+
+            assert_eq!(outcome.evicted, vec![(0, 0)]);
+        }
+
+        #[test]
+        fn reinserted_keys_join_the_main_queue() {
+            let mut lru_cache = two_queue_cache(4);
+            lru_cache.extend((0..5).map(|i| (i, i)));
+            assert!(!lru_cache.contains_key(&0));
+
+            let _ = lru_cache.insert(0, 0);
+            assert_eq!(lru_cache.map[&0].segment, Segment::Frequent);
+
+            // A scan only cycles through the first-in first-out queue.
+            lru_cache.extend((10..20).map(|i| (i, i)));
+            assert!(lru_cache.contains_key(&0));
+        }
+    }
+
+    mod clock {
+        use super::*;
+
+        fn clock_cache(capacity: usize) -> LruCache<usize, usize> {
+            policy_cache(EvictionPolicy::Clock, capacity)
+        }
+
+        #[test]
+        fn reads_only_mark_entries() {
+            let mut lru_cache = clock_cache(3);
+            lru_cache.extend((0..3).map(|i| (i, i)));
+            let _ = lru_cache.get(&0);
+
+            assert!(lru_cache.map[&0].referenced);
+            assert_eq!(lru_cache.list, vec![0, 1, 2]);
+        }
+
+        #[test]
+        fn referenced_entries_get_a_second_chance() {
+            let mut lru_cache = clock_cache(3);
+            lru_cache.extend((0..3).map(|i| (i, i)));
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.get(&1);
+
+            assert_eq!(lru_cache.notify_insert_full(3, 3).evicted, vec![(2, 2)]);
+            assert!(!lru_cache.map[&0].referenced);
+            assert_eq!(lru_cache.notify_insert_full(4, 4).evicted, vec![(0, 0)]);
+        }
+    }
+
+    mod mru {
+        use super::*;
+
+        fn mru_cache(capacity: usize) -> LruCache<usize, usize> {
+            policy_cache(EvictionPolicy::Mru, capacity)
+        }
+
+        #[test]
+        fn evicts_most_recently_used() {
+            let mut lru_cache = mru_cache(3);
+            lru_cache.extend((0..3).map(|i| (i, i)));
+            let _ = lru_cache.get(&0);
+
+            assert_eq!(lru_cache.notify_insert_full(3, 3).evicted, vec![(0, 0)]);
+            assert_eq!(lru_cache.notify_insert_full(4, 4).evicted, vec![(3, 3)]);
+            assert_eq!(lru_cache.list, vec![1, 2, 4]);
+        }
+
+        #[test]
+        fn skips_pinned_entries() {
+            let mut lru_cache = mru_cache(2);
+            lru_cache.extend((0..2).map(|i| (i, i)));
+            assert!(lru_cache.pin(&1));
+
+            assert_eq!(lru_cache.notify_insert_full(2, 2).evicted, vec![(0, 0)]);
+        }
+    }
+
+    mod random {
+        use super::*;
+
+        fn random_cache(capacity: usize) -> LruCache<usize, usize> {
+            let mut lru_cache = policy_cache(EvictionPolicy::Random, capacity);
+            lru_cache.extend((0..capacity).map(|i| (i, i)));
+            lru_cache
+        }
+
+        #[test]
+        fn evicts_any_entry() {
+            let mut evicted = BTreeSet::new();
+            for _ in 0..200 {
+                let mut lru_cache = random_cache(4);
+                for (key, _) in lru_cache.notify_insert_full(4, 4).evicted {
+                    let _ = evicted.insert(key);
+                }
+                assert_eq!(lru_cache.len(), 4);
+            }
+            assert_eq!(evicted, (0..4).collect());
+        }
+
+        #[test]
+        fn skips_pinned_entries() {
+            for _ in 0..20 {
+                let mut lru_cache = random_cache(2);
+                assert!(lru_cache.pin(&1));
+                assert_eq!(lru_cache.notify_insert_full(2, 2).evicted, vec![(0, 0)]);
+            }
+        }
+    }
+
+    mod gdsf {
+        use super::*;
+
+        fn gdsf_cache<Value>(capacity: usize) -> LruCache<usize, Value> {
+            policy_cache(EvictionPolicy::GreedyDualSizeFrequency, capacity)
+        }
+
+        #[test]
+        fn evicts_cheap_entries_first() {
+            let mut lru_cache = gdsf_cache(3);
+            let _ = lru_cache.insert_with_cost(0, 0, 100, 1);
+            let _ = lru_cache.insert_with_cost(1, 1, 1, 1);
+            let _ = lru_cache.insert_with_cost(2, 2, 50, 1);
+
+            assert_eq!(lru_cache.notify_insert_full(3, 3).evicted, vec![(1, 1)]);
+            assert_eq!(lru_cache.notify_insert_full(4, 4).evicted, vec![(3, 3)]);
+        }
+
+        #[test]
+        fn evicts_big_entries_first() {
+            let mut lru_cache = gdsf_cache(2);
+            let _ = lru_cache.insert_with_cost(0, 0, 10, 1);
+            let _ = lru_cache.insert_with_cost(1, 1, 10, 20);
+
+            assert_eq!(lru_cache.notify_insert_full(2, 2).evicted, vec![(1, 1)]);
+        }
+
+        #[test]
+        fn hits_raise_priority() {
+            let mut lru_cache = gdsf_cache(2);
+            let _ = lru_cache.insert_with_cost(0, 0, 2, 1);
+            let _ = lru_cache.insert_with_cost(1, 1, 3, 1);
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.get(&0);
+
+            assert_eq!(lru_cache.notify_insert_full(2, 2).evicted, vec![(1, 1)]);
+        }
+
+        #[test]
+        fn evictions_age_the_remaining_entries() {
+            let mut lru_cache = gdsf_cache(2);
+            let _ = lru_cache.insert_with_cost(0, 0, 5, 1);
+            let _ = lru_cache.insert_with_cost(1, 1, 1, 1);
+            for i in 2..7 {
+                let _ = lru_cache.insert(i, i);
+            }
+            // The inflation caught up with the costly entry, which was never hit.
+            assert!(!lru_cache.contains_key(&0));
+        }
+
+        #[test]
+        fn replacing_keeps_the_cost() {
+            let mut lru_cache = gdsf_cache(2);
+            let _ = lru_cache.insert_with_cost(0, 0, 100, 1);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(0, 10);
+
+            assert_eq!(lru_cache.notify_insert_full(2, 2).evicted, vec![(1, 1)]);
+        }
+
+        #[test]
+        fn rejects_entries_above_the_maximum_weight() {
+            let mut lru_cache = gdsf_cache(3);
+            assert_eq!(lru_cache.max_entry_weight(), None);
+            lru_cache.set_max_entry_weight(Some(10));
+            assert_eq!(lru_cache.max_entry_weight(), Some(10));
+            let _ = lru_cache.insert_with_cost(0, 0, 1, 10);
+            let _ = lru_cache.insert_with_cost(1, 1, 1, 1);
+
+            let outcome = lru_cache.notify_insert_with_cost(0, 10, 1, 11);
+            assert_eq!(outcome.rejected, Some(10));
+            assert_eq!(outcome.replaced, None);
+            assert_eq!(lru_cache.peek(&0), Some(&0));
+            assert_eq!(lru_cache.insert_with_cost(2, 2, 1, 100), None);
+            assert!(!lru_cache.contains_key(&2));
+            assert_eq!(lru_cache.len(), 2);
+
+            lru_cache.set_max_entry_weight(None);
+            assert_eq!(lru_cache.insert_with_cost(0, 20, 1, 100), Some(0));
+        }
+
+        #[test]
+        fn weigher_limits_plain_inserts() {
+            let mut lru_cache = LruCache::<usize, Vec<u8>>::with_capacity(10);
+            lru_cache.set_weigher(|_, value: &Vec<u8>| value.len() as u64);
+            lru_cache.set_max_entry_weight(Some(2));
+            let _ = lru_cache.insert(0, vec![0]);
+
+            assert_eq!(lru_cache.insert(1, vec![1; 3]), None);
+            let outcome = lru_cache.notify_insert_full(0, vec![0; 3]);
+            assert_eq!(outcome.rejected, Some(vec![0; 3]));
+            assert_eq!(outcome.replaced, None);
+            assert_eq!(
+                lru_cache.insert_many(vec![(2, vec![2; 3]), (3, vec![3])]),
+                vec![(2, vec![2; 3])]
+            );
+            assert_eq!(lru_cache.insert_permanent(0, vec![0; 3]), None);
+            assert_eq!(lru_cache.insert_until(0, vec![0; 3], Instant::now()), None);
+            lru_cache.extend(vec![(4, vec![4; 3])]);
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&3, &vec![3]), (&0, &vec![0])]
+            );
+            assert!(!lru_cache.is_pinned(&0));
+            assert_eq!(lru_cache.remaining_ttl(&0), None);
+
+            lru_cache.remove_weigher();
+            assert_eq!(lru_cache.insert(1, vec![1; 3]), None);
+            assert!(lru_cache.contains_key(&1));
+        }
+
+        #[test]
+        fn entry_apis_insert_regardless_of_weight() {
+            let mut lru_cache = LruCache::<usize, Vec<u8>>::with_capacity(10);
+            lru_cache.set_weigher(|_, value: &Vec<u8>| value.len() as u64);
+            lru_cache.set_max_entry_weight(Some(2));
+
+            assert_eq!(*lru_cache.entry(0).or_insert(vec![0; 3]), vec![0; 3]);
+            assert_eq!(*lru_cache.get_or_insert(1, vec![1; 3]), vec![1; 3]);
+            assert_eq!(
+                *lru_cache.get_or_insert_with_ttl(2, Duration::from_secs(10), || vec![2; 3]),
+                vec![2; 3]
+            );
+            assert!(lru_cache.try_insert(3, vec![3; 3]).is_ok());
+            assert_eq!(lru_cache.len(), 4);
+        }
+
+        #[test]
+        fn weigher_sizes_entries() {
+            let mut lru_cache = gdsf_cache(2);
+            lru_cache.set_weigher(|_, value: &Vec<u8>| value.len() as u64);
+            let _ = lru_cache.insert(0, vec![0; 100]);
+            let _ = lru_cache.insert(1, vec![1]);
+
+            let outcome = lru_cache.notify_insert_full(2, vec![2]);
+            assert_eq!(outcome.evicted, vec![(0, vec![0; 100])]);
+        }
+    }
+
+    mod reserve {
+        use super::*;
+
+        #[test]
+        fn reserves_room_for_additional_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            lru_cache.reserve(100);
+            assert!(lru_cache.list.capacity() >= 100);
+
+            let allocated = lru_cache.list.capacity();
+            for i in 0..100 {
+                let _ = lru_cache.insert(i, i);
+            }
+            assert_eq!(lru_cache.list.capacity(), allocated);
+            assert_eq!(lru_cache.len(), 100);
+        }
+
+        #[test]
+        fn reserves_no_more_than_capacity() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            for i in 0..5 {
+                let _ = lru_cache.insert(i, i);
+            }
+            lru_cache.reserve(usize::MAX);
+            assert!(lru_cache.list.capacity() >= 10);
+            assert!(lru_cache.list.capacity() < 1000);
+        }
+
+        #[test]
+        fn reports_allocation_failures() {
+            let mut lru_cache = LruCache::<usize, usize>::unbounded();
+            assert!(lru_cache.try_reserve(usize::MAX).is_err());
+            assert!(lru_cache.try_reserve(10).is_ok());
+            assert!(lru_cache.list.capacity() >= 10);
+
+            assert_eq!(lru_cache.try_insert_within_memory(0, 0).unwrap(), None);
+            assert_eq!(lru_cache.try_insert_within_memory(0, 1).unwrap(), Some(0));
+            assert_eq!(lru_cache.peek(&0), Some(&1));
+        }
+    }
+
+    mod get_many {
+        use super::*;
+
+        #[test]
+        fn returns_values_in_key_order() {
+            let mut lru_cache = LruCache::<String, usize>::with_capacity(10);
+            for (i, key) in ["a", "b", "c", "d"].iter().enumerate() {
+                let _ = lru_cache.insert((*key).to_string(), i);
+            }
+
+            let values = lru_cache.get_many(&["c", "x", "a", "c"]);
+            assert_eq!(values, vec![Some(&2), None, Some(&0), Some(&2)]);
+        }
+
+        #[test]
+        fn promotes_hits_like_consecutive_gets() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(5);
+            let mut expected = LruCache::<usize, usize>::with_capacity(5);
+            for i in 0..5 {
+                let _ = lru_cache.insert(i, i);
+                let _ = expected.insert(i, i);
+            }
+
+            let keys = [3, 1, 7, 3, 0];
+            let _ = lru_cache.get_many(&keys.iter().collect::<Vec<_>>());
+            for key in &keys {
+                let _ = expected.get(key);
+            }
+            assert_eq!(lru_cache.list, expected.list);
+            assert_eq!(lru_cache.list, vec![2, 4, 1, 3, 0]);
+        }
+
+        #[test]
+        fn skips_expired_entries() {
+            let time_to_live = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            assert!(lru_cache.set_entry_ttl(&2, Duration::from_secs(10)));
+            sleep(200);
+
+            assert_eq!(lru_cache.get_many(&[&1, &2]), vec![None, Some(&2)]);
+            assert_eq!(lru_cache.len(), 1);
+        }
+
+        #[test]
+        fn follows_the_eviction_policy() {
+            let policy = EvictionPolicy::Segmented {
+                protected_percent: 50,
+            };
+            let mut lru_cache = policy_cache(policy, 4);
+            for i in 0..4 {
+                let _ = lru_cache.insert(i, i);
+            }
+            let _ = lru_cache.get_many(&[&0, &1]);
+            assert_eq!(lru_cache.segments.frequent, 2);
+            assert_eq!(lru_cache.list, vec![2, 3, 0, 1]);
+        }
+    }
+
+    mod insert_many {
+        use super::*;
+
+        #[test]
+        fn inserts_in_order() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            let displaced = lru_cache.insert_many((0..5).map(|i| (i, i * 10)));
+            assert!(displaced.is_empty());
+            assert_eq!(lru_cache.len(), 5);
+            assert_eq!(lru_cache.list, vec![0, 1, 2, 3, 4]);
+            assert_eq!(lru_cache.peek(&3), Some(&30));
+        }
+
+        #[test]
+        fn returns_replaced_and_evicted() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+
+            let displaced = lru_cache.insert_many(vec![(1, 10), (2, 20), (3, 30), (3, 31)]);
+            assert_eq!(displaced, vec![(1, 1), (0, 0), (3, 30)]);
+            assert_eq!(lru_cache.list, vec![1, 2, 3]);
+            assert_eq!(lru_cache.peek(&3), Some(&31));
+        }
+
+        #[test]
+        fn returns_expired() {
+            let time_to_live = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            let _ = lru_cache.insert(0, 0);
+            sleep(200);
+
+            let displaced = lru_cache.insert_many(vec![(1, 1), (2, 2)]);
+            assert_eq!(displaced, vec![(0, 0)]);
+            assert_eq!(lru_cache.len(), 2);
+        }
+
+        #[test]
+        fn returns_rejected() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            lru_cache.set_admission_policy(AdmissionPolicy::TinyLfu);
+            let _ = lru_cache.insert(0, 0);
+            for _ in 0..5 {
+                let _ = lru_cache.get(&0);
+            }
+
+            let displaced = lru_cache.insert_many(vec![(1, 1)]);
+            assert_eq!(displaced, vec![(1, 1)]);
+            assert_eq!(lru_cache.peek(&0), Some(&0));
+        }
+    }
+
+    mod remove_many {
+        use super::*;
+
+        #[test]
+        fn removes_keys_in_order() {
+            let mut lru_cache = LruCache::<String, usize>::with_capacity(10);
+            for (i, key) in ["a", "b", "c", "d"].iter().enumerate() {
+                let _ = lru_cache.insert((*key).to_string(), i);
+            }
+
+            let removed = lru_cache.remove_many(vec!["c", "x", "a", "c"]);
+            assert_eq!(removed, vec![("c".to_string(), 2), ("a".to_string(), 0)]);
+            assert_eq!(lru_cache.len(), 2);
+            assert_eq!(lru_cache.list, vec!["b".to_string(), "d".to_string()]);
+            assert!(lru_cache.expiries.next_expiry().is_none());
+        }
+
+        #[test]
+        fn keeps_bookkeeping_consistent() {
+            let time_to_live = Duration::from_secs(10);
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration_and_capacity(time_to_live, 4);
+            lru_cache.set_eviction_policy(EvictionPolicy::Segmented {
+                protected_percent: 50,
+            });
+            for i in 0..4 {
+                let _ = lru_cache.insert(i, i);
+            }
+            let _ = lru_cache.get(&1);
+            assert_eq!(lru_cache.segments.frequent, 1);
+
+            let removed = lru_cache.remove_many(&[1, 2]);
+            assert_eq!(removed, vec![(1, 1), (2, 2)]);
+            assert_eq!(lru_cache.segments.frequent, 0);
+            assert_eq!(lru_cache.list, vec![0, 3]);
+            assert_eq!(
+                lru_cache
+                    .expiries
+                    .expired(Instant::now() + time_to_live * 2)
+                    .count(),
+                2
+            );
+        }
+    }
+
+    mod ttl_jitter {
+        use super::*;
+
+        #[test]
+        fn spreads_time_to_live_within_bounds() {
+            let time_to_live = Duration::from_secs(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            assert_eq!(lru_cache.ttl_jitter(), None);
+            lru_cache.set_ttl_jitter(Some(0.2));
+            assert_eq!(lru_cache.ttl_jitter(), Some(0.2));
+            lru_cache.extend((0..100).map(|i| (i, i)));
+
+            let ttls: Vec<Duration> = (0..100)
+                .map(|i| lru_cache.map[&i].time_to_live.unwrap())
+                .collect();
+            let (min, max) = (Duration::from_secs(80), Duration::from_secs(120));
+            assert!(ttls.iter().all(|ttl| *ttl > min && *ttl <= max));
+            assert!(ttls.iter().any(|ttl| *ttl != ttls[0]));
+            let remaining = lru_cache.remaining_ttl(&0).unwrap();
+            assert!(remaining <= max && remaining > min - Duration::from_secs(1));
+
+            lru_cache.set_ttl_jitter(None);
+            let _ = lru_cache.insert(0, 0);
+            assert_eq!(lru_cache.map[&0].time_to_live, None);
+        }
+
+        #[test]
+        fn caps_fraction_and_skips_entries_without_ttl() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.set_ttl_jitter(Some(5.0));
+            assert_eq!(lru_cache.ttl_jitter(), Some(1.0));
+            let _ = lru_cache.insert(0, 0);
+            assert_eq!(lru_cache.remaining_ttl(&0), None);
+        }
+
+        #[test]
+        fn saturates_at_the_longest_duration() {
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(Duration::MAX);
+            lru_cache.set_ttl_jitter(Some(0.5));
+            lru_cache.extend((0..100).map(|i| (i, i)));
+
+            assert!((0..100).any(|i| lru_cache.map[&i].time_to_live == Some(Duration::MAX)));
+            assert_eq!(lru_cache.len(), 100);
+        }
+    }
+
+    mod early_expiry {
+        use super::*;
+
+        #[test]
+        fn disabled_by_default() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            assert_eq!(lru_cache.early_expiry(), None);
+
+            lru_cache.set_early_expiry(Some(Duration::from_secs(1)));
+            assert_eq!(lru_cache.early_expiry(), Some(Duration::from_secs(1)));
+            lru_cache.set_early_expiry(None);
+            assert_eq!(lru_cache.early_expiry(), None);
+        }
+
+        #[test]
+        fn fresh_entries_are_hit() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            let _ = lru_cache.set_expiry_policy(ExpiryPolicy::AfterWrite);
+            lru_cache.set_early_expiry(Some(Duration::from_millis(1)));
+            let _ = lru_cache.insert(1, 1);
+
+            for _ in 0..1000 {
+                assert_eq!(lru_cache.get(&1), Some(&1));
+            }
+        }
+
+        #[test]
+        fn entries_close_to_expiry_are_missed() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            let _ = lru_cache.set_expiry_policy(ExpiryPolicy::AfterWrite);
+            lru_cache.set_early_expiry(Some(Duration::from_secs(1000)));
+            let _ = lru_cache.insert(1, 1);
+
+            let misses = (0..100).filter(|_| lru_cache.get(&1).is_none()).count();
+            assert!(misses > 0);
+            assert_eq!(lru_cache.peek(&1), Some(&1));
+            assert_eq!(lru_cache.len(), 1);
+        }
+
+        #[test]
+        fn pinned_entries_are_hit() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            lru_cache.set_early_expiry(Some(Duration::from_secs(1000)));
+            let _ = lru_cache.insert(1, 1);
+            assert!(lru_cache.pin(&1));
+
+            for _ in 0..100 {
+                assert_eq!(lru_cache.get(&1), Some(&1));
+            }
+        }
+
+        #[test]
+        fn entry_apis_refresh_entries_missed_early() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(100));
+            lru_cache.set_early_expiry(Some(Duration::from_secs(100)));
+            let _ = lru_cache.insert(1, 0);
+
+            for round in 1..=200 {
+                match lru_cache.entry(1) {
+                    Entry::Occupied(entry) => *entry.into_mut() += 1,
+                    Entry::Vacant(entry) => *entry.insert(0) += 1,
+                }
+                *lru_cache.get_or_insert(1, 0) += 1;
+                *lru_cache.get_or_insert_with_ttl(1, Duration::from_millis(100), || 0) += 1;
+                let _ = lru_cache.upsert(1, || 1, |value| *value += 1);
+                match lru_cache.try_insert(1, round) {
+                    Ok(value) => assert_eq!(*value, round),
+                    Err(error) => assert!(*error.entry.into_mut() > 0),
+                }
+                assert_eq!(lru_cache.len(), 1);
+            }
+        }
+    }
+
+    mod negative_caching {
+        use super::*;
+
+        #[test]
+        fn misses_expire_after_their_own_ttl() {
+            let mut lru_cache =
+                LruCache::<usize, Option<usize>>::with_expiry_duration(Duration::from_secs(10));
+            assert_eq!(lru_cache.miss_time_to_live(), None);
+            lru_cache.set_miss_time_to_live(Some(Duration::from_millis(50)));
+            assert_eq!(
+                lru_cache.miss_time_to_live(),
+                Some(Duration::from_millis(50))
+            );
+
+            let _ = lru_cache.insert(0, Some(0));
+            let _ = lru_cache.insert_miss(1);
+            let _ = lru_cache.insert(2, None);
+            assert_eq!(lru_cache.get(&1), Some(&None));
+            sleep(100);
+
+            assert_eq!(lru_cache.get(&0), Some(&Some(0)));
+            assert_eq!(lru_cache.get(&1), None);
+            assert_eq!(lru_cache.get(&2), None);
+            assert_eq!(lru_cache.len(), 1);
+        }
+
+        #[test]
+        fn replacing_a_miss_restores_the_cache_wide_ttl() {
+            let mut lru_cache =
+                LruCache::<usize, Option<usize>>::with_expiry_duration(Duration::from_secs(10));
+            lru_cache.set_miss_time_to_live(Some(Duration::from_millis(50)));
+            let _ = lru_cache.insert_miss(0);
+            assert_eq!(lru_cache.insert(0, Some(0)), Some(None));
+            sleep(100);
+
+            assert_eq!(lru_cache.get(&0), Some(&Some(0)));
+        }
+
+        #[test]
+        fn misses_expire_in_capacity_based_caches() {
+            let mut lru_cache = LruCache::<usize, Option<usize>>::with_capacity(10);
+            lru_cache.set_miss_time_to_live(Some(Duration::from_millis(50)));
+            let _ = lru_cache.insert(0, Some(0));
+            let _ = lru_cache.insert_miss(1);
+            sleep(100);
+
+            assert_eq!(lru_cache.peek(&1), None);
+            assert_eq!(lru_cache.len(), 1);
+
+            lru_cache.set_miss_time_to_live(None);
+            let _ = lru_cache.insert_miss(2);
+            sleep(100);
+            assert_eq!(lru_cache.peek(&2), Some(&None));
+        }
+    }
+
+    mod expiry_hook {
+        use super::*;
+
+        struct Token {
+            expires_in: Duration,
+        }
+
+        struct TokenExpiry;
+
+        impl Expiry<usize, Token> for TokenExpiry {
+            fn expire_after_create(&self, _key: &usize, token: &Token) -> Option<Duration> {
+                Some(token.expires_in)
+            }
+
+            fn expire_after_read(&self, key: &usize, _token: &Token) -> Option<Duration> {
+                if *key == 0 {
+                    Some(Duration::from_millis(200))
+                } else {
+                    None
+                }
+            }
+        }
+
+        fn token(millis: u64) -> Token {
+            Token {
+                expires_in: Duration::from_millis(millis),
+            }
+        }
+
+        #[test]
+        fn derives_time_to_live_from_values() {
+            let mut lru_cache =
+                LruCache::<usize, Token>::with_expiry_duration(Duration::from_millis(100));
+            lru_cache.set_expiry_hook(TokenExpiry);
+            let _ = lru_cache.insert(0, token(20));
+            let _ = lru_cache.insert(1, token(300));
+            let _ = lru_cache.insert(2, token(20));
+            let _ = lru_cache.insert(2, token(40));
+            assert!(lru_cache.remaining_ttl(&1).unwrap() > Duration::from_millis(100));
+            assert!(lru_cache.get(&0).is_some());
+
+            sleep(30);
+            assert!(lru_cache.contains_key(&0));
+            assert!(lru_cache.contains_key(&2));
+            sleep(20);
+            assert!(!lru_cache.contains_key(&2));
+
+            lru_cache.remove_expiry_hook();
+            let _ = lru_cache.insert(3, token(0));
+            assert!(lru_cache.remaining_ttl(&3).unwrap() > Duration::from_millis(50));
+        }
+
+        #[test]
+        fn classifies_keys() {
+            let mut lru_cache =
+                LruCache::<String, usize>::with_expiry_duration(Duration::from_millis(100));
+            lru_cache.set_ttl_classifier(|key: &String| {
+                if key.starts_with("peer/") {
+                    Duration::from_millis(20)
+                } else {
+                    Duration::from_millis(300)
+                }
+            });
+            let _ = lru_cache.insert("peer/0".to_string(), 0);
+            let _ = lru_cache.insert("chunk/0".to_string(), 1);
+            assert!(lru_cache.remaining_ttl("chunk/0").unwrap() > Duration::from_millis(100));
+
+            sleep(30);
+            assert!(!lru_cache.contains_key("peer/0"));
+            assert!(lru_cache.contains_key("chunk/0"));
+        }
+    }
+
+    mod loader {
+        use super::*;
+        use std::cell::Cell;
+
+        #[test]
+        fn loads_misses_once() {
+            let loads = Cell::new(0);
+            let loader = |key: &usize| {
+                loads.set(loads.get() + 1);
+                if *key < 10 {
+                    Some(key * 10)
+                } else {
+                    None
+                }
+            };
+            let mut cache = LoadingCache::new(LruCache::with_capacity(2), loader);
+
+            assert_eq!(cache.get(&1), Some(&10));
+            assert_eq!(cache.get(&1), Some(&10));
+            assert_eq!(loads.get(), 1);
+
+            assert_eq!(cache.get(&10), None);
+            assert_eq!(cache.get(&10), None);
+            assert_eq!(loads.get(), 3);
+            assert_eq!(cache.cache().len(), 1);
+
+            if let Some(value) = cache.get_mut(&2) {
+                *value += 1;
+            }
+            assert_eq!(cache.cache().peek(&2), Some(&21));
+            assert_eq!(cache.get(&3), Some(&30));
+            assert!(!cache.cache().contains_key(&1));
+            assert_eq!(loads.get(), 5);
+        }
+
+        #[test]
+        fn reloads_expired_entries() {
+            struct Counter(Cell<usize>);
+
+            impl CacheLoader<usize, usize> for Counter {
+                fn load(&self, _key: &usize) -> Option<usize> {
+                    self.0.set(self.0.get() + 1);
+                    Some(self.0.get())
+                }
+            }
+
+            let time_to_live = Duration::from_millis(100);
+            let mut cache = LoadingCache::new(
+                LruCache::with_expiry_duration(time_to_live),
+                Counter(Cell::new(0)),
+            );
+            assert_eq!(cache.get(&0), Some(&1));
+            let _ = cache.cache_mut().insert(1, 100);
+            sleep(200);
+
+            assert_eq!(cache.get(&0), Some(&2));
+            assert_eq!(cache.get(&1), Some(&3));
+            let (lru_cache, loader) = cache.into_parts();
+            assert_eq!(lru_cache.len(), 2);
+            assert_eq!(loader.0.get(), 3);
+        }
+    }
+
+    mod weak {
+        use super::*;
+        use std::sync::Arc;
+
+        #[test]
+        fn doesnt_keep_values_alive() {
+            let mut cache = WeakLruCache::new(LruCache::with_capacity(10));
+            let (zero, one) = (Arc::new(0), Arc::new(1));
+            assert_eq!(cache.insert(0, &zero), None);
+            let _ = cache.insert(1, &one);
+            let _ = cache.insert(2, &Arc::new(2));
+
+            assert_eq!(cache.get(&0), Some(Arc::clone(&zero)));
+            assert!(!cache.contains_key(&2));
+            assert_eq!(cache.get(&2), None);
+            assert_eq!(cache.len(), 2);
+
+            drop(one);
+            assert_eq!(cache.peek(&1), None);
+            assert_eq!(cache.remove_dropped(), vec![1]);
+            assert_eq!(cache.remove(&0), Some(zero));
+            assert!(cache.is_empty());
+        }
+    }
+
+    mod tagged {
+        use super::*;
+
+        #[test]
+        fn invalidates_entries_by_tag() {
+            let mut cache = TaggedLruCache::new(LruCache::with_capacity(10));
+            assert_eq!(cache.insert(0, "zero", vec!["even", "small"]), None);
+            assert_eq!(cache.insert(1, "one", vec!["small", "odd", "small"]), None);
+            assert_eq!(cache.insert(2, "two", vec!["even"]), None);
+            assert_eq!(cache.insert(3, "three", vec![]), None);
+            assert_eq!(cache.tags(&1), Some(&["odd", "small"][..]));
+
+            assert_eq!(cache.invalidate_tag("small"), vec![(0, "zero"), (1, "one")]);
+            assert_eq!(cache.invalidate_tag("small"), vec![]);
+            assert_eq!(cache.len(), 2);
+
+            // Replacing a value replaces its tags too.
+            assert_eq!(cache.insert(2, "deux", vec!["french"]), Some("two"));
+            assert_eq!(cache.invalidate_tag("even"), vec![]);
+            assert_eq!(cache.get(&2), Some(&"deux"));
+            assert_eq!(cache.invalidate_tag("french"), vec![(2, "deux")]);
+            assert!(cache.contains_key(&3));
+        }
+    }
+
+    mod sort_key {
+        use super::*;
+
+        #[test]
+        fn orders_keys_by_sort_key() {
+            let mut cache = SortKeyCache::new(LruCache::with_capacity(2), |key: &String| {
+                key.to_lowercase()
+            });
+            assert_eq!(cache.insert("Foo".to_string(), 0), None);
+            assert_eq!(cache.insert("bar".to_string(), 1), None);
+
+            assert_eq!(cache.get(&"FOO".to_string()), Some(&0));
+            assert_eq!(
+                cache.peek_key_value(&"foo".to_string()),
+                Some((&"Foo".to_string(), &0))
+            );
+            assert_eq!(
+                cache.insert("BAR".to_string(), 2),
+                Some(("bar".to_string(), 1))
+            );
+            assert_eq!(cache.len(), 2);
+            let keys: Vec<_> = cache.peek_iter().map(|(key, _)| key.as_str()).collect();
+            assert_eq!(keys, vec!["BAR", "Foo"]);
+
+            let _ = cache.insert("baz".to_string(), 3);
+            assert!(!cache.contains_key(&"foo".to_string()));
+            assert_eq!(
+                cache.remove(&"Baz".to_string()),
+                Some(("baz".to_string(), 3))
+            );
+            let (lru_cache, _) = cache.into_parts();
+            assert_eq!(lru_cache.peek("bar"), Some(&("BAR".to_string(), 2)));
+        }
+    }
+
+    mod backing_store {
+        use super::*;
+
+        #[derive(Default)]
+        struct Store {
+            values: BTreeMap<usize, usize>,
+            writes: usize,
+        }
+
+        impl BackingStore<usize, usize> for Store {
+            fn write(&mut self, key: &usize, value: &usize) {
+                let _ = self.values.insert(*key, *value);
+                self.writes += 1;
+            }
+
+            fn delete(&mut self, key: &usize) {
+                let _ = self.values.remove(key);
+            }
+        }
+
+        #[test]
+        fn write_through() {
+            let mut cache = BackedCache::new(
+                LruCache::with_capacity(2),
+                Store::default(),
+                WriteMode::WriteThrough,
+            );
+            assert_eq!(cache.write_mode(), WriteMode::WriteThrough);
+            assert_eq!(cache.insert(0, 0), None);
+            assert_eq!(cache.insert(0, 1), Some(0));
+            assert_eq!(cache.insert(1, 1), None);
+            assert_eq!(cache.insert(2, 2), None);
+            assert_eq!(cache.store().writes, 4);
+            assert_eq!(cache.store().values.len(), 3);
+
+            assert_eq!(cache.remove(&1), Some(1));
+            assert_eq!(cache.get(&2), Some(&2));
+            let (lru_cache, store) = cache.into_parts();
+            assert_eq!(lru_cache.len(), 1);
+            assert_eq!(store.writes, 4);
+            assert_eq!(
+                store.values.into_iter().collect::<Vec<_>>(),
+                vec![(0, 1), (2, 2)]
+            );
+        }
+
+        #[test]
+        fn write_back_on_eviction_and_flush() {
+            let mut cache = BackedCache::new(
+                LruCache::with_capacity(2),
+                Store::default(),
+                WriteMode::WriteBack,
+            );
+            let _ = cache.insert(0, 0);
+            let _ = cache.insert(0, 1);
+            let _ = cache.insert(1, 1);
+            assert_eq!(cache.store().writes, 0);
+
+            let _ = cache.insert(2, 2);
+            assert_eq!(cache.store().writes, 1);
+            assert_eq!(cache.store().values.get(&0), Some(&1));
+
+            assert_eq!(cache.remove(&1), Some(1));
+            cache.flush();
+            assert_eq!(cache.store().writes, 2);
+            cache.flush();
+            assert_eq!(cache.store().writes, 2);
+            assert_eq!(
+                cache.store().values.iter().collect::<Vec<_>>(),
+                vec![(&0, &1), (&2, &2)]
+            );
+        }
+
+        #[test]
+        fn write_back_on_expiry() {
+            let time_to_live = Duration::from_millis(100);
+            let mut cache = BackedCache::new(
+                LruCache::with_expiry_duration(time_to_live),
+                Store::default(),
+                WriteMode::WriteBack,
+            );
+            let _ = cache.insert(0, 0);
+            let _ = cache.insert(1, 1);
+            sleep(200);
+
+            assert_eq!(cache.get(&0), None);
+            assert_eq!(cache.store().writes, 2);
+            assert_eq!(cache.cache().len(), 0);
+
+            let (_, store) = cache.into_parts();
+            assert_eq!(store.writes, 2);
+        }
+
+        #[test]
+        fn write_back_rejected_values() {
+            let mut lru_cache = LruCache::with_capacity(1);
+            lru_cache.set_admission_policy(AdmissionPolicy::TinyLfu);
+            let mut cache = BackedCache::new(lru_cache, Store::default(), WriteMode::WriteBack);
+            let _ = cache.insert(0, 0);
+            for _ in 0..5 {
+                let _ = cache.get(&0);
+            }
+
+            let _ = cache.insert(1, 1);
+            assert_eq!(cache.store().values.get(&1), Some(&1));
+            assert!(cache.cache().contains_key(&0));
+        }
+    }
+
+    mod snapshot {
+        use super::*;
+
+        #[test]
+        fn round_trip_preserves_recency_and_ttl() {
+            let time_to_live = Duration::from_millis(200);
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration_and_capacity(time_to_live, 10);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_secs(10)));
+            assert!(lru_cache.pin(&2));
+            let _ = lru_cache.get(&0);
+
+            let snapshot = lru_cache.snapshot();
+            assert_eq!(snapshot.capacity, 10);
+            assert_eq!(snapshot.time_to_live, Some(time_to_live));
+            assert_eq!(
+                snapshot.entries.iter().map(|e| e.key).collect::<Vec<_>>(),
+                vec![1, 2, 0]
+            );
+            assert_eq!(snapshot.entries[1].remaining_ttl, None);
+            assert!(snapshot.entries[0].remaining_ttl > Some(time_to_live));
+
+            let restored = std::thread::spawn(move || LruCache::from_snapshot(snapshot))
+                .join()
+                .unwrap();
+            assert_eq!(restored.list, vec![1, 2, 0]);
+            assert!(restored.is_pinned(&2));
+            assert_eq!(restored.remaining_ttl(&2), None);
+            assert!(restored.remaining_ttl(&0) <= Some(time_to_live));
+            sleep(300);
+
+            assert_eq!(
+                restored.peek_iter().collect::<Vec<_>>(),
+                vec![(&2, &2), (&1, &1)]
+            );
+        }
+
+        #[test]
+        fn export_and_import_carry_remaining_ttl() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(100));
+            lru_cache.extend((0..3).map(|i| (i, i)));
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_millis(20)));
+            assert!(lru_cache.pin(&2));
+
+            let exported = lru_cache.export();
+            assert_eq!(
+                exported.iter().map(|(key, _, _)| *key).collect::<Vec<_>>(),
+                vec![0, 1, 2]
+            );
+            assert!(exported[0].2 > Some(Duration::from_millis(20)));
+            assert!(exported[1].2 <= Some(Duration::from_millis(20)));
+            assert_eq!(exported[2].2, None);
+
+            let mut imported = LruCache::<usize, usize>::with_capacity(10);
+            imported.import(exported);
+            assert_eq!(imported.list, vec![0, 1, 2]);
+            sleep(30);
+            assert!(!imported.contains_key(&1));
+            assert!(imported.contains_key(&0));
+            assert!(imported.contains_key(&2));
+            sleep(100);
+            assert!(!imported.contains_key(&0));
+            assert!(imported.contains_key(&2));
+        }
+
+        #[test]
+        fn import_keeps_non_expiring_entries() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..2).map(|i| (i, i)));
+            let exported = lru_cache.export();
+            assert!(exported
+                .iter()
+                .all(|(_, _, remaining_ttl)| remaining_ttl.is_none()));
+
+            let mut imported =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            let _ = imported.insert(2, 2);
+            imported.import(exported);
+            assert_eq!(imported.remaining_ttl(&0), None);
+            sleep(100);
+
+            assert_eq!(
+                imported.peek_iter().collect::<Vec<_>>(),
+                vec![(&1, &1), (&0, &0)]
+            );
+            assert_eq!(imported.export().len(), 2);
+        }
+
+        #[test]
+        fn skips_expired_entries() {
+            let time_to_live = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_secs(10)));
+            sleep(200);
+
+            let snapshot = lru_cache.snapshot();
+            assert_eq!(snapshot.entries.len(), 1);
+            assert_eq!(snapshot.entries[0].key, 1);
+        }
+
+        #[test]
+        fn restores_within_capacity() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            for i in 0..3 {
+                let _ = lru_cache.insert(i, i);
+            }
+            let mut snapshot = lru_cache.snapshot();
+            snapshot.capacity = 2;
+            let duplicate = snapshot.entries[1].clone();
+            snapshot.entries.push(duplicate);
+
+            let restored = LruCache::from_snapshot(snapshot);
+            assert_eq!(restored.capacity(), 2);
+            assert_eq!(restored.list, vec![2, 1]);
+            assert_eq!(restored.len(), 2);
+        }
+
+        #[test]
+        fn restores_permanent_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(100));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert_permanent(1, 1);
+
+            let snapshot = lru_cache.snapshot();
+            assert!(!snapshot.entries[0].permanent);
+            assert!(snapshot.entries[1].permanent);
+            assert_eq!(snapshot.entries[1].remaining_ttl, None);
+
+            let restored = LruCache::from_snapshot(snapshot);
+            assert_eq!(restored.remaining_ttl(&1), None);
+            sleep(200);
+            assert_eq!(restored.peek_iter().collect::<Vec<_>>(), vec![(&1, &1)]);
+        }
+
+        #[test]
+        fn restores_deadlines() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert_until(1, 1, Instant::now() + Duration::from_millis(100));
+
+            let snapshot = lru_cache.snapshot();
+            assert_eq!(snapshot.entries[0].until_deadline, None);
+            assert!(snapshot.entries[1].until_deadline <= Some(Duration::from_millis(100)));
+
+            let mut restored = LruCache::from_snapshot(snapshot);
+            assert!(restored.remaining_ttl(&1) <= Some(Duration::from_millis(100)));
+            sleep(50);
+            let _ = restored.get(&1);
+            sleep(100);
+            assert_eq!(restored.peek_iter().collect::<Vec<_>>(), vec![(&0, &0)]);
+        }
+    }
+
+    #[cfg(feature = "persistence")]
+    mod persistence {
+        use super::*;
+        use std::fs;
+        use std::path::PathBuf;
+
+        fn test_path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("lru_time_cache-{}-{}", std::process::id(), name))
+        }
+
+        #[test]
+        fn save_and_load() {
+            let path = test_path("save_and_load");
+            let time_to_live = Duration::from_millis(200);
+            let mut lru_cache =
+                LruCache::<String, Vec<u8>>::with_expiry_duration_and_capacity(time_to_live, 10);
+            let _ = lru_cache.set_expiry_policy(ExpiryPolicy::AfterWrite);
+            let _ = lru_cache.insert("a".to_string(), vec![1]);
+            let _ = lru_cache.insert("b".to_string(), vec![2, 2]);
+            let _ = lru_cache.insert("c".to_string(), vec![]);
+            assert!(lru_cache.set_entry_ttl("b", Duration::from_secs(10)));
+            let _ = lru_cache.get("a");
+
+            lru_cache.save_to_path(&path).unwrap();
+            let loaded = LruCache::<String, Vec<u8>>::load_from_path(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(loaded.capacity(), 10);
+            assert_eq!(loaded.time_to_live(), Some(time_to_live));
+            assert_eq!(loaded.expiry_policy(), ExpiryPolicy::AfterWrite);
+            assert_eq!(
+                loaded.peek_iter().collect::<Vec<_>>(),
+                vec![
+                    (&"a".to_string(), &vec![1]),
+                    (&"c".to_string(), &vec![]),
+                    (&"b".to_string(), &vec![2, 2]),
+                ]
+            );
+            sleep(300);
+            assert_eq!(loaded.len(), 1);
+            assert_eq!(loaded.peek("b"), Some(&vec![2, 2]));
+        }
+
+        #[test]
+        fn leaves_no_temporary_file() {
+            let path = test_path("leaves_no_temporary_file");
+            let mut lru_cache = LruCache::<u32, u32>::with_capacity(2);
+            let _ = lru_cache.insert(1, 1);
+            lru_cache.save_to_path(&path).unwrap();
+            let _ = lru_cache.insert(2, 2);
+            lru_cache.save_to_path(&path).unwrap();
+
+            let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let leftovers = fs::read_dir(path.parent().unwrap())
+                .unwrap()
+                .filter_map(|entry| entry.unwrap().file_name().into_string().ok())
+                .filter(|name| name.contains(&file_name) && *name != file_name)
+                .count();
+            assert_eq!(leftovers, 0);
+            assert_eq!(
+                LruCache::<u32, u32>::load_from_path(&path).unwrap().len(),
+                2
+            );
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn concurrent_saves_dont_clobber_each_other() {
+            let path = test_path("concurrent_saves_dont_clobber_each_other");
+            let savers: Vec<_> = (0..4)
+                .map(|i| {
+                    let path = path.clone();
+                    std::thread::spawn(move || {
+                        let mut lru_cache = LruCache::<u32, u32>::with_capacity(100);
+                        for j in 0..100 {
+                            let _ = lru_cache.insert(j, i);
+                        }
+                        for _ in 0..10 {
+                            lru_cache.save_to_path(&path).unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for saver in savers {
+                saver.join().unwrap();
+            }
+
+            let loaded = LruCache::<u32, u32>::load_from_path(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.len(), 100);
+            let saver = *loaded.peek(&0).unwrap();
+            assert!(loaded.peek_iter().all(|(_, value)| *value == saver));
+        }
+
+        #[test]
+        fn rejects_other_files() {
+            let path = test_path("rejects_other_files");
+            fs::write(&path, b"definitely not a cache").unwrap();
+            let error = LruCache::<u32, u32>::load_from_path(&path).err().unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn restores_permanent_entries() {
+            let path = test_path("restores_permanent_entries");
+            let mut lru_cache =
+                LruCache::<u32, u32>::with_expiry_duration(Duration::from_millis(100));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert_permanent(1, 1);
+
+            lru_cache.save_to_path(&path).unwrap();
+            let loaded = LruCache::<u32, u32>::load_from_path(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(loaded.remaining_ttl(&1), None);
+            sleep(200);
+            assert_eq!(loaded.peek_iter().collect::<Vec<_>>(), vec![(&1, &1)]);
+        }
+
+        #[test]
+        fn restores_deadlines() {
+            let path = test_path("restores_deadlines");
+            let mut lru_cache = LruCache::<u32, u32>::with_expiry_duration(Duration::from_secs(10));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert_until(1, 1, Instant::now() + Duration::from_millis(100));
+
+            lru_cache.save_to_path(&path).unwrap();
+            let mut loaded = LruCache::<u32, u32>::load_from_path(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert!(loaded.remaining_ttl(&1) <= Some(Duration::from_millis(100)));
+            sleep(50);
+            let _ = loaded.get(&1);
+            sleep(100);
+            assert_eq!(loaded.peek_iter().collect::<Vec<_>>(), vec![(&0, &0)]);
+        }
+    }
+
+    #[cfg(feature = "persistent")]
+    mod persistent {
+        use super::*;
+
+        #[test]
+        fn clones_are_independent_snapshots() {
+            let mut cache = PersistentLruCache::<usize, usize>::with_capacity(3);
+            for i in 0..3 {
+                let _ = cache.insert(i, i);
+            }
+            let snapshot = cache.clone();
+
+            assert_eq!(cache.get(&0), Some(&0));
+            assert_eq!(cache.insert(3, 3), None);
+            assert_eq!(cache.insert(0, 10), Some(0));
+            assert_eq!(cache.remove(&2), Some(2));
+            assert_eq!(
+                cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&0, &10), (&3, &3)]
+            );
+            assert_eq!(
+                snapshot.peek_iter().collect::<Vec<_>>(),
+                vec![(&2, &2), (&1, &1), (&0, &0)]
+            );
+            assert_eq!(snapshot.len(), 3);
+        }
+
+        #[test]
+        fn expires_entries() {
+            let mut cache =
+                PersistentLruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            let _ = cache.insert(0, 0);
+            let _ = cache.insert(1, 1);
+            sleep(30);
+            assert_eq!(cache.get(&0), Some(&0));
+            sleep(30);
+
+            assert_eq!(cache.len(), 1);
+            assert!(!cache.contains_key(&1));
+            assert_eq!(cache.peek(&0), Some(&0));
+            let _ = cache.insert(2, 2);
+            assert_eq!(cache.peek_iter().count(), 2);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    mod parallel {
+        use super::*;
+        use rayon::iter::ParallelIterator;
+
+        #[test]
+        fn par_peek_iter_skips_expired_entries() {
+            let time_to_live = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            for i in 0..1000 {
+                let _ = lru_cache.insert(i, i);
+            }
+            for i in 0..500 {
+                assert!(lru_cache.set_entry_ttl(&i, Duration::from_secs(10)));
+            }
+            let before = lru_cache.list.clone();
+            assert_eq!(lru_cache.par_peek_iter().count(), 1000);
+            sleep(200);
+
+            let sum: usize = lru_cache.par_peek_iter().map(|(_, value)| *value).sum();
+            assert_eq!(sum, (0..500).sum());
+            assert_eq!(lru_cache.list, before);
+        }
+
+        #[test]
+        fn par_retain() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1000);
+            for i in 0..1000 {
+                let _ = lru_cache.insert(i, i * 2);
+            }
+            let _ = lru_cache.get(&10);
+
+            lru_cache.par_retain(|key, value| key % 10 == 0 && *value < 1000);
+            assert_eq!(lru_cache.len(), 50);
+            assert_eq!(lru_cache.list.len(), 50);
+            assert_eq!(lru_cache.list.back(), Some(&10));
+            assert_eq!(lru_cache.list.front(), Some(&0));
+        }
+    }
+
+    mod iterator_traits {
+        use super::*;
+
+        fn cache_with_expired_entry() -> LruCache<usize, usize> {
+            let time_to_live = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            for i in 0..4 {
+                let _ = lru_cache.insert(i, i);
+                if i != 1 {
+                    assert!(lru_cache.set_entry_ttl(&i, Duration::from_secs(10)));
+                }
+            }
+            sleep(200);
+            lru_cache
+        }
+
+        #[test]
+        fn peek_iter() {
+            let lru_cache = cache_with_expired_entry();
+            let mut iter = lru_cache.peek_iter();
+            assert_eq!(iter.len(), 3);
+            assert_eq!(iter.next(), Some((&3, &3)));
+            assert_eq!(iter.len(), 2);
+            assert_eq!(iter.next_back(), Some((&0, &0)));
+            assert_eq!(iter.len(), 1);
+            assert_eq!(iter.next_back(), Some((&2, &2)));
+            assert_eq!(iter.len(), 0);
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next_back(), None);
+
+            let keys: Vec<_> = lru_cache.peek_iter().rev().map(|(key, _)| *key).collect();
+            assert_eq!(keys, vec![0, 2, 3]);
+        }
+
+        #[test]
+        fn iter() {
+            let mut lru_cache = cache_with_expired_entry();
+            {
+                let mut iter = lru_cache.iter();
+                assert_eq!(iter.len(), 3);
+                assert_eq!(iter.next_back(), Some((&0, &0)));
+                assert_eq!(iter.next(), Some((&3, &3)));
+                assert_eq!(iter.size_hint(), (1, Some(1)));
+                assert_eq!(iter.next_back(), Some((&2, &2)));
+                assert_eq!(iter.next(), None);
+                assert_eq!(iter.next_back(), None);
+            }
+            assert_eq!(lru_cache.list, vec![0, 3, 2]);
+
+            let keys: Vec<_> = lru_cache.iter().rev().map(|(key, _)| *key).collect();
+            assert_eq!(keys, vec![0, 3, 2]);
+            assert_eq!(lru_cache.list, vec![0, 3, 2]);
+        }
+
+        #[test]
+        fn notify_iter() {
+            let mut lru_cache = cache_with_expired_entry();
+            let mut iter = lru_cache.notify_iter();
+            assert_eq!(iter.len(), 4);
+            match iter.next_back() {
+                Some(TimedEntry::Expired(1, 1)) => (),
+                _ => panic!("expired entry expected"),
+            }
+            match iter.next_back() {
+                Some(TimedEntry::Valid(&0, &0)) => (),
+                _ => panic!("least recently used entry expected"),
+            }
+            match iter.next() {
+                Some(TimedEntry::Valid(&3, &3)) => (),
+                _ => panic!("most recently used entry expected"),
+            }
+            assert_eq!(iter.len(), 1);
+            assert!(iter.next().is_some());
+            assert!(iter.next().is_none());
+            assert!(iter.next_back().is_none());
+        }
+
+        #[test]
+        fn collect_pre_sizes() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(100);
+            for i in 0..100 {
+                let _ = lru_cache.insert(i, i);
+            }
+            let items: Vec<_> = lru_cache.peek_iter().collect();
+            assert_eq!(items.len(), 100);
+            assert_eq!(lru_cache.iter().len(), 100);
+            assert_eq!(lru_cache.notify_iter().len(), 100);
+        }
+    }
+
+    mod iter_with_meta {
+        use super::*;
+
+        #[test]
+        fn yields_timing_of_unexpired_entries() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            sleep(60);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert_permanent(2, 2);
+            sleep(60);
+
+            let items: Vec<_> = lru_cache.iter_with_meta().collect();
+            assert_eq!(items.len(), 2);
+            assert_eq!((items[0].0, items[0].1), (&2, &2));
+            assert_eq!(items[0].3, None);
+            assert_eq!((items[1].0, items[1].1), (&1, &1));
+            assert_eq!(items[1].2, lru_cache.map[&1].timestamp);
+            assert!(items[1].3.unwrap() <= Duration::from_millis(40));
+
+            let keys: Vec<_> = lru_cache
+                .iter_with_meta()
+                .rev()
+                .map(|(key, ..)| *key)
+                .collect();
+            assert_eq!(keys, vec![1, 2]);
+            assert_eq!(lru_cache.iter_with_meta().len(), 2);
+        }
+    }
+
+    mod peek_iter_from {
+        use super::*;
+
+        #[test]
+        fn pages_through_the_cache() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..7).map(|i| (i, i)));
+
+            let first: Vec<_> = lru_cache.peek_iter().take(3).map(|(key, _)| *key).collect();
+            assert_eq!(first, vec![6, 5, 4]);
+            let second: Vec<_> = lru_cache
+                .peek_iter_from(&4)
+                .skip(1)
+                .take(3)
+                .map(|(key, _)| *key)
+                .collect();
+            assert_eq!(second, vec![3, 2, 1]);
+            let mut last = lru_cache.peek_iter_from(&1);
+            assert_eq!(last.len(), 2);
+            assert_eq!(last.next_back(), Some((&0, &0)));
+            assert_eq!(last.next(), Some((&1, &1)));
+            assert_eq!(last.next(), None);
+        }
+
+        #[test]
+        fn yields_nothing_for_missing_keys() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            let _ = lru_cache.insert(0, 0);
+            assert_eq!(lru_cache.peek_iter_from(&1).next(), None);
+            let _ = lru_cache.insert(1, 1);
+            assert!(lru_cache.set_entry_ttl(&0, Duration::from_millis(10)));
+            sleep(20);
+            assert_eq!(lru_cache.peek_iter_from(&0).len(), 0);
+            assert_eq!(lru_cache.peek_iter_from(&1).len(), 1);
+        }
+    }
+
+    mod iter_stale {
+        use super::*;
+
+        #[test]
+        fn yields_idle_entries_coldest_first() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(200));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(60);
+            let _ = lru_cache.get(&1);
+
+            let stale: Vec<_> = lru_cache.iter_stale(Duration::from_millis(40)).collect();
+            assert_eq!(stale, vec![(&0, &0), (&2, &2)]);
+            assert_eq!(lru_cache.iter_stale(Duration::from_secs(1)).next(), None);
+            let keys: Vec<_> = lru_cache.peek_iter().map(|(key, _)| *key).collect();
+            assert_eq!(keys, vec![1, 2, 0]);
+        }
+    }
+
+    mod lru_iter {
+        use super::*;
+
+        #[test]
+        fn yields_coldest_first() {
+            let time_to_live = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            for i in 0..4 {
+                let _ = lru_cache.insert(i, i);
+                if i != 2 {
+                    assert!(lru_cache.set_entry_ttl(&i, Duration::from_secs(10)));
+                }
+            }
+            let _ = lru_cache.get(&0);
+            sleep(200);
+
+            let keys: Vec<_> = lru_cache.peek_lru_iter().map(|(key, _)| *key).collect();
+            assert_eq!(keys, vec![1, 3, 0]);
+            assert_eq!(lru_cache.list, vec![1, 2, 3, 0]);
+
+            let keys: Vec<_> = lru_cache.lru_iter().map(|(key, _)| *key).collect();
+            assert_eq!(keys, vec![1, 3, 0]);
+            assert_eq!(lru_cache.list, vec![1, 3, 0]);
+        }
+
+        #[test]
+        fn partial_traversal_promotes_visited_entries() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(4);
+            for i in 0..4 {
+                let _ = lru_cache.insert(i, i);
+            }
+            assert_eq!(lru_cache.lru_iter().next(), Some((&0, &0)));
+            assert_eq!(lru_cache.list, vec![1, 2, 3, 0]);
+        }
+    }
+
+    mod concurrent {
+        use super::*;
+        use std::sync::Arc;
+        use std::thread;
+
+        #[test]
+        fn reads_are_applied_on_write() {
+            let cache = ConcurrentLruCache::new(LruCache::<usize, usize>::with_capacity(3));
+            for i in 0..3 {
+                assert_eq!(cache.insert(i, i), None);
+            }
+            assert_eq!(cache.get(&0), Some(0));
+            assert_eq!(cache.get(&5), None);
+            assert_eq!(cache.read().list, vec![0, 1, 2]);
+
+            assert_eq!(cache.insert(3, 3), None);
+            assert!(cache.contains_key(&0));
+            assert!(!cache.contains_key(&1));
+            assert_eq!(cache.len(), 3);
+            assert_eq!(cache.remove(&2), Some(2));
+            assert_eq!(cache.into_inner().list, vec![0, 3]);
+        }
+
+        #[test]
+        fn reads_refresh_timestamps() {
+            let time_to_live = Duration::from_millis(200);
+            let cache = ConcurrentLruCache::new(LruCache::<usize, usize>::with_expiry_duration(
+                time_to_live,
+            ));
+            let _ = cache.insert(0, 0);
+            let _ = cache.insert(1, 1);
+            sleep(120);
+            assert_eq!(cache.get(&0), Some(0));
+            sleep(120);
+
+            assert_eq!(cache.get(&1), None);
+            assert_eq!(cache.write().peek(&0), Some(&0));
+            assert_eq!(cache.len(), 1);
+        }
+
+        #[test]
+        fn shared_between_threads() {
+            let cache = Arc::new(ConcurrentLruCache::new(
+                LruCache::<usize, usize>::with_capacity(100),
+            ));
+            for i in 0..100 {
+                let _ = cache.insert(i, i);
+            }
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    let cache = Arc::clone(&cache);
+                    thread::spawn(move || {
+                        for _ in 0..100 {
+                            for i in 0..10 {
+                                assert_eq!(cache.get(&i), Some(i));
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for reader in readers {
+                reader.join().unwrap();
+            }
+
+            let cache = Arc::try_unwrap(cache).ok().unwrap().into_inner();
+            assert_eq!(cache.len(), 100);
+            let mut hottest: Vec<_> = cache.list.iter().rev().take(10).cloned().collect();
+            hottest.sort_unstable();
+            assert_eq!(hottest, (0..10).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn updates_atomically() {
+            let cache = Arc::new(ConcurrentLruCache::new(
+                LruCache::<usize, usize>::with_capacity(10),
+            ));
+            let _ = cache.insert(0, 0);
+            let writers: Vec<_> = (0..4)
+                .map(|_| {
+                    let cache = Arc::clone(&cache);
+                    thread::spawn(move || {
+                        for _ in 0..100 {
+                            assert!(cache.update(&0, |value| *value += 1).is_some());
+                        }
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            assert_eq!(cache.get(&0), Some(400));
+            assert_eq!(cache.update(&1, |value| *value), None);
+        }
+
+        #[test]
+        fn compare_exchange_detects_interleaved_writes() {
+            let cache = ConcurrentLruCache::new(LruCache::<usize, usize>::with_capacity(10));
+            let _ = cache.insert(0, 0);
+            let seen = cache.get(&0).unwrap();
+            let _ = cache.insert(0, 5);
+
+            assert_eq!(cache.compare_exchange(&0, &seen, seen + 1), Err(Some(5)));
+            assert_eq!(cache.compare_exchange(&0, &5, 6), Ok(5));
+            assert_eq!(cache.compare_exchange(&1, &0, 1), Err(None));
+            assert_eq!(
+                cache.compare_exchange_by(&0, |value| value % 2 == 0, 7),
+                Ok(6)
+            );
+            assert_eq!(cache.get(&0), Some(7));
+        }
+
+        #[test]
+        fn counts_deferred_hits_and_misses() {
+            let cache = ConcurrentLruCache::new(LruCache::<usize, usize>::with_capacity(10));
+            let _ = cache.insert(0, 0);
+            for _ in 0..3 {
+                assert_eq!(cache.get(&0), Some(0));
+                assert_eq!(cache.get(&1), None);
+            }
+            let cache = cache.into_inner();
+            let stats = cache.stats();
+            assert_eq!((stats.hits, stats.misses), (3, 3));
+            assert_eq!(cache.map[&0].accesses, 3);
+        }
+
+        #[test]
+        fn applies_read_time_to_live_straight_away() {
+            struct ExpireSoonAfterRead;
+
+            impl Expiry<usize, usize> for ExpireSoonAfterRead {
+                fn expire_after_read(&self, _key: &usize, _value: &usize) -> Option<Duration> {
+                    Some(Duration::from_millis(50))
+                }
+            }
+
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            lru_cache.set_expiry_hook(ExpireSoonAfterRead);
+            let cache = ConcurrentLruCache::new(lru_cache);
+            let _ = cache.insert(0, 0);
+            assert_eq!(cache.get(&0), Some(0));
+            sleep(80);
+            assert_eq!(cache.get(&0), None);
+        }
+
+        #[test]
+        fn rolls_for_early_expiry() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            // Rolls an early expiry unless it draws within 10^-14 of 1.
+            lru_cache.set_early_expiry(Some(Duration::from_secs(1_000_000_000_000_000)));
+            let cache = ConcurrentLruCache::new(lru_cache);
+            let _ = cache.insert(0, 0);
+            for _ in 0..10 {
+                assert_eq!(cache.get(&0), None);
+            }
+            let stats = cache.read().stats();
+            assert_eq!((stats.hits, stats.misses), (0, 10));
+        }
+    }
+
+    mod events {
+        use super::*;
+        use std::sync::mpsc;
+
+        #[derive(Debug, PartialEq, Eq)]
+        enum Event {
+            Inserted(usize, usize),
+            Replaced(usize, usize, usize),
+            Removed(usize, usize),
+            Expired(usize, usize),
+            Evicted(usize, usize),
+        }
+
+        fn subscribe(lru_cache: &mut LruCache<usize, usize>) -> mpsc::Receiver<Event> {
+            let (sender, receiver) = mpsc::channel();
+            lru_cache.subscribe(move |event| {
+                let event = match event {
+                    CacheEvent::Inserted(key, value) => Event::Inserted(*key, *value),
+                    CacheEvent::Replaced(key, old, new) => Event::Replaced(*key, *old, *new),
+                    CacheEvent::Removed(key, value) => Event::Removed(*key, *value),
+                    CacheEvent::Expired(key, value) => Event::Expired(*key, *value),
+                    CacheEvent::Evicted(key, value) => Event::Evicted(*key, *value),
+                };
+                let _ = sender.send(event);
+            });
+            receiver
+        }
+
+        #[test]
+        fn reports_inserts_and_removals() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let events = subscribe(&mut lru_cache);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(0, 1);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            let _ = lru_cache.remove(&1);
+            let _ = lru_cache.extract_if(|_, _| true).count();
+            let _ = lru_cache.insert(3, 3);
+            lru_cache.clear();
+
+            assert_eq!(
+                events.try_iter().collect::<Vec<_>>(),
+                vec![
+                    Event::Inserted(0, 0),
+                    Event::Replaced(0, 0, 1),
+                    Event::Inserted(1, 1),
+                    Event::Evicted(0, 1),
+                    Event::Inserted(2, 2),
+                    Event::Removed(1, 1),
+                    Event::Removed(2, 2),
+                    Event::Inserted(3, 3),
+                    Event::Removed(3, 3),
+                ]
+            );
+
+            lru_cache.unsubscribe();
+            let _ = lru_cache.insert(4, 4);
+            assert!(events.try_recv().is_err());
+        }
+
+        #[test]
+        fn reports_expiry() {
+            let time_to_live = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            let events = subscribe(&mut lru_cache);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.insert(2, 2);
+            lru_cache.set_expiry_budget(Some(1));
+            sleep(200);
+
+            assert_eq!(lru_cache.pop_expired(), Some((0, 0)));
+            assert_eq!(lru_cache.get(&5), None);
+            assert_eq!(lru_cache.notify_iter().count(), 1);
+            assert_eq!(
+                events.try_iter().collect::<Vec<_>>(),
+                vec![
+                    Event::Inserted(0, 0),
+                    Event::Inserted(1, 1),
+                    Event::Inserted(2, 2),
+                    Event::Expired(0, 0),
+                    Event::Expired(1, 1),
+                    Event::Expired(2, 2),
+                ]
+            );
+        }
+
+        #[test]
+        fn reports_expired_leftovers_on_insert() {
+            let time_to_live = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            lru_cache.set_expiry_budget(Some(1));
+            let events = subscribe(&mut lru_cache);
+            sleep(200);
+
+            let _ = lru_cache.insert(1, 10);
+            assert_eq!(
+                events.try_iter().collect::<Vec<_>>(),
+                vec![
+                    Event::Expired(0, 0),
+                    Event::Expired(1, 1),
+                    Event::Inserted(1, 10),
+                ]
+            );
+        }
+
+        #[test]
+        fn clones_have_no_subscriber() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let events = subscribe(&mut lru_cache);
+            let mut clone = lru_cache.clone();
+            let _ = clone.insert(0, 0);
+            assert!(events.try_recv().is_err());
+        }
+    }
+
+    mod observer {
+        use super::*;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Counts {
+            hits: Vec<usize>,
+            misses: usize,
+            inserts: Vec<(usize, usize)>,
+            evictions: Vec<usize>,
+            expirations: Vec<usize>,
+        }
+
+        struct Counter(Arc<Mutex<Counts>>);
+
+        impl CacheObserver<usize, usize> for Counter {
+            fn on_hit(&mut self, key: &usize) {
+                self.0.lock().unwrap().hits.push(*key);
+            }
+
+            fn on_miss(&mut self) {
+                self.0.lock().unwrap().misses += 1;
+            }
+
+            fn on_insert(&mut self, key: &usize, value: &usize) {
+                self.0.lock().unwrap().inserts.push((*key, *value));
+            }
+
+            fn on_evict(&mut self, key: &usize, _value: &usize) {
+                self.0.lock().unwrap().evictions.push(*key);
+            }
+
+            fn on_expire(&mut self, key: &usize, _value: &usize) {
+                self.0.lock().unwrap().expirations.push(*key);
+            }
+        }
+
+        #[test]
+        fn notified_of_every_outcome() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 2);
+            let counts = Arc::new(Mutex::new(Counts::default()));
+            lru_cache.set_observer(Counter(Arc::clone(&counts)));
+
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(0, 1);
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.get(&2);
+            let _ = lru_cache.peek(&1);
+            let _ = lru_cache.insert(2, 2);
+            sleep(60);
+            let _ = lru_cache.get(&0);
+            lru_cache.remove_observer();
+            let _ = lru_cache.insert(3, 3);
+
+            let counts = counts.lock().unwrap();
+            assert_eq!(counts.hits, vec![0]);
+            assert_eq!(counts.misses, 2);
+            assert_eq!(counts.inserts, vec![(0, 0), (0, 1), (1, 1), (2, 2)]);
+            assert_eq!(counts.evictions, vec![1]);
+            assert_eq!(counts.expirations, vec![0, 2]);
+        }
+    }
+
+    mod freeze {
+        use super::*;
+        use std::thread;
+
+        #[test]
+        fn view_is_unaffected_by_later_changes() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            lru_cache.extend((0..5).map(|i| (i, i)));
+            let _ = lru_cache.get(&1);
+            let frozen = lru_cache.freeze();
+
+            let _ = lru_cache.remove(&0);
+            let _ = lru_cache.insert(5, 5);
+            sleep(60);
+            assert!(lru_cache.is_empty());
+
+            let shared = frozen.clone();
+            let keys =
+                thread::spawn(move || shared.peek_iter().map(|(key, _)| *key).collect::<Vec<_>>());
+            assert_eq!(keys.join().unwrap(), vec![1, 4, 3, 2, 0]);
+            assert_eq!(frozen.len(), 5);
+            assert_eq!(frozen.peek(&0), Some(&0));
+            assert!(!frozen.contains_key(&5));
+            assert_eq!(
+                frozen.range(1..3).collect::<Vec<_>>(),
+                vec![(&1, &1), (&2, &2)]
+            );
+            assert_eq!(frozen.peek_iter().next_back(), Some((&0, &0)));
+        }
+
+        #[test]
+        fn ranges_follow_key_order() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..10).rev().map(|i| (i * 2, i)));
+            let frozen = lru_cache.freeze();
+            let keys = |entries: Vec<(&usize, &usize)>| {
+                entries.into_iter().map(|(key, _)| *key).collect::<Vec<_>>()
+            };
+
+            assert_eq!(keys(frozen.range(3..=8).collect()), vec![4, 6, 8]);
+            assert_eq!(keys(frozen.range(..3).collect()), vec![0, 2]);
+            assert_eq!(keys(frozen.range(15..).rev().collect()), vec![18, 16]);
+            assert_eq!(
+                keys(
+                    frozen
+                        .range((Bound::Excluded(4), Bound::Excluded(8)))
+                        .collect()
+                ),
+                vec![6]
+            );
+            assert_eq!(frozen.range(7..7).count(), 0);
+            assert_eq!(frozen.peek(&5), None);
+            assert_eq!(frozen.peek(&6), Some(&3));
+        }
+    }
+
+    mod promotion_buffer {
+        use super::*;
+
+        #[test]
+        fn defers_promotions_until_flushed() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(4);
+            lru_cache.extend((0..4).map(|i| (i, i)));
+            lru_cache.set_promotion_buffer(Some(3));
+            assert_eq!(lru_cache.promotion_buffer(), Some(3));
+
+            assert_eq!(lru_cache.get(&1), Some(&1));
+            assert_eq!(lru_cache.get(&0), Some(&0));
+            assert_eq!(lru_cache.list, vec![0, 1, 2, 3]);
+            lru_cache.flush_recency();
+            assert_eq!(lru_cache.list, vec![2, 3, 1, 0]);
+
+            // A full buffer is applied straight away.
+            for key in &[2, 3, 2] {
+                let _ = lru_cache.get(key);
+            }
+            assert_eq!(lru_cache.list, vec![1, 0, 3, 2]);
+
+            // Inserting applies the buffer before evicting.
+            let _ = lru_cache.get(&1);
+            assert_eq!(lru_cache.insert(4, 4), None);
+            assert_eq!(lru_cache.list, vec![3, 2, 1, 4]);
+
+            let _ = lru_cache.get(&3);
+            lru_cache.set_promotion_buffer(None);
+            assert_eq!(lru_cache.list, vec![2, 1, 4, 3]);
+            let _ = lru_cache.get(&2);
+            assert_eq!(lru_cache.list, vec![1, 4, 3, 2]);
+        }
+    }
+
+    mod get_guard {
+        use super::*;
+
+        #[test]
+        fn promotes_on_commit_or_drop_only() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..3).map(|i| (i, i * 10)));
+            let timestamp = lru_cache.map[&0].timestamp;
+
+            let guard = lru_cache.get_guard(&0).unwrap();
+            assert_eq!(*guard, 0);
+            assert_eq!(guard.key(), &0);
+            guard.discard();
+            assert_eq!(lru_cache.list, vec![0, 1, 2]);
+            assert_eq!(lru_cache.map[&0].timestamp, timestamp);
+            assert_eq!(lru_cache.stats().hits, 0);
+
+            lru_cache.get_guard(&0).unwrap().commit();
+            assert_eq!(lru_cache.list, vec![1, 2, 0]);
+            {
+                let guard = lru_cache.get_guard(&1).unwrap();
+                assert_eq!(*guard + 1, 11);
+            }
+            assert_eq!(lru_cache.list, vec![2, 0, 1]);
+            assert_eq!(lru_cache.stats().hits, 2);
+
+            assert!(lru_cache.get_guard(&3).is_none());
+            assert_eq!(lru_cache.stats().misses, 1);
+        }
+
+        #[test]
+        fn early_expiry_counts_as_a_miss() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            // Rolls an early expiry unless it draws within 10^-14 of 1.
+            lru_cache.set_early_expiry(Some(Duration::from_secs(1_000_000_000_000_000)));
+            let _ = lru_cache.insert(0, 0);
+
+            for _ in 0..10 {
+                assert!(lru_cache.get_guard(&0).is_none());
+            }
+            let stats = lru_cache.stats();
+            assert_eq!((stats.hits, stats.misses), (0, 10));
+            assert_eq!(lru_cache.map[&0].accesses, 0);
+        }
+    }
+
+    mod handles {
+        use super::*;
+        use std::thread;
+
+        #[test]
+        fn readers_see_published_contents() {
+            let ttl = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
+            let (reader, mut writer) = lru_cache.into_handles();
+            assert_eq!(reader.get(&0), Some(0));
+
+            let _ = writer.insert(1, 1);
+            assert!(!reader.contains_key(&1));
+            writer.publish();
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    let reader = reader.clone();
+                    thread::spawn(move || reader.get(&1))
+                })
+                .collect();
+            for handle in readers {
+                assert_eq!(handle.join().unwrap(), Some(1));
+            }
+            assert_eq!(reader.len(), 2);
+
+            sleep(60);
+            assert!(reader.is_empty());
+            assert_eq!(writer.cache().len(), 0);
+        }
+
+        #[test]
+        fn publishing_applies_the_changes_since() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            for i in 0..3 {
+                let _ = lru_cache.insert(i, i);
+            }
+            let (reader, mut writer) = lru_cache.into_handles();
+            for round in 1..4 {
+                let _ = writer.remove(&(round - 1));
+                let _ = writer.insert(round + 2, round);
+                let _ = writer.insert(round + 1, round);
+                writer.publish();
+                assert_eq!(reader.get(&(round - 1)), None);
+                assert_eq!(reader.get(&(round + 1)), Some(round));
+                assert_eq!(reader.get(&(round + 2)), Some(round));
+                assert_eq!(reader.len(), writer.cache().len());
+            }
+
+            // Evictions are published too.
+            let _ = writer.insert(7, 7);
+            writer.publish();
+            assert!(!reader.contains_key(&3));
+            assert_eq!(reader.len(), 3);
+        }
+
+        #[test]
+        fn publish_all_covers_changes_without_events() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            let _ = lru_cache.insert(0, 0);
+            let (reader, mut writer) = lru_cache.into_handles();
+            *writer.cache_mut().peek_mut(&0).unwrap() = 1;
+            writer.publish();
+            assert_eq!(reader.get(&0), Some(0));
+            writer.publish_all();
+            assert_eq!(reader.get(&0), Some(1));
+
+            let _ = writer.insert(1, 1);
+            writer.publish();
+            writer.publish();
+            assert_eq!(reader.get(&0), Some(1));
+            assert_eq!(reader.get(&1), Some(1));
+        }
+    }
+
+    #[cfg(any(debug_assertions, feature = "validate"))]
+    mod validate {
+        use super::*;
+
+        #[test]
+        fn holds_after_random_operations() {
+            let mut lru_cache =
+                LruCache::<u8, u8>::with_expiry_duration_and_capacity(Duration::from_millis(5), 8);
+            for (i, byte) in generate_random_vec::<u8>(500).into_iter().enumerate() {
+                let key = byte % 16;
+                match byte % 5 {
+                    0 | 1 => {
+                        let _ = lru_cache.insert(key, byte);
+                    }
+                    2 => {
+                        let _ = lru_cache.get(&key);
+                    }
+                    3 => {
+                        let _ = lru_cache.remove(&key);
+                    }
+                    _ => {
+                        let _ = lru_cache.set_entry_ttl(&key, Duration::from_millis(1));
+                    }
+                }
+                if i % 100 == 0 {
+                    sleep(2);
+                }
+                assert_eq!(lru_cache.validate(), Ok(()));
+            }
+        }
+
+        #[test]
+        fn reports_corruption() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            for i in 0..3 {
+                let _ = lru_cache.insert(i, i);
+                sleep(1);
+            }
+            assert_eq!(lru_cache.validate(), Ok(()));
+
+            lru_cache.list.push_back(1);
+            assert_eq!(
+                lru_cache.validate(),
+                Err(ValidationError::DuplicateInList(1))
+            );
+            let _ = lru_cache.list.pop_back();
+            lru_cache.list.swap(0, 2);
+            assert_eq!(
+                lru_cache.validate(),
+                Err(ValidationError::TimestampsOutOfOrder(2, 1))
+            );
+            let _ = lru_cache.list.pop_front();
+            assert_eq!(
+                lru_cache.validate(),
+                Err(ValidationError::MissingFromList(2))
+            );
+            assert_eq!(
+                ValidationError::MissingFromList(2).to_string(),
+                "key 2 is missing from the recency list"
+            );
+        }
+    }
+
+    mod op_log {
+        use super::*;
+
+        #[test]
+        fn records_changes_and_reads() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let _ = lru_cache.insert(0, 0);
+            lru_cache.start_recording();
+            let _ = lru_cache.insert(1, 1);
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.insert(2, 2);
+            let _ = lru_cache.remove(&0);
+            let _ = lru_cache.peek(&2);
+
+            let ops: Vec<_> = lru_cache
+                .op_log()
+                .unwrap()
+                .records()
+                .iter()
+                .map(|record| record.op.clone())
+                .collect();
+            assert_eq!(
+                ops,
+                vec![
+                    Op::Insert(1, 1),
+                    Op::Access(0),
+                    Op::Evict(1),
+                    Op::Insert(2, 2),
+                    Op::Remove(0),
+                ]
+            );
+            let log = lru_cache.stop_recording().unwrap();
+            assert!(lru_cache.op_log().is_none());
+            assert!(log
+                .records()
+                .windows(2)
+                .all(|pair| pair[0].offset <= pair[1].offset));
+        }
+
+        #[test]
+        fn replay_reproduces_the_cache() {
+            let ttl = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(ttl, 3);
+            lru_cache.start_recording();
+            for i in 0..4 {
+                let _ = lru_cache.insert(i, i);
+            }
+            let _ = lru_cache.get(&1);
+            sleep(60);
+            let _ = lru_cache.insert(4, 4);
+            let _ = lru_cache.get(&2);
+            sleep(60);
+            let _ = lru_cache.insert(5, 5);
+            let log = lru_cache.stop_recording().unwrap();
+
+            let replayed = log.replay(LruCache::with_expiry_duration_and_capacity(ttl, 3));
+            assert_eq!(
+                replayed.peek_iter().collect::<Vec<_>>(),
+                lru_cache.peek_iter().collect::<Vec<_>>()
+            );
+            assert_eq!(log.records()[3].op, Op::Evict(0));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    mod tracing_events {
+        use super::*;
+        use std::fmt::Debug;
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Collects the messages and fields of all the events, e.g. "lookup hit=true".
+        #[derive(Clone, Default)]
+        struct Collector(Arc<Mutex<Vec<String>>>);
+
+        struct Fields(String);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                if field.name() == "message" {
+                    self.0.insert_str(0, &format!("{:?}", value));
+                } else if field.name() != "duration_us" {
+                    self.0.push_str(&format!(" {}={:?}", field.name(), value));
+                }
+            }
+        }
+
+        impl Subscriber for Collector {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _span: &Attributes<'_>) -> Id {
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut fields = Fields(String::new());
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+            }
+
+            fn enter(&self, _span: &Id) {}
+
+            fn exit(&self, _span: &Id) {}
+        }
+
+        #[test]
+        fn reports_activity() {
+            let collector = Collector::default();
+            let time_to_live = Duration::from_millis(100);
+            tracing::subscriber::with_default(collector.clone(), || {
+                let mut lru_cache =
+                    LruCache::<usize, usize>::with_expiry_duration_and_capacity(time_to_live, 1);
+                let _ = lru_cache.insert(0, 0);
+                let _ = lru_cache.insert(1, 1);
+                let _ = lru_cache.get(&1);
+                let _ = lru_cache.get(&0);
+                sleep(200);
+                let _ = lru_cache.get(&1);
+            });
 
-    fn generate_random_vec<T>(len: usize) -> Vec<T>
-    where
-        Standard: Distribution<T>,
-    {
-        let mut rng = thread_rng();
-        let v: Vec<T> = Standard.sample_iter(&mut rng).take(len).collect();
-        v
+            assert_eq!(
+                *collector.0.lock().unwrap(),
+                vec![
+                    "insert replaced=false rejected=false evicted=0",
+                    "evicted entries count=1",
+                    "insert replaced=false rejected=false evicted=1",
+                    "lookup hit=true",
+                    "lookup hit=false",
+                    "removed expired entries count=1",
+                    "lookup hit=false",
+                ]
+            );
+        }
     }
 
-    #[test]
-    fn size_only() {
-        let size = 10usize;
-        let mut lru_cache = super::LruCache::<usize, usize>::with_capacity(size);
+    mod stats {
+        use super::*;
 
-        for i in 0..10 {
-            assert_eq!(lru_cache.len(), i);
-            let _ = lru_cache.insert(i, i);
-            assert_eq!(lru_cache.len(), i + 1);
-        }
+        #[test]
+        fn counts_lifetime_activity() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            for i in 0..3 {
+                let _ = lru_cache.insert(i, i);
+            }
+            assert_eq!(lru_cache.get(&2), Some(&2));
+            assert_eq!(lru_cache.get(&0), None);
+            assert_eq!(lru_cache.peek(&1), Some(&1));
 
-        for i in 10..1000 {
-            let _ = lru_cache.insert(i, i);
-            assert_eq!(lru_cache.len(), size);
+            let stats = lru_cache.stats();
+            assert_eq!(
+                (stats.hits, stats.misses, stats.inserts, stats.evictions),
+                (1, 1, 3, 1)
+            );
+            assert_eq!(stats.expirations, 0);
+            assert_eq!(stats.hit_ratio(), Some(0.5));
+            assert_eq!(lru_cache.stats_window(), None);
         }
 
-        for _ in (0..1000).rev() {
-            assert!(lru_cache.contains_key(&(1000 - 1)));
-            assert!(lru_cache.get(&(1000 - 1)).is_some());
-            assert_eq!(*lru_cache.get(&(1000 - 1)).unwrap(), 1000 - 1);
+        #[test]
+        fn counts_expirations() {
+            let time_to_live = Duration::from_millis(50);
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            sleep(100);
+            assert_eq!(lru_cache.get(&0), None);
+
+            let stats = lru_cache.stats();
+            assert_eq!((stats.misses, stats.expirations), (1, 2));
         }
-    }
 
-    #[test]
-    fn time_only() {
-        let time_to_live = Duration::from_millis(100);
-        let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+        #[test]
+        fn operations_window() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            lru_cache.set_stats_window(Some(StatsWindow::Operations(2)));
+            assert_eq!(lru_cache.get(&0), None);
+            assert_eq!(lru_cache.get(&0), None);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            assert_eq!(lru_cache.get(&1), Some(&1));
 
-        for i in 0..10 {
-            assert_eq!(lru_cache.len(), i);
-            let _ = lru_cache.insert(i, i);
-            assert_eq!(lru_cache.len(), i + 1);
+            let windowed = lru_cache.stats_window().unwrap();
+            assert_eq!(
+                (windowed.hits, windowed.misses, windowed.inserts),
+                (1, 0, 1)
+            );
+            assert_eq!(windowed.evictions, 1);
+            assert_eq!(windowed.hit_ratio(), Some(1.0));
+            assert_eq!(lru_cache.stats().misses, 2);
         }
 
-        sleep(101);
-        let _ = lru_cache.insert(11, 11);
+        #[test]
+        fn time_window() {
+            let window = Duration::from_millis(100);
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            lru_cache.set_stats_window(Some(StatsWindow::Time(window)));
+            let _ = lru_cache.insert(0, 0);
+            assert_eq!(lru_cache.get(&1), None);
+            sleep(150);
+            let _ = lru_cache.insert(1, 1);
+            assert_eq!(lru_cache.get(&1), Some(&1));
 
-        assert_eq!(lru_cache.len(), 1);
+            let windowed = lru_cache.stats_window().unwrap();
+            assert_eq!(
+                (windowed.hits, windowed.misses, windowed.inserts),
+                (1, 0, 1)
+            );
+            assert_eq!(windowed.evictions, 1);
+            assert_eq!(windowed.elapsed, window);
+            assert_eq!(windowed.eviction_rate(), Some(10.0));
 
-        for i in 0..10 {
-            assert!(!lru_cache.is_empty());
-            assert_eq!(lru_cache.len(), i + 1);
-            let _ = lru_cache.insert(i, i);
-            assert_eq!(lru_cache.len(), i + 2);
+            lru_cache.set_stats_window(None);
+            assert_eq!(lru_cache.stats_window(), None);
+            assert_eq!(lru_cache.stats().inserts, 2);
         }
 
-        sleep(101);
-        assert_eq!(0, lru_cache.len());
-        assert!(lru_cache.is_empty());
+        #[test]
+        fn evictions_are_counted_at_the_time_given() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            lru_cache.set_stats_window(Some(StatsWindow::Time(Duration::from_millis(100))));
+            let past = Instant::now() - Duration::from_secs(1);
+            let _ = lru_cache.insert_at(0, 0, past);
+            let _ = lru_cache.insert_at(1, 1, past);
+
+            let windowed = lru_cache.stats_window().unwrap();
+            assert_eq!((windowed.inserts, windowed.evictions), (0, 0));
+            assert_eq!(lru_cache.stats().evictions, 1);
+        }
     }
 
-    #[test]
-    fn time_only_check() {
-        let time_to_live = Duration::from_millis(50);
-        let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
-
-        assert_eq!(lru_cache.len(), 0);
-        let _ = lru_cache.insert(0, 0);
-        assert_eq!(lru_cache.len(), 1);
+    mod metadata {
+        use super::*;
 
-        sleep(101);
+        #[test]
+        fn reports_entry_bookkeeping() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            for i in 0..3 {
+                let _ = lru_cache.insert(i, i);
+            }
+            let inserted = lru_cache.metadata(&0).unwrap().inserted;
+            sleep(10);
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.insert(0, 10);
+            assert_eq!(lru_cache.peek(&1), Some(&1));
+
+            let metadata = lru_cache.metadata(&0).unwrap();
+            assert_eq!(metadata.inserted, inserted);
+            assert!(metadata.written > inserted);
+            assert!(metadata.last_accessed > inserted);
+            assert_eq!(metadata.access_count, 2);
+            assert_eq!(metadata.recency_rank, 0);
+            assert_eq!(lru_cache.metadata(&1).unwrap().recency_rank, 2);
+            assert_eq!(lru_cache.metadata(&2).unwrap().recency_rank, 1);
+            assert_eq!(lru_cache.metadata(&1).unwrap().access_count, 0);
+            assert_eq!(lru_cache.metadata(&3), None);
+        }
 
-        assert!(!lru_cache.contains_key(&0));
-        assert_eq!(lru_cache.len(), 0);
+        #[test]
+        fn skips_expired_entries() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_millis(10)));
+            sleep(20);
+            assert_eq!(lru_cache.metadata(&1), None);
+            assert_eq!(lru_cache.metadata(&0).unwrap().recency_rank, 0);
+        }
     }
 
-    #[test]
-    fn time_and_size() {
-        let size = 10usize;
-        let time_to_live = Duration::from_millis(100);
-        let mut lru_cache =
-            super::LruCache::<usize, usize>::with_expiry_duration_and_capacity(time_to_live, size);
+    mod promotion {
+        use super::*;
 
-        for i in 0..1000 {
-            if i < size {
-                assert_eq!(lru_cache.len(), i);
+        fn filled(promotion: Promotion) -> LruCache<usize, usize> {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+            lru_cache.set_promotion(promotion);
+            for i in 0..3 {
+                let _ = lru_cache.insert(i, i);
             }
+            let _ = lru_cache.get(&0);
+            let _ = lru_cache.insert(1, 10);
+            lru_cache
+        }
 
-            let _ = lru_cache.insert(i, i);
+        fn order(lru_cache: &LruCache<usize, usize>) -> Vec<usize> {
+            lru_cache.peek_iter().map(|(key, _)| *key).collect()
+        }
 
-            if i < size {
-                assert_eq!(lru_cache.len(), i + 1);
-            } else {
-                assert_eq!(lru_cache.len(), size);
-            }
+        #[test]
+        fn on_read_and_write_by_default() {
+            let lru_cache = filled(Promotion::default());
+            assert_eq!(lru_cache.promotion(), Promotion::OnReadAndWrite);
+            assert_eq!(order(&lru_cache), vec![1, 0, 2]);
         }
 
-        sleep(101);
-        let _ = lru_cache.insert(1, 1);
+        #[test]
+        fn on_read() {
+            assert_eq!(order(&filled(Promotion::OnRead)), vec![0, 2, 1]);
+        }
 
-        assert_eq!(lru_cache.len(), 1);
-    }
+        #[test]
+        fn on_write() {
+            assert_eq!(order(&filled(Promotion::OnWrite)), vec![1, 2, 0]);
+        }
 
-    #[derive(PartialEq, PartialOrd, Ord, Clone, Eq)]
-    struct Temp {
-        id: Vec<u8>,
-    }
+        #[test]
+        fn never_evicts_in_insertion_order() {
+            let mut lru_cache = filled(Promotion::Never);
+            assert_eq!(order(&lru_cache), vec![2, 1, 0]);
+            assert_eq!(lru_cache.get_many(&[&0, &1]), vec![Some(&0), Some(&10)]);
+            let _ = lru_cache.insert(3, 3);
+            assert!(!lru_cache.contains_key(&0));
 
-    #[test]
-    fn time_size_struct_value() {
-        let size = 100usize;
-        let time_to_live = Duration::from_millis(100);
+            assert_eq!(lru_cache.get_and_promote(&1), Some(&10));
+            assert_eq!(order(&lru_cache), vec![1, 3, 2]);
+        }
+    }
 
-        let mut lru_cache =
-            super::LruCache::<Temp, usize>::with_expiry_duration_and_capacity(time_to_live, size);
+    mod insert_until {
+        use super::*;
 
-        for i in 0..1000 {
-            if i < size {
-                assert_eq!(lru_cache.len(), i);
-            }
+        #[test]
+        fn expires_at_the_deadline_despite_accesses() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            let deadline = Instant::now() + Duration::from_millis(100);
+            assert_eq!(lru_cache.insert_until(0, 0, deadline), None);
+            let _ = lru_cache.insert(1, 1);
+            assert_eq!(lru_cache.expiry_time(&0), Some(deadline));
 
-            let _ = lru_cache.insert(
-                Temp {
-                    id: generate_random_vec::<u8>(64),
-                },
-                i,
-            );
+            sleep(60);
+            assert_eq!(lru_cache.get(&0), Some(&0));
+            sleep(60);
+            assert_eq!(lru_cache.notify_insert(2, 2).1, vec![(0, 0)]);
+            assert!(lru_cache.contains_key(&1));
+        }
 
-            if i < size {
-                assert_eq!(lru_cache.len(), i + 1);
-            } else {
-                assert_eq!(lru_cache.len(), size);
-            }
+        #[test]
+        fn applies_to_caches_without_time_to_live() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let _ = lru_cache.insert_until(0, 0, Instant::now() + Duration::from_millis(50));
+            sleep(100);
+            assert_eq!(lru_cache.pop_expired(), Some((0, 0)));
         }
 
-        sleep(101);
-        let _ = lru_cache.insert(
-            Temp {
-                id: generate_random_vec::<u8>(64),
-            },
-            1,
-        );
+        #[test]
+        fn time_to_live_applies_if_earlier() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            let _ = lru_cache.insert_until(0, 0, Instant::now() + Duration::from_secs(10));
+            sleep(100);
+            assert!(!lru_cache.contains_key(&0));
+        }
 
-        assert_eq!(lru_cache.len(), 1);
+        #[test]
+        fn replacing_clears_the_deadline() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let _ = lru_cache.insert_until(0, 0, Instant::now() + Duration::from_millis(50));
+            assert_eq!(lru_cache.insert(0, 1), Some(0));
+            sleep(100);
+            assert_eq!(lru_cache.get(&0), Some(&1));
+            assert_eq!(lru_cache.expiry_time(&0), None);
+        }
     }
 
-    mod notify_insert {
+    mod insert_permanent {
         use super::*;
 
         #[test]
-        fn it_removes_expired_entries() {
-            let ttl = Duration::from_millis(200);
-            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+        fn never_expires() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            assert_eq!(lru_cache.insert_permanent(0, 0), None);
             let _ = lru_cache.insert(1, 1);
-            let _ = lru_cache.insert(2, 2);
-            sleep(250);
+            assert_eq!(lru_cache.expiry_time(&0), None);
 
-            let _ = lru_cache.notify_insert(3, 3);
-
-            assert_eq!(lru_cache.map.len(), 1);
-            assert_eq!(lru_cache.map[&3].0, 3);
+            sleep(100);
+            assert_eq!(lru_cache.get(&0), Some(&0));
+            assert_eq!(lru_cache.get(&1), None);
+            assert_eq!(lru_cache.len(), 1);
         }
 
         #[test]
-        fn it_returns_removed_expired_entries() {
-            let ttl = Duration::from_millis(200);
-            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+        fn is_evicted_when_full() {
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(
+                Duration::from_millis(50),
+                2,
+            );
+            let _ = lru_cache.insert_permanent(0, 0);
             let _ = lru_cache.insert(1, 1);
-            let _ = lru_cache.insert(2, 2);
-            sleep(250);
+            assert_eq!(lru_cache.notify_insert_full(2, 2).evicted, vec![(0, 0)]);
+        }
 
-            let (_replaced, expired) = lru_cache.notify_insert(3, 3);
+        #[test]
+        fn replacing_makes_it_expire_again() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            let _ = lru_cache.insert_permanent(0, 0);
+            assert_eq!(lru_cache.insert(0, 1), Some(0));
+            sleep(100);
+            assert!(!lru_cache.contains_key(&0));
+        }
+    }
 
-            assert_eq!(expired.len(), 2);
-            assert_eq!(expired[0], (1, 1));
-            assert_eq!(expired[1], (2, 2));
+    mod expired_introspection {
+        use super::*;
+
+        #[test]
+        fn reports_expired_entries_not_removed_yet() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert_permanent(1, 1);
+            assert_eq!(lru_cache.expired_len(), 0);
+
+            sleep(100);
+            assert!(!lru_cache.contains_key(&0));
+            assert!(lru_cache.contains_key_ignore_expiry(&0));
+            assert_eq!(lru_cache.len(), 1);
+            assert_eq!(lru_cache.len_with_expired(), 2);
+            assert_eq!(lru_cache.expired_len(), 1);
+
+            assert_eq!(lru_cache.pop_expired(), Some((0, 0)));
+            assert!(!lru_cache.contains_key_ignore_expiry(&0));
+            assert_eq!(lru_cache.len_with_expired(), 1);
+            assert_eq!(lru_cache.expired_len(), 0);
         }
     }
 
-    mod iter {
+    mod remaining_capacity {
         use super::*;
 
         #[test]
-        fn it_returns_none_when_cache_is_empty() {
-            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+        fn counts_unexpired_entries() {
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(
+                Duration::from_millis(50),
+                2,
+            );
+            assert!(!lru_cache.is_full());
+            assert_eq!(lru_cache.remaining_capacity(), 2);
 
-            let next = lru_cache.iter().next();
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert_permanent(1, 1);
+            assert!(lru_cache.is_full());
+            assert_eq!(lru_cache.remaining_capacity(), 0);
+
+            sleep(100);
+            assert!(!lru_cache.is_full());
+            assert_eq!(lru_cache.remaining_capacity(), 1);
+            assert!(lru_cache.notify_insert_full(2, 2).evicted.is_empty());
+        }
 
-            assert!(next.is_none());
+        #[test]
+        fn unlimited_caches_are_never_full() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_secs(10));
+            let _ = lru_cache.insert(0, 0);
+            assert!(!lru_cache.is_full());
+            assert_eq!(lru_cache.remaining_capacity(), usize::MAX - 1);
         }
+    }
+
+    mod evict {
+        use super::*;
 
         #[test]
-        fn it_updates_item_timestamps_of_traversed_items() {
-            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
+        fn evicts_coldest_unexpired_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
             let _ = lru_cache.insert(0, 0);
-            sleep(1);
-            let _ = lru_cache.insert(1, 1);
-            sleep(1);
-            let _ = lru_cache.insert(2, 2);
-            sleep(1);
+            sleep(100);
+            lru_cache.extend((1..5).map(|i| (i, i)));
+            let _ = lru_cache.get(&1);
+            assert!(lru_cache.pin(&2));
+
+            assert_eq!(lru_cache.evict(2), vec![(3, 3), (4, 4)]);
+            assert_eq!(lru_cache.len_with_expired(), 2);
+            assert_eq!(lru_cache.evict(5), vec![(1, 1)]);
+            assert_eq!(lru_cache.len(), 1);
+        }
+    }
 
-            let initial_instant0 = lru_cache.map[&0].1;
-            let initial_instant2 = lru_cache.map[&2].1;
-            sleep(1);
+    mod evict_older_than {
+        use super::*;
 
-            // only the first two entries should have their timestamp updated (and position in list)
-            let _ = lru_cache.iter().take(2).all(|_| true);
+        #[test]
+        fn evicts_entries_idle_for_longer() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..4).map(|i| (i, i)));
+            assert!(lru_cache.pin(&3));
+            sleep(100);
+            let _ = lru_cache.get(&1);
+            let _ = lru_cache.insert(4, 4);
 
-            assert_ne!(lru_cache.map[&2].1, initial_instant2);
-            assert_eq!(lru_cache.map[&0].1, initial_instant0);
+            assert_eq!(
+                lru_cache.evict_older_than(Duration::from_millis(50)),
+                vec![(0, 0), (2, 2)]
+            );
+            assert_eq!(lru_cache.stats().evictions, 2);
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&4, &4), (&1, &1), (&3, &3)]
+            );
+            assert!(lru_cache
+                .evict_older_than(Duration::from_millis(50))
+                .is_empty());
         }
+    }
+
+    mod try_insert {
+        use super::*;
 
         #[test]
-        fn it_moves_traversed_items_to_the_top_of_the_cache() {
-            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
-            let _ = lru_cache.insert(0, 0);
+        fn inserts_absent_keys_only() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            *lru_cache.try_insert(0, 0).ok().unwrap() += 1;
+            assert_eq!(lru_cache.peek(&0), Some(&1));
             let _ = lru_cache.insert(1, 1);
-            let _ = lru_cache.insert(2, 2);
 
-            let _ = lru_cache.iter().take(2).all(|_| true);
-
-            assert_eq!(*lru_cache.list.front().unwrap(), 0);
-            assert_eq!(*lru_cache.list.back().unwrap(), 1);
+            let error = match lru_cache.try_insert(0, 10) {
+                Ok(_) => panic!("key 0 should exist"),
+                Err(error) => error,
+            };
+            assert_eq!((error.key, error.value), (0, 10));
+            *error.entry.into_mut() += 1;
+            assert_eq!(lru_cache.peek(&0), Some(&2));
+            // The existing entry was accessed.
+            assert_eq!(lru_cache.notify_insert_full(2, 2).evicted, vec![(1, 1)]);
         }
 
         #[test]
-        fn it_yields_the_most_recent_items_first() {
-            let mut lru_cache = LruCache::<usize, usize>::with_capacity(4);
-            let _ = lru_cache.insert(2, 2);
-            let _ = lru_cache.insert(0, 0);
-            let _ = lru_cache.insert(3, 3);
-            let _ = lru_cache.insert(1, 1);
-
-            let cached = lru_cache.iter().collect::<Vec<_>>();
+        fn replaces_expired_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            let _ = lru_cache.insert(0, 0);
+            sleep(100);
+            assert_eq!(lru_cache.try_insert(0, 1).ok(), Some(&mut 1));
+        }
 
-            assert_eq!(cached, vec![(&1, &1), (&3, &3), (&0, &0), (&2, &2)]);
+        #[test]
+        fn error_display() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            let _ = lru_cache.insert(0, 0);
+            let error = lru_cache
+                .try_insert(0, 1)
+                .err()
+                .map(|error| error.to_string());
+            assert_eq!(
+                error.as_deref(),
+                Some("failed to insert 1, key 0 already exists with value 0")
+            );
         }
+    }
+
+    mod update {
+        use super::*;
 
         #[test]
-        fn it_removes_expired_items() {
-            let mut lru_cache =
-                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(3));
+        fn applies_the_closure_to_unexpired_values() {
+            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration_and_capacity(
+                Duration::from_millis(50),
+                2,
+            );
             let _ = lru_cache.insert(0, 0);
-            sleep(1);
             let _ = lru_cache.insert(1, 1);
-            sleep(4);
+            assert_eq!(
+                lru_cache.update(&0, |value| {
+                    *value += 10;
+                    *value * 2
+                }),
+                Some(20)
+            );
+            assert_eq!(lru_cache.peek(&0), Some(&10));
             let _ = lru_cache.insert(2, 2);
+            assert!(!lru_cache.contains_key(&1));
 
-            let items: Vec<_> = lru_cache.iter().collect();
-
-            assert_eq!(items.len(), 1);
-            assert_eq!(items[0], (&2, &2));
+            sleep(60);
+            assert_eq!(lru_cache.update(&0, |_| ()), None);
         }
     }
 
-    mod notify_iter {
+    mod upsert {
         use super::*;
 
         #[test]
-        fn it_returns_none_when_cache_is_empty() {
-            let mut lru_cache = LruCache::<usize, usize>::with_capacity(3);
-
-            let next = lru_cache.notify_iter().next();
+        fn inserts_or_updates() {
+            let mut lru_cache =
+                LruCache::<&str, usize>::with_expiry_duration(Duration::from_millis(50));
+            for word in ["a", "b", "a", "a"] {
+                let _ = lru_cache.upsert(word, || 1, |count| *count += 1);
+            }
+            assert_eq!(lru_cache.peek(&"a"), Some(&3));
+            assert_eq!(lru_cache.peek(&"b"), Some(&1));
 
-            assert!(next.is_none());
+            assert!(!lru_cache.upsert("b", || unreachable!(), |count| *count += 1));
+            sleep(60);
+            assert!(lru_cache.upsert("a", || 10, |_| unreachable!()));
+            assert_eq!(lru_cache.peek(&"a"), Some(&10));
         }
+    }
+
+    mod get_or_insert {
+        use super::*;
 
         #[test]
-        fn it_yields_the_most_recent_items_first() {
-            let mut lru_cache = LruCache::<usize, usize>::with_capacity(4);
-            let _ = lru_cache.insert(2, 2);
-            let _ = lru_cache.insert(0, 0);
-            let _ = lru_cache.insert(3, 3);
+        fn inserts_only_if_absent() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(2);
+            *lru_cache.get_or_insert(0, 0) += 1;
+            assert_eq!(*lru_cache.get_or_insert(0, 10), 1);
             let _ = lru_cache.insert(1, 1);
-
-            let cached = lru_cache
-                .notify_iter()
-                .map(|entry| match entry {
-                    TimedEntry::Valid(key, value) => (key, value),
-                    _ => panic!("Unexpected expired entry"),
-                })
-                .collect::<Vec<_>>();
-
-            assert_eq!(cached, vec![(&1, &1), (&3, &3), (&0, &0), (&2, &2)]);
+            assert_eq!(*lru_cache.get_or_insert(0, 10), 1);
+            assert_eq!(lru_cache.notify_insert_full(2, 2).evicted, vec![(1, 1)]);
         }
+    }
+
+    mod get_or_insert_with_ttl {
+        use super::*;
 
         #[test]
-        fn it_produces_expired_and_valid_entries() {
+        fn gives_inserted_values_their_own_ttl() {
             let mut lru_cache =
-                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(300));
-            let _ = lru_cache.insert(0, 0);
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(100));
+            assert_eq!(
+                *lru_cache.get_or_insert_with_ttl(0, Duration::from_millis(20), || 0),
+                0
+            );
             let _ = lru_cache.insert(1, 1);
-            sleep(250);
-            let _ = lru_cache.insert(2, 2);
-            sleep(60);
-
-            let expired: Vec<_> = lru_cache
-                .notify_iter()
-                .filter_map(|entry| match entry {
-                    TimedEntry::Expired(key, value) => Some((key, value)),
-                    _ => None,
-                })
-                .collect();
-            let valid: Vec<_> = lru_cache
-                .notify_iter()
-                .filter_map(|entry| match entry {
-                    TimedEntry::Valid(key, value) => Some((key, value)),
-                    _ => None,
-                })
-                .collect();
+            assert_eq!(
+                *lru_cache.get_or_insert_with_ttl(1, Duration::from_millis(20), || unreachable!()),
+                1
+            );
+            assert!(lru_cache.remaining_ttl(&0).unwrap() <= Duration::from_millis(20));
+            assert!(lru_cache.remaining_ttl(&1).unwrap() > Duration::from_millis(20));
 
-            assert_eq!(valid.len(), 1);
-            assert_eq!(valid[0], (&2, &2));
-            assert_eq!(expired.len(), 2);
-            assert_eq!(expired[0], (1, 1));
-            assert_eq!(expired[1], (0, 0));
+            sleep(30);
+            assert_eq!(lru_cache.peek(&0), None);
+            assert_eq!(lru_cache.peek(&1), Some(&1));
         }
+    }
+
+    mod replace {
+        use super::*;
 
         #[test]
-        fn it_removes_expired_items() {
+        fn reports_when_the_old_value_was_written() {
             let mut lru_cache =
-                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(300));
-            let _ = lru_cache.insert(0, 0);
-            let _ = lru_cache.insert(1, 1);
-            sleep(250);
-            let _ = lru_cache.insert(2, 2);
-            sleep(60);
-
-            let _items: Vec<_> = lru_cache.notify_iter().collect();
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(100));
+            let before = Instant::now();
+            assert_eq!(lru_cache.replace(0, 0), None);
+            sleep(10);
+            let _ = lru_cache.get(&0);
+
+            let (old, written) = lru_cache.replace(0, 1).unwrap();
+            assert_eq!(old, 0);
+            assert!(written >= before);
+            assert!(Instant::now().saturating_duration_since(written) >= Duration::from_millis(10));
+            assert_eq!(lru_cache.peek(&0), Some(&1));
 
-            assert!(lru_cache.get(&0).is_none());
-            assert!(lru_cache.get(&1).is_none());
+            sleep(150);
+            assert_eq!(lru_cache.replace(0, 2), None);
         }
     }
 
-    mod peek_iter {
+    mod remove_entry {
         use super::*;
 
         #[test]
-        fn it_yields_cached_entries_in_most_recently_used_order() {
-            let time_to_live = Duration::from_millis(500);
-            let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
-
-            let _ = lru_cache.insert(1, 1);
-            let _ = lru_cache.insert(2, 2);
-            let _ = lru_cache.insert(3, 3);
+        fn returns_the_stored_key() {
+            let mut lru_cache = LruCache::<String, usize>::with_capacity(2);
+            let _ = lru_cache.insert("zero".to_string(), 0);
+            let _ = lru_cache.insert("one".to_string(), 1);
 
             assert_eq!(
-                vec![(&3, &3), (&2, &2), (&1, &1)],
-                lru_cache.peek_iter().collect::<Vec<_>>()
+                lru_cache.remove_entry("zero"),
+                Some(("zero".to_string(), 0))
             );
+            assert_eq!(lru_cache.remove_entry("zero"), None);
+            assert_eq!(lru_cache.list, vec!["one".to_string()]);
+            assert_eq!(lru_cache.len(), 1);
         }
+    }
+
+    mod remove_range {
+        use super::*;
 
         #[test]
-        fn it_yields_only_unexpired_entries() {
-            let time_to_live = Duration::from_millis(500);
-            let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+        fn removes_keys_within_range() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..6).map(|i| (i, i)));
+            let _ = lru_cache.get(&2);
+
+            assert_eq!(lru_cache.remove_range(1..4), vec![(1, 1), (2, 2), (3, 3)]);
+            assert_eq!(lru_cache.remove_range(1..4), vec![]);
+            assert_eq!(lru_cache.list, vec![0, 4, 5]);
+            assert_eq!(lru_cache.remove_range(5..), vec![(5, 5)]);
+            assert_eq!(lru_cache.len(), 2);
+        }
 
-            let _ = lru_cache.insert(1, 1);
-            let _ = lru_cache.insert(2, 2);
-            sleep(300);
-            let _ = lru_cache.insert(3, 3);
-            sleep(300);
+        #[test]
+        fn removes_keys_with_prefix() {
+            let mut lru_cache = LruCache::<String, usize>::with_capacity(10);
+            for (i, key) in [
+                "/users/4",
+                "/users/42",
+                "/users/42/a",
+                "/users/42/b",
+                "/users/43/a",
+            ]
+            .iter()
+            .enumerate()
+            {
+                let _ = lru_cache.insert(key.to_string(), i);
+            }
 
-            let entries = lru_cache.peek_iter().collect::<Vec<_>>();
+            assert_eq!(
+                lru_cache.remove_prefix("/users/42/"),
+                vec![
+                    ("/users/42/a".to_string(), 2),
+                    ("/users/42/b".to_string(), 3)
+                ]
+            );
+            assert_eq!(lru_cache.list, vec!["/users/4", "/users/42", "/users/43/a"]);
 
-            assert_eq!(entries, vec![(&3, &3)]);
+            let mut lru_cache = LruCache::<Vec<u8>, usize>::with_capacity(10);
+            let _ = lru_cache.insert(vec![1, 2], 0);
+            let _ = lru_cache.insert(vec![1, 2, 255], 1);
+            let _ = lru_cache.insert(vec![1, 3], 2);
+            assert_eq!(
+                lru_cache.remove_prefix(&[1, 2]),
+                vec![(vec![1, 2], 0), (vec![1, 2, 255], 1)]
+            );
+            assert_eq!(lru_cache.len(), 1);
         }
+    }
+
+    mod remove_where {
+        use super::*;
 
         #[test]
-        fn it_doesnt_modify_entry_update_time() {
-            let time_to_live = Duration::from_millis(500);
-            let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+        fn returns_removed_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            lru_cache.extend((0..6).map(|i| (i, i * 10)));
+            assert!(lru_cache.set_entry_ttl(&0, Duration::from_millis(10)));
+            let _ = lru_cache.get(&2);
+            sleep(20);
+
+            let removed = lru_cache.remove_where(|key, value| key % 2 == 0 || *value > 40);
+            assert_eq!(removed, vec![(4, 40), (5, 50), (2, 20)]);
+            assert_eq!(lru_cache.list, vec![1, 3]);
+            assert_eq!(lru_cache.remove_where(|_, _| false), vec![]);
+        }
+    }
 
-            let _ = lru_cache.insert(1, 1);
-            let expected_time = lru_cache
-                .map
-                .values()
-                .map(|(_, updated_at)| updated_at)
-                .next()
-                .unwrap();
+    mod get_key_value {
+        use super::*;
 
-            let _ = lru_cache.peek_iter().collect::<Vec<_>>();
+        #[test]
+        fn returns_the_stored_key() {
+            let mut lru_cache = LruCache::<String, usize>::with_capacity(2);
+            let _ = lru_cache.insert("zero".to_string(), 0);
+            let _ = lru_cache.insert("one".to_string(), 1);
 
-            let real_time = lru_cache
-                .map
-                .values()
-                .map(|(_, updated_at)| updated_at)
-                .next()
-                .unwrap();
-            assert_eq!(real_time, expected_time);
+            assert_eq!(
+                lru_cache.peek_key_value("zero"),
+                Some((&"zero".to_string(), &0))
+            );
+            assert_eq!(lru_cache.list[0], "zero");
+            assert_eq!(
+                lru_cache.get_key_value("zero"),
+                Some((&"zero".to_string(), &0))
+            );
+            assert_eq!(lru_cache.list[0], "one");
+            assert_eq!(lru_cache.get_key_value("two"), None);
+            assert_eq!(lru_cache.peek_key_value("two"), None);
         }
-    }
 
-    #[test]
-    fn update_time_check() {
-        let time_to_live = Duration::from_millis(500);
-        let mut lru_cache = super::LruCache::<usize, usize>::with_expiry_duration(time_to_live);
+        #[test]
+        fn skips_expired_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            let _ = lru_cache.insert(0, 0);
+            sleep(100);
+            assert_eq!(lru_cache.peek_key_value(&0), None);
+            assert_eq!(lru_cache.get_key_value(&0), None);
+        }
+    }
 
-        assert_eq!(lru_cache.len(), 0);
-        let _ = lru_cache.insert(0, 0);
-        assert_eq!(lru_cache.len(), 1);
+    mod get_with_generation {
+        use super::*;
 
-        sleep(300);
-        assert_eq!(Some(&0), lru_cache.get(&0));
-        sleep(300);
-        assert_eq!(Some(&0), lru_cache.peek(&0));
-        sleep(300);
-        assert_eq!(None, lru_cache.peek(&0));
-    }
+        #[test]
+        fn bumps_on_every_write() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            let _ = lru_cache.insert(0, 0);
+            let _ = lru_cache.insert(1, 1);
+            let (_, first) = lru_cache.get_with_generation(&0).unwrap();
+            let (_, second) = lru_cache.get_with_generation(&1).unwrap();
+            assert!(second > first);
+
+            // Mutating in place keeps the generation, replacing doesn't.
+            *lru_cache.get_mut(&0).unwrap() = 2;
+            assert_eq!(lru_cache.get_with_generation(&0), Some((&2, first)));
+            let _ = lru_cache.insert(0, 2);
+            let (_, third) = lru_cache.get_with_generation(&0).unwrap();
+            assert!(third > second);
+            assert_eq!(lru_cache.metadata(&0).unwrap().generation, third);
+
+            // Removing and reinserting the same value still gives a new generation.
+            let _ = lru_cache.remove(&0);
+            let _ = lru_cache.insert(0, 2);
+            assert!(lru_cache.get_with_generation(&0).unwrap().1 > third);
+            assert_eq!(lru_cache.get_with_generation(&3), None);
+        }
 
-    #[test]
-    fn deref_coercions() {
-        let mut lru_cache = super::LruCache::<String, usize>::with_capacity(1);
-        let _ = lru_cache.insert("foo".to_string(), 0);
-        assert_eq!(true, lru_cache.contains_key("foo"));
-        assert_eq!(Some(&0), lru_cache.get("foo"));
-        assert_eq!(Some(&mut 0), lru_cache.get_mut("foo"));
-        assert_eq!(Some(&0), lru_cache.peek("foo"));
-        assert_eq!(Some(0), lru_cache.remove("foo"));
+        #[test]
+        fn carries_over_to_split_caches() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..4).map(|i| (i, i)));
+            let last = lru_cache.get_with_generation(&3).unwrap().1;
+            let mut other = lru_cache.split_off(&2);
+            assert_eq!(other.get_with_generation(&3).unwrap().1, last);
+            let _ = other.insert(4, 4);
+            assert!(other.get_with_generation(&4).unwrap().1 > last);
+        }
     }
 
-    mod remove_expired {
+    mod peek_entry {
         use super::*;
 
         #[test]
-        fn it_removes_expired_entries_from_the_map() {
-            let ttl = Duration::from_millis(200);
+        fn views_the_entry_without_updating_it() {
+            let ttl = Duration::from_millis(100);
             let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
+            let _ = lru_cache.insert(0, 0);
             let _ = lru_cache.insert(1, 1);
-            let _ = lru_cache.insert(2, 2);
-            sleep(150);
-            let _ = lru_cache.insert(3, 3);
-            let _ = lru_cache.insert(4, 4);
-            sleep(60);
+            let inserted = lru_cache.map[&0].timestamp;
+            sleep(10);
 
-            let now = Instant::now();
-            let _ = lru_cache.remove_expired(now);
+            let view = lru_cache.peek_entry(&0).unwrap();
+            assert_eq!((view.key, view.value), (&0, &0));
+            assert_eq!(view.last_accessed, inserted);
+            assert!(view.remaining_ttl.unwrap() <= ttl - Duration::from_millis(10));
+            assert_eq!(lru_cache.list, vec![0, 1]);
+            assert_eq!(lru_cache.peek_entry(&2), None);
 
-            assert_eq!(lru_cache.map.len(), 2);
-            assert_eq!(lru_cache.map[&3].0, 3);
-            assert_eq!(lru_cache.map[&4].0, 4);
+            sleep(150);
+            assert_eq!(lru_cache.peek_entry(&0), None);
         }
 
         #[test]
-        fn it_removes_expired_entries_from_the_list() {
-            let ttl = Duration::from_millis(200);
-            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
-            let _ = lru_cache.insert(1, 1);
-            let _ = lru_cache.insert(2, 2);
-            sleep(150);
-            let _ = lru_cache.insert(3, 3);
-            let _ = lru_cache.insert(4, 4);
-            sleep(60);
+        fn entries_without_time_to_live() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(1);
+            let _ = lru_cache.insert(0, 0);
+            assert_eq!(lru_cache.peek_entry(&0).unwrap().remaining_ttl, None);
+        }
+    }
 
-            let now = Instant::now();
-            let _ = lru_cache.remove_expired(now);
+    mod first_and_last_key_value {
+        use super::*;
 
-            assert_eq!(lru_cache.list.len(), 2);
-            assert_eq!(lru_cache.list[0], 3);
-            assert_eq!(lru_cache.list[1], 4);
+        #[test]
+        fn skip_expired_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            assert_eq!(lru_cache.first_key_value(), None);
+            assert_eq!(lru_cache.last_key_value(), None);
+
+            lru_cache.extend((0..5).map(|i| (i, i)));
+            assert_eq!(lru_cache.first_key_value(), Some((&0, &0)));
+            assert_eq!(lru_cache.last_key_value(), Some((&4, &4)));
+
+            assert!(lru_cache.set_entry_ttl(&0, Duration::from_millis(10)));
+            assert!(lru_cache.set_entry_ttl(&4, Duration::from_millis(10)));
+            sleep(20);
+            assert_eq!(lru_cache.first_key_value(), Some((&1, &1)));
+            assert_eq!(lru_cache.last_key_value(), Some((&3, &3)));
+            assert_eq!(lru_cache.list, vec![0, 1, 2, 3, 4]);
+        }
+    }
+
+    mod cursor {
+        use super::*;
+
+        fn keys(lru_cache: &LruCache<usize, usize>) -> Vec<usize> {
+            lru_cache.peek_iter().map(|(key, _)| *key).collect()
         }
 
         #[test]
-        fn it_returns_expired_entries() {
-            let ttl = Duration::from_millis(200);
-            let mut lru_cache = LruCache::<usize, usize>::with_expiry_duration(ttl);
-            let _ = lru_cache.insert(1, 1);
-            let _ = lru_cache.insert(2, 2);
-            sleep(150);
-            let _ = lru_cache.insert(3, 3);
-            sleep(60);
+        fn traverses_in_both_directions() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..3).map(|i| (i, i)));
+            let mut cursor = lru_cache.cursor_mut();
+            assert_eq!(cursor.key(), Some(&2));
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(cursor.key(), Some(&0));
+            cursor.move_next();
+            assert_eq!(cursor.key(), None);
+            cursor.move_prev();
+            assert_eq!(cursor.key(), Some(&0));
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(cursor.key(), Some(&2));
+            cursor.move_prev();
+            assert_eq!(cursor.key(), None);
+            cursor.move_prev();
+            assert_eq!(cursor.key(), Some(&0));
+        }
 
-            let now = Instant::now();
-            let expired = lru_cache.remove_expired(now);
+        #[test]
+        fn updates_and_removes_entries() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..5).map(|i| (i, i)));
+            let mut cursor = lru_cache.cursor_mut();
+            while let Some((key, value)) = cursor.current() {
+                if key % 2 == 0 {
+                    *value *= 10;
+                    cursor.move_next();
+                } else {
+                    assert_eq!(cursor.remove_current().map(|(key, _)| key % 2), Some(1));
+                }
+            }
+            assert_eq!(
+                lru_cache.peek_iter().collect::<Vec<_>>(),
+                vec![(&4, &40), (&2, &20), (&0, &0)]
+            );
+            assert_eq!(lru_cache.len(), 3);
+        }
 
-            assert_eq!(expired.len(), 2);
-            assert_eq!(expired[0], (1, 1));
-            assert_eq!(expired[1], (2, 2));
+        #[test]
+        fn repositions_entries() {
+            let mut lru_cache = LruCache::<usize, usize>::with_capacity(10);
+            lru_cache.extend((0..5).map(|i| (i, i)));
+            let mut cursor = lru_cache.cursor_mut();
+            cursor.move_next();
+            assert!(cursor.move_current_to_back());
+            assert_eq!(cursor.key(), Some(&2));
+            assert!(cursor.move_current_to_front());
+            assert_eq!(cursor.key(), Some(&1));
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(cursor.key(), Some(&3));
+            assert!(cursor.move_current_to_back());
+            assert_eq!(cursor.key(), None);
+            assert!(!cursor.move_current_to_front());
+            assert_eq!(keys(&lru_cache), vec![2, 4, 1, 0, 3]);
+        }
+
+        #[test]
+        fn skips_expired_entries() {
+            let mut lru_cache =
+                LruCache::<usize, usize>::with_expiry_duration(Duration::from_millis(50));
+            lru_cache.set_expiry_budget(Some(0));
+            lru_cache.extend((0..3).map(|i| (i, i)));
+            assert!(lru_cache.set_entry_ttl(&1, Duration::from_millis(10)));
+            sleep(20);
+
+            let mut cursor = lru_cache.cursor_mut();
+            cursor.move_next();
+            assert_eq!(cursor.key(), Some(&0));
+            cursor.move_prev();
+            assert_eq!(cursor.key(), Some(&2));
         }
     }
 }
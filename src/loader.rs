@@ -0,0 +1,85 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Read-through caching, populating misses from a loader.
+
+use crate::LruCache;
+
+/// Source of the values of a `LoadingCache`.
+pub trait CacheLoader<Key, Value> {
+    /// Returns the value of `key`, or `None` if it has none.
+    fn load(&self, key: &Key) -> Option<Value>;
+}
+
+impl<Key, Value, F> CacheLoader<Key, Value> for F
+where
+    F: Fn(&Key) -> Option<Value>,
+{
+    fn load(&self, key: &Key) -> Option<Value> {
+        self(key)
+    }
+}
+
+/// `LruCache` which transparently loads the values missing from the cache.
+///
+/// Loaded values are inserted into the cache like any other, so they are subject to its time to
+/// live, eviction and admission policies.  Keys the loader has no value for aren't cached; use an
+/// `Option` value type and a loader returning `Some(None)` to cache misses as well.
+pub struct LoadingCache<Key, Value, Loader> {
+    cache: LruCache<Key, Value>,
+    loader: Loader,
+}
+
+impl<Key, Value, Loader> LoadingCache<Key, Value, Loader>
+where
+    Key: Ord + Clone,
+    Loader: CacheLoader<Key, Value>,
+{
+    /// Constructs a `LoadingCache` populating `cache` from `loader`.
+    pub fn new(cache: LruCache<Key, Value>, loader: Loader) -> Self {
+        Self { cache, loader }
+    }
+
+    /// Retrieves a reference to the value stored under `key`, loading and inserting it if it
+    /// isn't cached.  Returns `None` if the loader has no value for `key`, or the admission policy
+    /// turned the loaded value down.
+    pub fn get(&mut self, key: &Key) -> Option<&Value> {
+        self.get_mut(key).map(|value| &*value)
+    }
+
+    /// Retrieves a mutable reference to the value stored under `key`, loading and inserting it if
+    /// it isn't cached.
+    pub fn get_mut(&mut self, key: &Key) -> Option<&mut Value> {
+        if self.cache.get(key).is_none() {
+            let value = self.loader.load(key)?;
+            let _ = self.cache.insert(key.clone(), value);
+        }
+        self.cache.peek_mut(key)
+    }
+
+    /// Returns the underlying cache, e.g. to inspect it without loading anything.
+    pub fn cache(&self) -> &LruCache<Key, Value> {
+        &self.cache
+    }
+
+    /// Returns the underlying cache, e.g. to insert or invalidate entries directly.
+    pub fn cache_mut(&mut self) -> &mut LruCache<Key, Value> {
+        &mut self.cache
+    }
+
+    /// Returns the loader.
+    pub fn loader(&self) -> &Loader {
+        &self.loader
+    }
+
+    /// Unwraps the underlying cache and the loader.
+    pub fn into_parts(self) -> (LruCache<Key, Value>, Loader) {
+        (self.cache, self.loader)
+    }
+}
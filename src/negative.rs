@@ -0,0 +1,64 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Negative caching, i.e. remembering for a while that a key has no value.
+
+use crate::LruCache;
+use std::time::Duration;
+
+/// Time to live given to the values recording a miss.
+pub(crate) struct MissTtl<Value> {
+    pub time_to_live: Duration,
+    pub is_miss: fn(&Value) -> bool,
+}
+
+impl<Value> Clone for MissTtl<Value> {
+    fn clone(&self) -> Self {
+        Self {
+            time_to_live: self.time_to_live,
+            is_miss: self.is_miss,
+        }
+    }
+}
+
+impl<Value> MissTtl<Value> {
+    /// Returns the time to live of `value` if it records a miss.
+    pub fn time_to_live(&self, value: &Value) -> Option<Duration> {
+        if (self.is_miss)(value) {
+            Some(self.time_to_live)
+        } else {
+            None
+        }
+    }
+}
+
+impl<Key, Value> LruCache<Key, Option<Value>>
+where
+    Key: Ord + Clone,
+{
+    /// Sets the time to live of the `None` values, which record that a key has no value, or makes
+    /// them expire like any other entry if `None`.
+    ///
+    /// Misses are typically cached for a shorter time than hits, so that keys which get a value
+    /// later on are soon picked up, while still sparing the source of the values repeated lookups
+    /// of keys which don't exist.  Applies to the misses inserted from then on, as if by
+    /// `set_entry_ttl()`.
+    pub fn set_miss_time_to_live(&mut self, time_to_live: Option<Duration>) {
+        self.miss_time_to_live = time_to_live.map(|time_to_live| MissTtl {
+            time_to_live,
+            is_miss: Option::is_none,
+        });
+    }
+
+    /// Records that `key` has no value, replacing its value if any.  Short for
+    /// `insert(key, None)`.
+    pub fn insert_miss(&mut self, key: Key) -> Option<Option<Value>> {
+        self.insert(key, None)
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Recording of the changes made to a cache, so they can be replayed offline.
+
+use crate::events::CacheEvent;
+use crate::{Instant, LruCache};
+use std::time::Duration;
+
+/// Change made to a cache, as recorded in an `OpLog`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op<Key, Value> {
+    /// A value was inserted, or replaced the previous one.
+    Insert(Key, Value),
+    /// An entry was read, e.g. by `get()`.
+    Access(Key),
+    /// An entry was removed on request.
+    Remove(Key),
+    /// An entry expired and was removed.
+    Expire(Key),
+    /// An entry was evicted to make room for others, or to fit a reduced capacity.
+    Evict(Key),
+}
+
+/// An `Op` along with the time it happened at, relative to the start of the recording.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpRecord<Key, Value> {
+    /// Time elapsed between the start of the recording and the change.
+    pub offset: Duration,
+    /// The change.
+    pub op: Op<Key, Value>,
+}
+
+/// Log of the changes made to a cache since `LruCache::start_recording()` was called.
+#[derive(Clone, Debug)]
+pub struct OpLog<Key, Value> {
+    start: Instant,
+    records: Vec<OpRecord<Key, Value>>,
+}
+
+impl<Key, Value> OpLog<Key, Value> {
+    fn new(start: Instant) -> Self {
+        Self {
+            start,
+            records: Vec::new(),
+        }
+    }
+
+    /// Returns the recorded changes, in the order they happened.
+    pub fn records(&self) -> &[OpRecord<Key, Value>] {
+        &self.records
+    }
+
+    /// Returns the number of recorded changes.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns `true` if no change has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn push(&mut self, op: Op<Key, Value>) {
+        let offset = Instant::now().saturating_duration_since(self.start);
+        self.records.push(OpRecord { offset, op });
+    }
+}
+
+impl<Key, Value> OpLog<Key, Value>
+where
+    Key: Ord + Clone,
+    Value: Clone,
+{
+    /// Replays the recorded changes on `cache`, which should be empty and configured like the
+    /// recorded one, e.g. to reproduce an eviction offline.
+    ///
+    /// The inserts, accesses and removals are replayed at their recorded times, shifted so that
+    /// the last change happens now, using `insert_at()`, `get_at()` and `remove()`.  Expirations
+    /// and evictions aren't replayed, since the cache reproduces them itself as long as it is
+    /// configured the same way.
+    pub fn replay(&self, mut cache: LruCache<Key, Value>) -> LruCache<Key, Value> {
+        let now = Instant::now();
+        let last = self
+            .records
+            .last()
+            .map_or(Duration::ZERO, |record| record.offset);
+        let start = now.checked_sub(last).unwrap_or(now);
+        for record in &self.records {
+            let at = start.checked_add(record.offset).unwrap_or(now);
+            match &record.op {
+                Op::Insert(key, value) => {
+                    let _ = cache.insert_at(key.clone(), value.clone(), at);
+                }
+                Op::Access(key) => {
+                    let _ = cache.get_at(key, at);
+                }
+                Op::Remove(key) => {
+                    let _ = cache.remove(key);
+                }
+                Op::Expire(_) | Op::Evict(_) => (),
+            }
+        }
+        cache
+    }
+}
+
+/// The log being recorded by a cache, if any.
+pub(crate) struct Recorder<Key, Value> {
+    log: OpLog<Key, Value>,
+    /// Clones the recorded values, which are only required to be `Clone` when recording.
+    clone_value: fn(&Value) -> Value,
+}
+
+impl<Key, Value> Recorder<Key, Value>
+where
+    Key: Clone,
+{
+    pub fn record_event(&mut self, event: CacheEvent<'_, Key, Value>) {
+        let op = match event {
+            CacheEvent::Inserted(key, value) | CacheEvent::Replaced(key, _, value) => {
+                Op::Insert(key.clone(), (self.clone_value)(value))
+            }
+            CacheEvent::Removed(key, _) => Op::Remove(key.clone()),
+            CacheEvent::Expired(key, _) => Op::Expire(key.clone()),
+            CacheEvent::Evicted(key, _) => Op::Evict(key.clone()),
+        };
+        self.log.push(op);
+    }
+
+    pub fn record_access(&mut self, key: &Key) {
+        self.log.push(Op::Access(key.clone()));
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Starts recording the changes made to the entries of the cache, and the reads of them,
+    /// discarding the previous recording if any.
+    ///
+    /// Changes to the configuration of the cache, or to the settings of individual entries such
+    /// as their time to live, aren't recorded.  Clones of the cache don't record.
+    pub fn start_recording(&mut self)
+    where
+        Value: Clone,
+    {
+        self.events.recorder = Some(Recorder {
+            log: OpLog::new(Instant::now()),
+            clone_value: Value::clone,
+        });
+    }
+
+    /// Returns the log recorded so far, or `None` if the cache isn't recording.
+    pub fn op_log(&self) -> Option<&OpLog<Key, Value>> {
+        self.events.recorder.as_ref().map(|recorder| &recorder.log)
+    }
+
+    /// Stops recording and returns the recorded log, or `None` if the cache wasn't recording.
+    pub fn stop_recording(&mut self) -> Option<OpLog<Key, Value>> {
+        self.events.recorder.take().map(|recorder| recorder.log)
+    }
+}
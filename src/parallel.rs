@@ -0,0 +1,49 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Parallel iteration using rayon.
+
+use crate::{Instant, LruCache};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone + Sync,
+    Value: Sync,
+{
+    /// Returns a parallel iterator over all the unexpired entries, in no particular order, without
+    /// updating their timestamps.
+    pub fn par_peek_iter(&self) -> impl ParallelIterator<Item = (&Key, &Value)> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.map
+            .par_iter()
+            .filter(move |(_, entry)| !entry.is_expired(expiry, now))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Retains only the entries for which `f` returns true, evaluating it on the entries in
+    /// parallel.  Also removes expired elements.
+    ///
+    /// `f` sees each unexpired entry exactly once, in no particular order.  The timestamps and
+    /// recency order of the retained entries are left untouched.
+    pub fn par_retain<F>(&mut self, f: F)
+    where
+        F: Fn(&Key, &Value) -> bool + Sync + Send,
+    {
+        self.discard_all_expired(Instant::now());
+        let rejected: Vec<&Key> = self
+            .map
+            .par_iter()
+            .filter(|(key, entry)| !f(key, &entry.value))
+            .map(|(key, _)| key)
+            .collect();
+        let rejected: Vec<Key> = rejected.into_iter().cloned().collect();
+        let _ = self.remove_many(&rejected);
+    }
+}
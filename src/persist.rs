@@ -0,0 +1,232 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Saving caches to files and loading them back.
+
+use crate::snapshot::{CacheSnapshot, SnapshotEntry};
+use crate::{ExpiryPolicy, Instant, LruCache};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Identifies the files written by `save_to_path()`.
+const MAGIC: [u8; 8] = *b"LRUTCACH";
+/// Version of the file format, bumped on every incompatible change.
+const VERSION: u32 = 1;
+
+/// Number of temporary files named so far, to tell those of concurrent saves apart.
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Settings of the cache, as stored in the file.
+type Header = (u64, Option<Duration>, u8, Option<Duration>, u64);
+/// Key, value, idle time, age, own time to live, pinned flag, permanent flag, time left until
+/// the deadline and remaining time to live of an entry, as stored in the file.
+type Record<Key, Value> = (
+    Key,
+    Value,
+    Duration,
+    Duration,
+    Option<Duration>,
+    bool,
+    bool,
+    Option<Duration>,
+    Option<Duration>,
+);
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone + Serialize,
+    Value: Serialize,
+{
+    /// Saves the unexpired entries, along with their recency order and remaining lifetime, and
+    /// the capacity and expiry settings to the file at `path`.
+    ///
+    /// The file is first written under a temporary name in the same directory, then renamed, so
+    /// `path` never holds a partially written cache, even if the process crashes meanwhile.  Each
+    /// save gets its own temporary file, so concurrent saves to the same `path` don't clobber each
+    /// other: the last one renamed wins.  Like `snapshot()`, the eviction and admission policies
+    /// aren't saved.
+    pub fn save_to_path<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temp_path = temp_path(path)?;
+        match self.write_to(&temp_path) {
+            Ok(()) => {
+                fs::rename(&temp_path, path)?;
+                sync_parent(path)
+            }
+            Err(error) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(error)
+            }
+        }
+    }
+
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        let entries: Vec<_> = self
+            .list
+            .iter()
+            .map(|key| (key, &self.map[key]))
+            .filter(|(_, entry)| !entry.is_expired(expiry, now))
+            .collect();
+        let (policy, time_to_idle) = match self.expiry_policy {
+            ExpiryPolicy::AfterAccess => (0, None),
+            ExpiryPolicy::AfterWrite => (1, None),
+            ExpiryPolicy::AfterWriteOrIdle(time_to_idle) => (2, Some(time_to_idle)),
+        };
+        let header: Header = (
+            self.capacity as u64,
+            self.time_to_live,
+            policy,
+            time_to_idle,
+            entries.len() as u64,
+        );
+
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, &header).map_err(invalid_data)?;
+        for (key, entry) in entries {
+            let record = (
+                key,
+                &entry.value,
+                now.saturating_duration_since(entry.timestamp),
+                now.saturating_duration_since(entry.written),
+                entry.time_to_live,
+                entry.pinned,
+                entry.permanent,
+                entry
+                    .expires_at
+                    .map(|deadline| deadline.saturating_duration_since(now)),
+                entry
+                    .expiry_time(expiry)
+                    .map(|expiry_time| expiry_time.saturating_duration_since(now)),
+            );
+            bincode::serialize_into(&mut writer, &record).map_err(invalid_data)?;
+        }
+        let file = writer.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone + DeserializeOwned,
+    Value: DeserializeOwned,
+{
+    /// Loads a cache saved by `save_to_path()` from the file at `path`.
+    ///
+    /// The entries carry on as if no time had passed since they were saved.  Fails with
+    /// `io::ErrorKind::InvalidData` if the file wasn't written by `save_to_path()`, or by an
+    /// incompatible version of this crate.
+    pub fn load_from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("not a saved cache"));
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != VERSION {
+            return Err(invalid_data("unsupported saved cache version"));
+        }
+
+        let (capacity, time_to_live, policy, time_to_idle, count): Header =
+            bincode::deserialize_from(&mut reader).map_err(invalid_data)?;
+        let expiry_policy = match (policy, time_to_idle) {
+            (0, None) => ExpiryPolicy::AfterAccess,
+            (1, None) => ExpiryPolicy::AfterWrite,
+            (2, Some(time_to_idle)) => ExpiryPolicy::AfterWriteOrIdle(time_to_idle),
+            _ => return Err(invalid_data("unknown expiry policy")),
+        };
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let record: Record<Key, Value> =
+                bincode::deserialize_from(&mut reader).map_err(invalid_data)?;
+            let (
+                key,
+                value,
+                idle,
+                age,
+                time_to_live,
+                pinned,
+                permanent,
+                until_deadline,
+                remaining_ttl,
+            ) = record;
+            entries.push(SnapshotEntry {
+                key,
+                value,
+                idle,
+                age,
+                time_to_live,
+                pinned,
+                permanent,
+                until_deadline,
+                remaining_ttl,
+            });
+        }
+        Ok(Self::from_snapshot(CacheSnapshot {
+            capacity: usize::try_from(capacity).unwrap_or(usize::MAX),
+            time_to_live,
+            expiry_policy,
+            entries,
+        }))
+    }
+}
+
+/// Returns a path, unique to this call, of a temporary file `path` can be written to before being
+/// renamed.
+fn temp_path(path: &Path) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(path.with_file_name(temp_name))
+}
+
+/// Flushes the directory holding `path` to disk, so that renaming a file to `path` survives a
+/// crash.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+/// Directories can't be opened, let alone flushed, on other platforms, where renames are made
+/// durable by the file system itself if at all.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
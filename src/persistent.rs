@@ -0,0 +1,217 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Cache whose clones share their contents, making snapshots cheap.
+
+use crate::Instant;
+use im::OrdMap;
+use std::borrow::Borrow;
+use std::time::Duration;
+
+#[derive(Clone)]
+struct Entry<Value> {
+    value: Value,
+    /// Time the entry was last accessed.
+    timestamp: Instant,
+    /// Position of the entry in the recency order, higher for more recently used entries.
+    tick: u64,
+}
+
+/// Variant of `LruCache` backed by persistent maps, whose clones share their contents with the
+/// original until either of them changes.
+///
+/// Cloning takes constant time, so a consistent snapshot of a large cache can be taken as often
+/// as needed, and later changes only copy the parts of the maps they touch.  In exchange, lookups
+/// and changes take logarithmic rather than constant time, and values must be `Clone` since
+/// changing an entry shared with a clone copies it.
+///
+/// Only the capacity and the time to live, measured from the last access, are supported: none of
+/// the other policies of `LruCache` are.
+#[derive(Clone)]
+pub struct PersistentLruCache<Key, Value> {
+    map: OrdMap<Key, Entry<Value>>,
+    /// Keys of the entries by tick, least recently used first.
+    list: OrdMap<u64, Key>,
+    next_tick: u64,
+    capacity: usize,
+    time_to_live: Option<Duration>,
+}
+
+impl<Key, Value> PersistentLruCache<Key, Value>
+where
+    Key: Ord + Clone,
+    Value: Clone,
+{
+    /// Constructor for capacity based `PersistentLruCache`.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::new(capacity, None)
+    }
+
+    /// Constructor for time based `PersistentLruCache`.
+    pub fn with_expiry_duration(time_to_live: Duration) -> Self {
+        Self::new(usize::MAX, Some(time_to_live))
+    }
+
+    /// Constructor for dual-feature capacity and time based `PersistentLruCache`.
+    pub fn with_expiry_duration_and_capacity(time_to_live: Duration, capacity: usize) -> Self {
+        Self::new(capacity, Some(time_to_live))
+    }
+
+    fn new(capacity: usize, time_to_live: Option<Duration>) -> Self {
+        Self {
+            map: OrdMap::new(),
+            list: OrdMap::new(),
+            next_tick: 0,
+            capacity,
+            time_to_live,
+        }
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the key already existed in the cache, the existing value is returned and overwritten in
+    /// the cache.  Otherwise, the key-value pair is inserted and `None` is returned.  Also removes
+    /// expired elements.
+    pub fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
+        let now = Instant::now();
+        self.remove_expired(now);
+        let tick = self.tick();
+        let entry = Entry {
+            value,
+            timestamp: now,
+            tick,
+        };
+        let replaced = self.map.insert(key.clone(), entry);
+        if let Some(old) = &replaced {
+            let _ = self.list.remove(&old.tick);
+        }
+        let _ = self.list.insert(tick, key);
+        while self.map.len() > self.capacity {
+            let lru = self.list.get_min().map(|(tick, _)| *tick);
+            if let Some(key) = lru.and_then(|tick| self.list.remove(&tick)) {
+                let _ = self.map.remove(&key);
+            }
+        }
+        replaced.map(|entry| entry.value)
+    }
+
+    /// Removes a key-value pair from the cache.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let entry = self.map.remove(key)?;
+        let _ = self.list.remove(&entry.tick);
+        Some(entry.value)
+    }
+
+    /// Retrieves a reference to the value stored under `key`, or `None` if the key doesn't exist.
+    /// Also removes expired elements and updates the time.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = Instant::now();
+        self.remove_expired(now);
+        let tick = self.tick();
+        let entry = self.map.get_mut(key)?;
+        if let Some(key) = self.list.remove(&entry.tick) {
+            let _ = self.list.insert(tick, key);
+        }
+        entry.tick = tick;
+        entry.timestamp = now;
+        Some(&entry.value)
+    }
+
+    /// Returns a reference to the value stored under `key`, if present and not expired, without
+    /// updating the timestamp.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let now = Instant::now();
+        self.map
+            .get(key)
+            .filter(|entry| !self.is_expired(entry, now))
+            .map(|entry| &entry.value)
+    }
+
+    /// Returns whether `key` exists in the cache or not.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Returns the size of the cache, i.e. the number of cached non-expired key-value pairs.
+    pub fn len(&self) -> usize {
+        let now = Instant::now();
+        // Expired entries are the least recently used ones.
+        let expired = self
+            .list
+            .values()
+            .take_while(|key| self.map.get(*key).is_some_and(|e| self.is_expired(e, now)))
+            .count();
+        self.map.len() - expired
+    }
+
+    /// Returns `true` if there are no non-expired entries in the cache.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over all unexpired entries that does not modify the timestamps.
+    /// Values are produced in the most recently used order.
+    pub fn peek_iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        let now = Instant::now();
+        self.list
+            .values()
+            .rev()
+            .filter_map(move |key| {
+                self.map
+                    .get_key_value(key)
+                    .filter(|(_, entry)| !self.is_expired(entry, now))
+            })
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Clears the cache, removing all values.  Clones keep their contents.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.list.clear();
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    fn is_expired(&self, entry: &Entry<Value>, now: Instant) -> bool {
+        self.time_to_live
+            .and_then(|time_to_live| entry.timestamp.checked_add(time_to_live))
+            .is_some_and(|expiry_time| expiry_time < now)
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        while let Some((tick, key)) = self.list.get_min() {
+            if !self.map.get(key).is_some_and(|e| self.is_expired(e, now)) {
+                break;
+            }
+            let tick = *tick;
+            if let Some(key) = self.list.remove(&tick) {
+                let _ = self.map.remove(&key);
+            }
+        }
+    }
+}
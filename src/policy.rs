@@ -0,0 +1,244 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Policies deciding which entries to evict when the cache is full.
+
+use crate::cache_entry::CacheEntry;
+use std::collections::BTreeMap;
+
+/// Decides which entry is evicted when a new one is inserted into a full cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evicts the least recently used entry.  This is the default.
+    #[default]
+    Lru,
+    /// Evicts the most recently used entry.  Suits cyclic scans over more entries than the cache
+    /// holds, where the entry just used is the one needed again the furthest in the future.
+    Mru,
+    /// Segmented LRU: new entries are put on probation and only become protected on their second
+    /// hit.  Probationary entries are evicted first, so a burst of one-off accesses can't flush
+    /// the protected working set.
+    ///
+    /// `protected_percent` is the share of the capacity reserved for protected entries.  When the
+    /// protected segment outgrows it, its least recently used entry is moved back to probation.
+    Segmented {
+        /// Percentage of the capacity reserved for protected entries, capped at 100.
+        protected_percent: u8,
+    },
+    /// Adaptive Replacement Cache: like `Segmented`, entries are split into those hit once and
+    /// those hit repeatedly, but the share of the capacity given to each adapts to the workload.
+    /// The keys of recently evicted entries are remembered, and a miss on such a key grows the
+    /// segment it was evicted from.
+    Adaptive,
+    /// 2Q: new entries join a first-in first-out queue, where hits don't reorder them.  The keys
+    /// evicted from that queue are remembered, and only entries inserted again while remembered
+    /// join the main LRU queue.  Entries seen only once are evicted first once the queue exceeds
+    /// its share of the capacity, so scans can't pollute the main queue.
+    ///
+    /// Up to half the capacity worth of evicted keys are remembered.
+    TwoQueue {
+        /// Percentage of the capacity the first-in first-out queue may take up before its entries
+        /// are evicted in preference to the main queue's, capped at 100.
+        recent_percent: u8,
+    },
+    /// Evicts an entry chosen uniformly at random among the unexpired ones.  Cheap, immune to the
+    /// pathological behaviour of LRU under scans, and a useful baseline to evaluate the other
+    /// policies against.
+    Random,
+    /// Greedy-Dual-Size-Frequency: evicts the entry least worth keeping, weighing how often it
+    /// was hit and how costly retrieving it again is against its size, as given to
+    /// `LruCache::insert_with_cost()`.  So that entries which were popular once don't stay
+    /// forever, the priority of each entry is raised by that of the last evicted one whenever it
+    /// is inserted or hit.  Ties are broken in least recently used order.
+    GreedyDualSizeFrequency,
+    /// CLOCK, an approximation of LRU: hits only mark entries as referenced rather than moving
+    /// them to the top of the cache, which makes reads cheaper.  Eviction sweeps the entries in
+    /// insertion order, giving referenced ones a second chance by clearing their mark and moving
+    /// them to the back.  The order entries are iterated in only approximates recency.
+    Clock,
+}
+
+/// Decides which accesses promote an entry, i.e. count as a use of it for the eviction policy,
+/// which under LRU moves it to the top of the cache.
+///
+/// New entries always join the top of the cache, so with `Never` entries are evicted in insertion
+/// order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Promotion {
+    /// Both reads and writes promote entries.  This is the default.
+    #[default]
+    OnReadAndWrite,
+    /// Only reads, e.g. `get()`, promote entries.
+    OnRead,
+    /// Only writes replacing the value of an entry, e.g. `insert()`, promote it.
+    OnWrite,
+    /// Entries are never promoted.
+    Never,
+}
+
+impl Promotion {
+    pub(crate) fn on_read(self) -> bool {
+        matches!(self, Promotion::OnReadAndWrite | Promotion::OnRead)
+    }
+
+    pub(crate) fn on_write(self) -> bool {
+        matches!(self, Promotion::OnReadAndWrite | Promotion::OnWrite)
+    }
+}
+
+/// Segment of the cache an entry belongs to under the segmented policies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Segment {
+    /// Entries which have been hit only once since insertion.
+    Recent,
+    /// Entries which have been hit repeatedly.
+    Frequent,
+}
+
+/// Number of entries in each segment.
+///
+/// Must be told about every entry leaving the cache, or entering or leaving the frequent segment.
+#[derive(Clone, Default)]
+pub(crate) struct SegmentCounts {
+    pub frequent: usize,
+}
+
+impl SegmentCounts {
+    /// Accounts for `entry` leaving the cache.
+    pub fn remove<Value>(&mut self, entry: &CacheEntry<Value>) {
+        if entry.segment == Segment::Frequent {
+            self.frequent -= 1;
+        }
+    }
+
+    /// Recounts the entries of `map`.
+    pub fn rebuild<Key, Value>(&mut self, map: &BTreeMap<Key, CacheEntry<Value>>) {
+        self.frequent = map
+            .values()
+            .filter(|entry| entry.segment == Segment::Frequent)
+            .count();
+    }
+}
+
+/// Returns `percent` percent of `capacity`, rounded down.
+pub(crate) fn share(capacity: usize, percent: u8) -> usize {
+    let percent = u128::from(percent.min(100));
+    (capacity as u128 * percent / 100) as usize
+}
+
+/// Keys of entries recently evicted from one segment, without their values.
+#[derive(Clone)]
+pub(crate) struct GhostList<Key> {
+    keys: BTreeMap<Key, u64>,
+    /// The keys by insertion sequence number, oldest first.
+    order: BTreeMap<u64, Key>,
+    next: u64,
+}
+
+impl<Key> GhostList<Key>
+where
+    Key: Ord + Clone,
+{
+    fn new() -> Self {
+        Self {
+            keys: BTreeMap::new(),
+            order: BTreeMap::new(),
+            next: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Remembers `key`, forgetting the oldest keys beyond `limit`.
+    pub fn push(&mut self, key: Key, limit: usize) {
+        let _ = self.remove(&key);
+        let _ = self.order.insert(self.next, key.clone());
+        let _ = self.keys.insert(key, self.next);
+        self.next += 1;
+        while self.keys.len() > limit {
+            let oldest = match self.order.keys().next() {
+                Some(sequence) => *sequence,
+                None => break,
+            };
+            if let Some(key) = self.order.remove(&oldest) {
+                let _ = self.keys.remove(&key);
+            }
+        }
+    }
+
+    /// Forgets `key`, returning whether it was remembered.
+    pub fn remove(&mut self, key: &Key) -> bool {
+        match self.keys.remove(key) {
+            Some(sequence) => self.order.remove(&sequence).is_some(),
+            None => false,
+        }
+    }
+}
+
+/// History of evicted keys kept by the adaptive policy.
+#[derive(Clone)]
+pub(crate) struct Ghosts<Key> {
+    pub recent: GhostList<Key>,
+    pub frequent: GhostList<Key>,
+    /// Number of entries the recent segment is aiming for.
+    pub target: usize,
+}
+
+impl<Key> Ghosts<Key>
+where
+    Key: Ord + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            recent: GhostList::new(),
+            frequent: GhostList::new(),
+            target: 0,
+        }
+    }
+
+    /// Adapts the target size of the recent segment if `key` was evicted recently, and returns
+    /// the segment the new entry `key` belongs to.
+    pub fn admit(&mut self, key: &Key, capacity: usize) -> Segment {
+        if self.recent.remove(key) {
+            let step = (self.frequent.len() / (self.recent.len() + 1)).max(1);
+            self.target = self.target.saturating_add(step).min(capacity);
+            Segment::Frequent
+        } else if self.frequent.remove(key) {
+            let step = (self.recent.len() / (self.frequent.len() + 1)).max(1);
+            self.target = self.target.saturating_sub(step);
+            Segment::Frequent
+        } else {
+            Segment::Recent
+        }
+    }
+
+    /// Returns the segment the new entry `key` belongs to under the 2Q policy, which only
+    /// remembers keys evicted from the recent segment.
+    pub fn admit_two_queue(&mut self, key: &Key) -> Segment {
+        if self.recent.remove(key) {
+            Segment::Frequent
+        } else {
+            Segment::Recent
+        }
+    }
+
+    /// Remembers the evicted entry `key` which belonged to `segment`.
+    pub fn push(&mut self, key: Key, segment: Segment, capacity: usize) {
+        match segment {
+            Segment::Recent => self.recent.push(key, capacity),
+            Segment::Frequent => self.frequent.push(key, capacity),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Removal of the entries whose keys share a prefix.
+
+use crate::{Instant, LruCache};
+use std::ops::Bound;
+
+impl<Value> LruCache<String, Value> {
+    /// Removes the entries whose keys start with `prefix`, e.g. everything under `/users/42/`,
+    /// and returns them in ascending key order.  Also removes expired elements.
+    pub fn remove_prefix(&mut self, prefix: &str) -> Vec<(String, Value)> {
+        self.discard_all_expired(Instant::now());
+        let keys: Vec<String> = self
+            .map
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        self.remove_many::<String, _>(&keys)
+    }
+}
+
+impl<Value> LruCache<Vec<u8>, Value> {
+    /// Removes the entries whose keys start with `prefix`, and returns them in ascending key
+    /// order.  Also removes expired elements.
+    pub fn remove_prefix(&mut self, prefix: &[u8]) -> Vec<(Vec<u8>, Value)> {
+        self.discard_all_expired(Instant::now());
+        let keys: Vec<Vec<u8>> = self
+            .map
+            .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        self.remove_many::<Vec<u8>, _>(&keys)
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Recency list of the keys, least recently used first, shared by `LruCache` and `LruHashCache`.
+
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+
+/// Moves `key` to the back of `list`, i.e. makes it the most recently used.
+pub(crate) fn touch<Key, Q>(list: &mut VecDeque<Key>, key: &Q)
+where
+    Key: Borrow<Q>,
+    Q: PartialEq + ?Sized,
+{
+    if let Some(pos) = list.iter().position(|k| k.borrow() == key) {
+        let _ = list.remove(pos).map(|it| list.push_back(it));
+    }
+}
+
+/// Returns how many entries a cache holding `len` of them must evict to make room for a new one
+/// within `capacity`.
+pub(crate) fn overflow(len: usize, capacity: usize) -> usize {
+    if len >= capacity {
+        len + 1 - capacity
+    } else {
+        0
+    }
+}
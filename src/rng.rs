@@ -0,0 +1,61 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Pseudo-random numbers for the randomised policies, which don't need to be unpredictable.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Xorshift generator, seeded from the randomly keyed `RandomState`.
+#[derive(Clone)]
+pub(crate) struct Rng {
+    /// Never zero.
+    state: u64,
+}
+
+impl Rng {
+    pub fn new() -> Self {
+        let seed = RandomState::new().build_hasher().finish();
+        Self { state: seed | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Returns a number uniformly distributed in (0, 1].
+    pub fn next_unit(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns `duration` scaled by a factor uniformly distributed in (1 - fraction, 1 + fraction].
+    pub fn jitter(&mut self, duration: Duration, fraction: f64) -> Duration {
+        let offset = (self.next_unit() * 2.0 - 1.0) * fraction;
+        let scaled = duration.as_secs_f64() * (1.0 + offset);
+        // Saturates rather than overflowing for durations meaning "forever".
+        if scaled >= Duration::MAX.as_secs_f64() {
+            Duration::MAX
+        } else {
+            Duration::from_secs_f64(scaled)
+        }
+    }
+
+    /// Returns a number uniformly distributed in [0, bound), or 0 if `bound` is 0.
+    pub fn below(&mut self, bound: usize) -> usize {
+        if bound == 0 {
+            return 0;
+        }
+        // Multiply-shift rather than modulo, so the bias is negligible for any bound.
+        ((u128::from(self.next_u64()) * bound as u128) >> 64) as usize
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Snapshots of the contents of a cache, independent of the clock they were taken with.
+
+use crate::cache_entry::CacheEntry;
+use crate::{recency, ExpiryPolicy, Instant, LruCache};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Contents and configuration of a cache at some point in time.
+///
+/// All times are stored relative to when the snapshot was taken, so a cache restored from it by
+/// `LruCache::from_snapshot()` carries on as if no time had passed in between, even in another
+/// process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheSnapshot<Key, Value> {
+    /// Maximum number of entries the cache may hold.
+    pub capacity: usize,
+    /// Cache-wide time to live of the entries.
+    pub time_to_live: Option<Duration>,
+    /// Point in time the time to live is measured from.
+    pub expiry_policy: ExpiryPolicy,
+    /// The unexpired entries, least recently used first.
+    pub entries: Vec<SnapshotEntry<Key, Value>>,
+}
+
+/// Entry of a `CacheSnapshot`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotEntry<Key, Value> {
+    /// Key of the entry.
+    pub key: Key,
+    /// Value of the entry.
+    pub value: Value,
+    /// Time since the entry was last accessed.
+    pub idle: Duration,
+    /// Time since the value was inserted or last replaced.
+    pub age: Duration,
+    /// Time to live of the entry overriding the cache-wide one, if any.
+    pub time_to_live: Option<Duration>,
+    /// Whether the entry is pinned.
+    pub pinned: bool,
+    /// Whether the entry was inserted by `insert_permanent()`.
+    pub permanent: bool,
+    /// Time left until the deadline set by `insert_until()`, if any.
+    pub until_deadline: Option<Duration>,
+    /// Time left until the entry expires, or `None` if it doesn't.
+    pub remaining_ttl: Option<Duration>,
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+    Value: Clone,
+{
+    /// Returns a snapshot of the unexpired entries along with their recency order and remaining
+    /// lifetime, and of the capacity and expiry settings.
+    ///
+    /// The eviction and admission policies, and the access history they keep, aren't captured.
+    pub fn snapshot(&self) -> CacheSnapshot<Key, Value> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        let entries = self
+            .list
+            .iter()
+            .filter_map(|key| {
+                let entry = &self.map[key];
+                if entry.is_expired(expiry, now) {
+                    return None;
+                }
+                Some(SnapshotEntry {
+                    key: key.clone(),
+                    value: entry.value.clone(),
+                    idle: now.saturating_duration_since(entry.timestamp),
+                    age: now.saturating_duration_since(entry.written),
+                    time_to_live: entry.time_to_live,
+                    pinned: entry.pinned,
+                    permanent: entry.permanent,
+                    until_deadline: entry
+                        .expires_at
+                        .map(|deadline| deadline.saturating_duration_since(now)),
+                    remaining_ttl: entry
+                        .expiry_time(expiry)
+                        .map(|expiry_time| expiry_time.saturating_duration_since(now)),
+                })
+            })
+            .collect();
+        CacheSnapshot {
+            capacity: self.capacity,
+            time_to_live: self.time_to_live,
+            expiry_policy: self.expiry_policy,
+            entries,
+        }
+    }
+
+    /// Returns the unexpired entries, least recently used first, along with the time left until
+    /// they expire, or `None` for those which don't.  Does not update the timestamps.
+    ///
+    /// Unlike `snapshot()`, only the bare contents are exported, e.g. to hand a warm cache over to
+    /// a peer process through `import()` over any wire format.
+    pub fn export(&self) -> Vec<(Key, Value, Option<Duration>)> {
+        let (now, expiry) = (Instant::now(), self.expiry());
+        self.list
+            .iter()
+            .filter_map(|key| {
+                let entry = &self.map[key];
+                if entry.is_expired(expiry, now) {
+                    return None;
+                }
+                let remaining_ttl = entry
+                    .expiry_time(expiry)
+                    .map(|expiry_time| expiry_time.saturating_duration_since(now));
+                Some((key.clone(), entry.value.clone(), remaining_ttl))
+            })
+            .collect()
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Constructs a cache holding the entries of `snapshot`, with its capacity and expiry
+    /// settings.
+    ///
+    /// The entries keep the recency order and remaining lifetime they had when the snapshot was
+    /// taken; `remaining_ttl` is informative only.  If a key appears more than once, its last
+    /// entry wins, and if there are more entries than the capacity allows for, the least recently
+    /// used ones are dropped.
+    pub fn from_snapshot(snapshot: CacheSnapshot<Key, Value>) -> Self {
+        let now = Instant::now();
+        let mut cache = Self::new(
+            VecDeque::with_capacity(snapshot.entries.len().min(snapshot.capacity)),
+            snapshot.capacity,
+            snapshot.time_to_live,
+        );
+        cache.expiry_policy = snapshot.expiry_policy;
+        for snapshot_entry in snapshot.entries {
+            let mut entry = CacheEntry::new(snapshot_entry.value, now);
+            entry.generation = cache.next_generation();
+            entry.timestamp = now.checked_sub(snapshot_entry.idle).unwrap_or(now);
+            entry.written = now.checked_sub(snapshot_entry.age).unwrap_or(now);
+            entry.inserted = entry.written;
+            entry.time_to_live = snapshot_entry.time_to_live;
+            entry.pinned = snapshot_entry.pinned;
+            entry.permanent = snapshot_entry.permanent;
+            entry.expires_at = snapshot_entry
+                .until_deadline
+                .map(|until_deadline| now.checked_add(until_deadline).unwrap_or(now));
+            if cache
+                .map
+                .insert(snapshot_entry.key.clone(), entry)
+                .is_some()
+            {
+                recency::touch(&mut cache.list, &snapshot_entry.key);
+            } else {
+                cache.list.push_back(snapshot_entry.key);
+            }
+        }
+        let expiry = cache.expiry();
+        cache.expiries.rebuild(&mut cache.map, expiry);
+        cache.discard_all_expired(now);
+        let _ = cache.evict_lru(cache.map.len().saturating_sub(cache.capacity), now);
+        cache
+    }
+
+    /// Inserts entries as exported by `export()`, least recently used first, so that they keep
+    /// their recency order.
+    ///
+    /// Entries with a remaining lifetime expire once it runs out at the latest, as if inserted by
+    /// `insert_until()`, or earlier if this cache's time to live runs out first.  The others didn't
+    /// expire in the exporting cache, so they are inserted by `insert_permanent()` and don't
+    /// expire here either.  Entries may get evicted or turned down by the admission policy as
+    /// usual.
+    pub fn import<I>(&mut self, entries: I)
+    where
+        I: IntoIterator<Item = (Key, Value, Option<Duration>)>,
+    {
+        let now = Instant::now();
+        for (key, value, remaining_ttl) in entries {
+            match remaining_ttl.and_then(|remaining_ttl| now.checked_add(remaining_ttl)) {
+                Some(deadline) => {
+                    let _ = self.insert_until(key, value, deadline);
+                }
+                None => {
+                    let _ = self.insert_permanent(key, value);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Caching under keys ordered by a key extraction function rather than their own `Ord`.
+
+use crate::LruCache;
+
+/// `LruCache` ordering its keys by the sort key extracted from them, e.g. to look up strings
+/// case-insensitively, without wrapping every key in a newtype.
+///
+/// Keys with equal sort keys are considered the same: inserting one replaces the entry stored
+/// under the other, key included.  The underlying cache is keyed by the sort keys and holds the
+/// original keys along with the values.
+pub struct SortKeyCache<Key, Value, Sort, F> {
+    cache: LruCache<Sort, (Key, Value)>,
+    sort_key: F,
+}
+
+impl<Key, Value, Sort, F> SortKeyCache<Key, Value, Sort, F>
+where
+    Sort: Ord + Clone,
+    F: Fn(&Key) -> Sort,
+{
+    /// Constructs a `SortKeyCache` storing its entries in `cache`, under the sort keys `sort_key`
+    /// extracts from the keys.
+    pub fn new(cache: LruCache<Sort, (Key, Value)>, sort_key: F) -> Self {
+        Self { cache, sort_key }
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If a key with the same sort key already existed in the cache, the existing key-value pair
+    /// is returned and overwritten in the cache.  Otherwise, the key-value pair is inserted and
+    /// `None` is returned.
+    pub fn insert(&mut self, key: Key, value: Value) -> Option<(Key, Value)> {
+        let sort_key = (self.sort_key)(&key);
+        self.cache.insert(sort_key, (key, value))
+    }
+
+    /// Removes the key-value pair stored under the sort key of `key`.
+    pub fn remove(&mut self, key: &Key) -> Option<(Key, Value)> {
+        self.cache.remove(&(self.sort_key)(key))
+    }
+
+    /// Retrieves a reference to the value stored under the sort key of `key`, or `None` if there
+    /// is none.  Also removes expired elements and updates the time.
+    pub fn get(&mut self, key: &Key) -> Option<&Value> {
+        self.get_mut(key).map(|value| &*value)
+    }
+
+    /// Retrieves a mutable reference to the value stored under the sort key of `key`, or `None`
+    /// if there is none.  Also removes expired elements and updates the time.
+    pub fn get_mut(&mut self, key: &Key) -> Option<&mut Value> {
+        let sort_key = (self.sort_key)(key);
+        self.cache.get_mut(&sort_key).map(|(_, value)| value)
+    }
+
+    /// Returns a reference to the value stored under the sort key of `key`, if present and not
+    /// expired, without updating the timestamp.
+    pub fn peek(&self, key: &Key) -> Option<&Value> {
+        self.peek_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Much like `peek()`, but also returns the stored key, which may differ from `key`.
+    pub fn peek_key_value(&self, key: &Key) -> Option<(&Key, &Value)> {
+        self.cache
+            .peek(&(self.sort_key)(key))
+            .map(|(key, value)| (key, value))
+    }
+
+    /// Returns whether a key with the same sort key as `key` exists in the cache or not.
+    pub fn contains_key(&self, key: &Key) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// Returns the size of the cache, i.e. the number of cached non-expired key-value pairs.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns `true` if there are no non-expired entries in the cache.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Returns an iterator over all unexpired entries that does not modify the timestamps.
+    /// Values are produced in the most recently used order.
+    pub fn peek_iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        self.cache.peek_iter().map(|(_, (key, value))| (key, value))
+    }
+
+    /// Returns the underlying cache, e.g. to inspect it in sort key order.
+    pub fn cache(&self) -> &LruCache<Sort, (Key, Value)> {
+        &self.cache
+    }
+
+    /// Unwraps the underlying cache and the key extraction function.
+    pub fn into_parts(self) -> (LruCache<Sort, (Key, Value)>, F) {
+        (self.cache, self.sort_key)
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Counters of the cache activity, over its whole lifetime and over a rolling window.
+
+use crate::{Instant, LruCache};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of buckets a time window is split into.  The window slides by a bucket at a time.
+const TIME_BUCKETS: u32 = 64;
+
+/// Recent activity `LruCache::stats_window()` reports on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsWindow {
+    /// The activity over the given time up to now, accurate to a 64th of it.
+    Time(Duration),
+    /// The given number of most recent lookups and insertions, along with the evictions and
+    /// expirations which happened in the meantime.
+    Operations(usize),
+}
+
+/// Counters of the activity of a cache over some period.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of lookups which found an unexpired entry.
+    pub hits: u64,
+    /// Number of lookups which didn't.
+    pub misses: u64,
+    /// Number of insertions, including the ones turned down by the admission policy.
+    pub inserts: u64,
+    /// Number of entries evicted to make room for others, or to fit a reduced capacity.
+    pub evictions: u64,
+    /// Number of expired entries removed.
+    pub expirations: u64,
+    /// Length of the period covered.
+    pub elapsed: Duration,
+}
+
+impl CacheStats {
+    /// Returns the share of the lookups which were hits, or `None` if there were no lookups.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return None;
+        }
+        Some(self.hits as f64 / lookups as f64)
+    }
+
+    /// Returns the number of evictions per second, or `None` if no time was covered.
+    pub fn eviction_rate(&self) -> Option<f64> {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds <= 0.0 {
+            return None;
+        }
+        Some(self.evictions as f64 / seconds)
+    }
+
+    fn add(&mut self, other: &CacheStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.inserts += other.inserts;
+        self.evictions += other.evictions;
+        self.expirations += other.expirations;
+    }
+}
+
+/// Lifetime counters of a cache, plus a ring buffer of timestamped counters for its window.
+#[derive(Clone)]
+pub(crate) struct StatsRecorder {
+    started: Instant,
+    totals: CacheStats,
+    window: Option<StatsWindow>,
+    window_started: Instant,
+    /// Counters of each bucket of the window along with its start, oldest first.  A bucket
+    /// covers a slice of time for `StatsWindow::Time`, and a single operation for
+    /// `StatsWindow::Operations`.
+    buckets: VecDeque<(Instant, CacheStats)>,
+}
+
+impl StatsRecorder {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            totals: CacheStats::default(),
+            window: None,
+            window_started: now,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    pub fn set_window(&mut self, window: Option<StatsWindow>) {
+        self.window = window;
+        self.window_started = Instant::now();
+        self.buckets.clear();
+    }
+
+    pub fn lookup(&mut self, hit: bool, now: Instant) {
+        self.record(now, true, |stats| {
+            if hit {
+                stats.hits += 1;
+            } else {
+                stats.misses += 1;
+            }
+        });
+    }
+
+    pub fn insertion(&mut self, now: Instant) {
+        self.record(now, true, |stats| stats.inserts += 1);
+    }
+
+    pub fn eviction(&mut self, count: usize, now: Instant) {
+        if count > 0 {
+            self.record(now, false, |stats| stats.evictions += count as u64);
+        }
+    }
+
+    pub fn expiration(&mut self, count: usize, now: Instant) {
+        if count > 0 {
+            self.record(now, false, |stats| stats.expirations += count as u64);
+        }
+    }
+
+    /// Applies `count` to the totals and to the current bucket, starting a new bucket if
+    /// `operation` is set or the current one is over.
+    fn record<F: Fn(&mut CacheStats)>(&mut self, now: Instant, operation: bool, count: F) {
+        count(&mut self.totals);
+        let new_bucket = match self.window {
+            None => return,
+            Some(StatsWindow::Time(window)) => {
+                let width = window / TIME_BUCKETS;
+                self.buckets.back().map_or(true, |(start, _)| {
+                    now.saturating_duration_since(*start) >= width
+                })
+            }
+            Some(StatsWindow::Operations(_)) => operation || self.buckets.is_empty(),
+        };
+        if new_bucket {
+            self.buckets.push_back((now, CacheStats::default()));
+            self.prune(now);
+        }
+        if let Some((_, stats)) = self.buckets.back_mut() {
+            count(stats);
+        }
+    }
+
+    /// Drops the buckets which fell out of the window.
+    fn prune(&mut self, now: Instant) {
+        match self.window {
+            None => self.buckets.clear(),
+            Some(StatsWindow::Time(window)) => {
+                while self
+                    .buckets
+                    .front()
+                    .is_some_and(|(start, _)| now.saturating_duration_since(*start) > window)
+                {
+                    let _ = self.buckets.pop_front();
+                }
+            }
+            Some(StatsWindow::Operations(count)) => {
+                while self.buckets.len() > count {
+                    let _ = self.buckets.pop_front();
+                }
+            }
+        }
+    }
+
+    fn totals(&self, now: Instant) -> CacheStats {
+        CacheStats {
+            elapsed: now.saturating_duration_since(self.started),
+            ..self.totals
+        }
+    }
+
+    fn windowed(&self, now: Instant) -> Option<CacheStats> {
+        let window = self.window?;
+        let mut windowed = CacheStats::default();
+        let mut oldest = None;
+        for (start, stats) in &self.buckets {
+            if let StatsWindow::Time(window) = window {
+                if now.saturating_duration_since(*start) > window {
+                    continue;
+                }
+            }
+            oldest = oldest.or(Some(*start));
+            windowed.add(stats);
+        }
+        windowed.elapsed = match window {
+            StatsWindow::Time(window) => {
+                window.min(now.saturating_duration_since(self.window_started))
+            }
+            StatsWindow::Operations(_) => oldest.map_or(Duration::default(), |oldest| {
+                now.saturating_duration_since(oldest)
+            }),
+        };
+        Some(windowed)
+    }
+}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Returns the counters of the cache activity since it was constructed.
+    pub fn stats(&self) -> CacheStats {
+        self.stats.totals(Instant::now())
+    }
+
+    /// Returns the counters of the recent cache activity, or `None` if no window is set.  See
+    /// `set_stats_window()`.
+    pub fn stats_window(&self) -> Option<CacheStats> {
+        self.stats.windowed(Instant::now())
+    }
+
+    /// Starts keeping the counters of the activity within `window`, or stops if `None`.
+    ///
+    /// The counters are kept in a ring buffer, so the window slides along with the activity
+    /// without the whole of it being remembered.  The activity before the call isn't accounted
+    /// for.  Lookups which don't update the cache, such as `peek()`, aren't counted.
+    pub fn set_stats_window(&mut self, window: Option<StatsWindow>) {
+        self.stats.set_window(window);
+    }
+}
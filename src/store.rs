@@ -0,0 +1,154 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Write-through and write-back caching in front of a backing store.
+
+use crate::LruCache;
+use std::collections::BTreeSet;
+
+/// Secondary store written to by a `BackedCache`, e.g. a database or a disk store.
+pub trait BackingStore<Key, Value> {
+    /// Stores `value` under `key`, replacing the previous value if any.
+    fn write(&mut self, key: &Key, value: &Value);
+
+    /// Removes the value stored under `key`, if any.
+    fn delete(&mut self, key: &Key);
+}
+
+/// Decides when a `BackedCache` writes the inserted values to its backing store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Values are written as soon as they are inserted.
+    WriteThrough,
+    /// Values are written once they leave the cache, by being evicted or expiring, or when the
+    /// cache is flushed.  Replacing a value which hasn't been written yet saves writing it at all.
+    WriteBack,
+}
+
+/// `LruCache` writing the inserted values to a backing store.
+///
+/// Removals are always passed on to the store immediately.  In `WriteMode::WriteBack`, the values
+/// not written yet are lost unless `flush()` or `into_parts()` is called before the cache is
+/// dropped.
+pub struct BackedCache<Key, Value, Store> {
+    cache: LruCache<Key, Value>,
+    store: Store,
+    mode: WriteMode,
+    /// Keys of the entries not written to the store yet.
+    dirty: BTreeSet<Key>,
+}
+
+impl<Key, Value, Store> BackedCache<Key, Value, Store>
+where
+    Key: Ord + Clone,
+    Store: BackingStore<Key, Value>,
+{
+    /// Constructs a `BackedCache` writing the values inserted into `cache` to `store`.
+    pub fn new(cache: LruCache<Key, Value>, store: Store, mode: WriteMode) -> Self {
+        Self {
+            cache,
+            store,
+            mode,
+            dirty: BTreeSet::new(),
+        }
+    }
+
+    /// Returns when the values are written to the store.
+    pub fn write_mode(&self) -> WriteMode {
+        self.mode
+    }
+
+    /// Inserts a key-value pair into the cache, and writes it to the store as the write mode
+    /// requires.  Also writes back the dirty entries which expired or were evicted.
+    ///
+    /// If the key already existed in the cache, the existing value is returned and overwritten in
+    /// the cache.  Otherwise, the key-value pair is inserted and `None` is returned.
+    pub fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
+        if self.mode == WriteMode::WriteThrough {
+            self.store.write(&key, &value);
+        }
+        let outcome = self.cache.notify_insert_full(key.clone(), value);
+        self.write_back(outcome.expired);
+        self.write_back(outcome.evicted);
+        match outcome.rejected {
+            Some(value) => {
+                // Never makes it into the cache, so can't be written back later.
+                if self.mode == WriteMode::WriteBack {
+                    self.store.write(&key, &value);
+                }
+            }
+            None => {
+                if self.mode == WriteMode::WriteBack {
+                    let _ = self.dirty.insert(key);
+                }
+            }
+        }
+        outcome.replaced
+    }
+
+    /// Removes a key-value pair from both the cache and the store.
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        let _ = self.dirty.remove(key);
+        self.store.delete(key);
+        self.cache.remove(key)
+    }
+
+    /// Retrieves a reference to the value stored under `key`, or `None` if it isn't cached.  Also
+    /// writes back the dirty entries which expired.
+    pub fn get(&mut self, key: &Key) -> Option<&Value> {
+        let (value, expired) = self.cache.notify_get(key);
+        write_back(&mut self.store, &mut self.dirty, expired);
+        value
+    }
+
+    /// Writes all the dirty entries to the store.
+    pub fn flush(&mut self) {
+        for key in std::mem::take(&mut self.dirty) {
+            if let Some(entry) = self.cache.map.get(&key) {
+                self.store.write(&key, &entry.value);
+            }
+        }
+    }
+
+    /// Returns the underlying cache.
+    pub fn cache(&self) -> &LruCache<Key, Value> {
+        &self.cache
+    }
+
+    /// Returns the backing store.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Flushes the dirty entries, then unwraps the underlying cache and the store.
+    pub fn into_parts(mut self) -> (LruCache<Key, Value>, Store) {
+        self.flush();
+        (self.cache, self.store)
+    }
+
+    fn write_back(&mut self, entries: Vec<(Key, Value)>) {
+        write_back(&mut self.store, &mut self.dirty, entries);
+    }
+}
+
+/// Writes the dirty ones of `entries`, which left the cache, to `store`.
+fn write_back<Key, Value, Store>(
+    store: &mut Store,
+    dirty: &mut BTreeSet<Key>,
+    entries: Vec<(Key, Value)>,
+) where
+    Key: Ord,
+    Store: BackingStore<Key, Value>,
+{
+    for (key, value) in entries {
+        if dirty.remove(&key) {
+            store.write(&key, &value);
+        }
+    }
+}
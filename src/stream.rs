@@ -0,0 +1,39 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Asynchronous notification about expired cache entries.
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::stream::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A stream of entries evicted from an `LruCache` because their time to live has passed.
+///
+/// Created by `LruCache::expired_stream()`.  Entries are only fed into the stream when the cache
+/// removes them, either on its regular activity or from the task spawned by
+/// `LruCache::spawn_expiry_task()`.
+pub struct ExpiredStream<Key, Value> {
+    receiver: UnboundedReceiver<(Key, Value)>,
+}
+
+impl<Key, Value> ExpiredStream<Key, Value> {
+    pub(crate) fn channel() -> (UnboundedSender<(Key, Value)>, Self) {
+        let (sender, receiver) = mpsc::unbounded();
+        (sender, Self { receiver })
+    }
+}
+
+impl<Key, Value> Stream for ExpiredStream<Key, Value> {
+    type Item = (Key, Value);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
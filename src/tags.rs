@@ -0,0 +1,152 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Invalidation of groups of entries sharing a tag.
+
+use crate::LruCache;
+use std::borrow::Borrow;
+
+/// `LruCache` whose entries carry tags given at insert time, so that all the entries sharing a
+/// tag can be removed in one call, e.g. the values derived from an upstream object which has
+/// changed.
+///
+/// The underlying cache holds the tags of each entry along with its value.
+pub struct TaggedLruCache<Key, Value, Tag> {
+    cache: LruCache<Key, (Value, Vec<Tag>)>,
+}
+
+impl<Key, Value, Tag> TaggedLruCache<Key, Value, Tag>
+where
+    Key: Ord + Clone,
+    Tag: Ord,
+{
+    /// Constructs a `TaggedLruCache` storing its entries in `cache`.
+    pub fn new(cache: LruCache<Key, (Value, Vec<Tag>)>) -> Self {
+        Self { cache }
+    }
+
+    /// Inserts a key-value pair into the cache, tagged with `tags`.
+    ///
+    /// If the key already existed in the cache, the existing value is returned and overwritten
+    /// in the cache, tags included.  Otherwise, the key-value pair is inserted and `None` is
+    /// returned.
+    pub fn insert<I>(&mut self, key: Key, value: Value, tags: I) -> Option<Value>
+    where
+        I: IntoIterator<Item = Tag>,
+    {
+        let mut tags: Vec<_> = tags.into_iter().collect();
+        tags.sort();
+        tags.dedup();
+        self.cache
+            .insert(key, (value, tags))
+            .map(|(value, _)| value)
+    }
+
+    /// Removes a key-value pair from the cache.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache.remove(key).map(|(value, _)| value)
+    }
+
+    /// Removes all the entries tagged with `tag`, and returns them least recently used first.
+    /// Expired entries are removed beforehand, and aren't returned.
+    ///
+    /// Goes over all the entries of the cache.
+    pub fn invalidate_tag<Q>(&mut self, tag: &Q) -> Vec<(Key, Value)>
+    where
+        Tag: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache
+            .extract_if(|_, (_, tags)| {
+                tags.binary_search_by(|probe| probe.borrow().cmp(tag))
+                    .is_ok()
+            })
+            .map(|(key, (value, _))| (key, value))
+            .collect()
+    }
+
+    /// Retrieves a reference to the value stored under `key`, or `None` if the key doesn't
+    /// exist.  Also removes expired elements and updates the time.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_mut(key).map(|value| &*value)
+    }
+
+    /// Retrieves a mutable reference to the value stored under `key`, or `None` if the key
+    /// doesn't exist.  Also removes expired elements and updates the time.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache.get_mut(key).map(|(value, _)| value)
+    }
+
+    /// Returns a reference to the value stored under `key`, if present and not expired, without
+    /// updating the timestamp.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&Value>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache.peek(key).map(|(value, _)| value)
+    }
+
+    /// Returns the tags of the entry stored under `key`, in ascending order, if present and not
+    /// expired.
+    pub fn tags<Q>(&self, key: &Q) -> Option<&[Tag]>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache.peek(key).map(|(_, tags)| &tags[..])
+    }
+
+    /// Returns whether `key` exists in the cache or not.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache.contains_key(key)
+    }
+
+    /// Returns the size of the cache, i.e. the number of cached non-expired key-value pairs.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns `true` if there are no non-expired entries in the cache.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Returns the underlying cache, e.g. to inspect it.
+    pub fn cache(&self) -> &LruCache<Key, (Value, Vec<Tag>)> {
+        &self.cache
+    }
+
+    /// Returns the underlying cache, e.g. for operations not offered here.  Tags set through it
+    /// must be kept in ascending order, without duplicates.
+    pub fn cache_mut(&mut self) -> &mut LruCache<Key, (Value, Vec<Tag>)> {
+        &mut self.cache
+    }
+
+    /// Unwraps the underlying cache.
+    pub fn into_inner(self) -> LruCache<Key, (Value, Vec<Tag>)> {
+        self.cache
+    }
+}
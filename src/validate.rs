@@ -0,0 +1,165 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Checking of the internal invariants of a cache, e.g. from fuzzers and property tests.
+
+use crate::policy::Segment;
+use crate::{EvictionPolicy, LruCache, Promotion};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// Inconsistency in the internal state of a cache, found by `LruCache::validate()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError<Key> {
+    /// The key has an entry but is missing from the recency list.
+    MissingFromList(Key),
+    /// The key is in the recency list but has no entry.
+    MissingFromMap(Key),
+    /// The key appears more than once in the recency list.
+    DuplicateInList(Key),
+    /// The first key comes before the second one in the recency list, i.e. is less recently
+    /// used, although it was accessed later.
+    TimestampsOutOfOrder(Key, Key),
+    /// The key isn't filed under the time its entry expires at in the expiry index.
+    ExpiryIndexMismatch(Key),
+    /// The expiry index holds a different number of keys than the number of entries which
+    /// expire.
+    ExpiryIndexLength {
+        /// Number of keys in the expiry index.
+        indexed: usize,
+        /// Number of entries which expire.
+        expiring: usize,
+    },
+    /// The recorded number of entries in the frequent segment is wrong.
+    SegmentCount {
+        /// Recorded number of entries.
+        recorded: usize,
+        /// Actual number of entries.
+        counted: usize,
+    },
+}
+
+impl<Key: Debug> Display for ValidationError<Key> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingFromList(key) => {
+                write!(formatter, "key {:?} is missing from the recency list", key)
+            }
+            Self::MissingFromMap(key) => {
+                write!(formatter, "key {:?} in the recency list has no entry", key)
+            }
+            Self::DuplicateInList(key) => {
+                write!(formatter, "key {:?} is duplicated in the recency list", key)
+            }
+            Self::TimestampsOutOfOrder(earlier, later) => write!(
+                formatter,
+                "key {:?} is less recently used than key {:?} but was accessed later",
+                earlier, later,
+            ),
+            Self::ExpiryIndexMismatch(key) => write!(
+                formatter,
+                "key {:?} isn't filed under its expiry time in the expiry index",
+                key,
+            ),
+            Self::ExpiryIndexLength { indexed, expiring } => write!(
+                formatter,
+                "expiry index holds {} keys but {} entries expire",
+                indexed, expiring,
+            ),
+            Self::SegmentCount { recorded, counted } => write!(
+                formatter,
+                "{} entries recorded in the frequent segment but {} found",
+                recorded, counted,
+            ),
+        }
+    }
+}
+
+impl<Key: Debug> Error for ValidationError<Key> {}
+
+impl<Key, Value> LruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Checks that the internal bookkeeping of the cache is consistent, returning the first
+    /// inconsistency found if any.  Meant to catch state corruption early, e.g. from fuzzers and
+    /// property tests, and takes time proportional to the number of entries.
+    ///
+    /// The recency list must hold exactly the keys of the entries, and the expiry index the ones
+    /// of the entries which expire, under their expiry time.  Under LRU eviction where both reads
+    /// and writes promote entries, the recency list must also be ordered by access time, once the
+    /// buffered promotions if any are applied.
+    /// `get_and_promote()`, `get_and_refresh()`, repositioning entries through a cursor and
+    /// inserting them at a past time with `insert_at()` break that ordering on purpose, so don't
+    /// validate caches used that way.
+    ///
+    /// Only available in debug builds, or with the `validate` feature.
+    pub fn validate(&self) -> Result<(), ValidationError<Key>> {
+        let mut listed = BTreeSet::new();
+        for key in &self.list {
+            if !self.map.contains_key(key) {
+                return Err(ValidationError::MissingFromMap(key.clone()));
+            }
+            if !listed.insert(key) {
+                return Err(ValidationError::DuplicateInList(key.clone()));
+            }
+        }
+        if let Some(key) = self.map.keys().find(|key| !listed.contains(key)) {
+            return Err(ValidationError::MissingFromList(key.clone()));
+        }
+
+        if self.eviction_policy == EvictionPolicy::Lru
+            && self.promotion == Promotion::OnReadAndWrite
+            && self.pending_promotions.is_empty()
+        {
+            let mut keys = self.list.iter().zip(self.list.iter().skip(1));
+            if let Some((earlier, later)) =
+                keys.find(|(a, b)| self.map[*a].timestamp > self.map[*b].timestamp)
+            {
+                return Err(ValidationError::TimestampsOutOfOrder(
+                    earlier.clone(),
+                    later.clone(),
+                ));
+            }
+        }
+
+        let expiry = self.expiry();
+        let indexed: BTreeSet<_> = self.expiries.iter().collect();
+        let mut expiring = 0;
+        for (key, entry) in &self.map {
+            let deadline = entry.expiry_time(expiry);
+            let filed =
+                deadline.map_or(true, |deadline| indexed.contains(&&(deadline, key.clone())));
+            if entry.deadline != deadline || !filed {
+                return Err(ValidationError::ExpiryIndexMismatch(key.clone()));
+            }
+            expiring += usize::from(deadline.is_some());
+        }
+        if indexed.len() != expiring {
+            return Err(ValidationError::ExpiryIndexLength {
+                indexed: indexed.len(),
+                expiring,
+            });
+        }
+
+        let counted = self
+            .map
+            .values()
+            .filter(|entry| entry.segment == Segment::Frequent)
+            .count();
+        if counted != self.segments.frequent {
+            return Err(ValidationError::SegmentCount {
+                recorded: self.segments.frequent,
+                counted,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Caching of shared values without keeping them alive.
+
+use crate::LruCache;
+use std::borrow::Borrow;
+use std::sync::{Arc, Weak};
+
+/// `LruCache` holding weak references to values shared through `Arc`s, so that the cache never
+/// keeps a value alive on its own.
+///
+/// Entries whose value has been dropped everywhere else are treated as absent, and are removed
+/// lazily, when looked up by `get()` or by `remove_dropped()`.  Until then they still count
+/// towards the capacity of the cache and its length.
+pub struct WeakLruCache<Key, Value> {
+    cache: LruCache<Key, Weak<Value>>,
+}
+
+impl<Key, Value> WeakLruCache<Key, Value>
+where
+    Key: Ord + Clone,
+{
+    /// Constructs a `WeakLruCache` storing its entries in `cache`.
+    pub fn new(cache: LruCache<Key, Weak<Value>>) -> Self {
+        Self { cache }
+    }
+
+    /// Inserts a weak reference to `value` into the cache.
+    ///
+    /// If the key already existed in the cache, the existing value is returned if it is still
+    /// alive, and the entry is overwritten.
+    pub fn insert(&mut self, key: Key, value: &Arc<Value>) -> Option<Arc<Value>> {
+        self.cache
+            .insert(key, Arc::downgrade(value))
+            .and_then(|old| old.upgrade())
+    }
+
+    /// Removes the entry stored under `key`, returning its value if it is still alive.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Arc<Value>>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache.remove(key).and_then(|value| value.upgrade())
+    }
+
+    /// Retrieves the value stored under `key`, or `None` if the key doesn't exist or its value
+    /// has been dropped, in which case the entry is removed.  Also removes expired elements and
+    /// updates the time.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<Arc<Value>>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let value = self.cache.get(key)?.upgrade();
+        if value.is_none() {
+            let _ = self.cache.remove(key);
+        }
+        value
+    }
+
+    /// Returns the value stored under `key`, if present, not expired and still alive, without
+    /// updating the timestamp.
+    pub fn peek<Q>(&self, key: &Q) -> Option<Arc<Value>>
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache.peek(key).and_then(Weak::upgrade)
+    }
+
+    /// Returns whether `key` exists in the cache with a value which is still alive.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.cache
+            .peek(key)
+            .is_some_and(|value| value.strong_count() > 0)
+    }
+
+    /// Removes the entries whose value has been dropped, and returns their keys.
+    pub fn remove_dropped(&mut self) -> Vec<Key> {
+        self.cache
+            .extract_if(|_, value| value.strong_count() == 0)
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Returns the number of unexpired entries in the cache, including the ones whose value has
+    /// been dropped but which haven't been removed yet.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns `true` if there are no unexpired entries in the cache.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Returns the underlying cache, e.g. to inspect it.
+    pub fn cache(&self) -> &LruCache<Key, Weak<Value>> {
+        &self.cache
+    }
+
+    /// Returns the underlying cache, e.g. for operations not offered here.
+    pub fn cache_mut(&mut self) -> &mut LruCache<Key, Weak<Value>> {
+        &mut self.cache
+    }
+
+    /// Unwraps the underlying cache.
+    pub fn into_inner(self) -> LruCache<Key, Weak<Value>> {
+        self.cache
+    }
+}